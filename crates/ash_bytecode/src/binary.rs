@@ -0,0 +1,594 @@
+use std::rc::Rc;
+
+use thiserror::Error;
+
+use crate::prelude::{Chunk, Function, NumTy, OpCode, Symbol, Value};
+
+/// File layout:
+/// magic | version: u16 | payload length: u32 | payload checksum: u32 | payload.
+/// Payload is the top level chunk: constants | code | span table | symbol table.
+/// Symbol is name | global | type | mutable: u8.
+/// Every number is little-endian
+pub const MAGIC: [u8; 4] = *b"ASHC";
+/// Bumped on every change of the layout, opcodes or constant tags,
+/// so loaders reject files they can not run.
+/// 2: string conversion, cast and bitwise opcodes, sized number constants
+/// 3: types and mutability of symbols
+/// 4: loading the function of the call frame
+pub const VERSION: u16 = 4;
+pub const EXTENSION: &str = "ashc";
+
+const HEADER_LEN: usize = 14;
+// Functions nested deeper are rejected before they overflow the stack of the loader
+const MAX_NESTING: usize = 64;
+
+const TAG_I32: u8 = 0;
+const TAG_F64: u8 = 1;
+const TAG_BOOL: u8 = 2;
+const TAG_STRING: u8 = 3;
+const TAG_FUNCTION: u8 = 4;
+const TAG_VOID: u8 = 5;
+const TAG_I8: u8 = 6;
+const TAG_I16: u8 = 7;
+const TAG_I64: u8 = 8;
+const TAG_U8: u8 = 9;
+const TAG_U16: u8 = 10;
+const TAG_U32: u8 = 11;
+const TAG_U64: u8 = 12;
+const TAG_F32: u8 = 13;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum BinaryError {
+    #[error("Not an Ash bytecode file")]
+    BadMagic,
+    #[error("Unsupported bytecode version {0}, expected {VERSION}")]
+    UnsupportedVersion(u16),
+    #[error("Checksum mismatch, file is corrupted")]
+    ChecksumMismatch,
+    #[error("Unexpected end of file")]
+    UnexpectedEnd,
+    #[error("Unknown constant tag {0}")]
+    BadConstantTag(u8),
+    #[error("Invalid UTF-8 in string constant")]
+    InvalidUtf8,
+    #[error("Functions are nested deeper than {MAX_NESTING} levels")]
+    NestingTooDeep,
+    #[error("{0} can not be written to a bytecode file")]
+    Unserializable(&'static str),
+    #[error("Invalid bytecode in `{function}` at {offset:0>5}: {reason}")]
+    Invalid {
+        function: String,
+        offset: usize,
+        reason: String,
+    },
+}
+
+pub type BinaryResult<T = ()> = Result<T, BinaryError>;
+
+impl Chunk {
+    pub fn to_bytes(&self) -> BinaryResult<Vec<u8>> {
+        let mut payload = Writer::default();
+        payload.chunk(self)?;
+        let payload = payload.bytes;
+
+        let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&crc32(&payload).to_le_bytes());
+        bytes.extend_from_slice(&payload);
+        Ok(bytes)
+    }
+
+    /// Loads and validates chunk written by `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> BinaryResult<Self> {
+        let mut header = Reader::new(bytes);
+        if header.take(MAGIC.len())? != MAGIC {
+            return Err(BinaryError::BadMagic);
+        }
+        let version = header.u16()?;
+        if version != VERSION {
+            return Err(BinaryError::UnsupportedVersion(version));
+        }
+        let len = header.u32()? as usize;
+        let checksum = header.u32()?;
+        let payload = header.take(len)?;
+        if crc32(payload) != checksum {
+            return Err(BinaryError::ChecksumMismatch);
+        }
+
+        let chunk = Reader::new(payload).chunk()?;
+        chunk.validate("script")?;
+        Ok(chunk)
+    }
+
+    /// Checks that every instruction is well formed
+    /// so the VM never reads outside of code or constants
+    pub fn validate(&self, name: &str) -> BinaryResult {
+        let invalid = |offset: usize, reason: String| BinaryError::Invalid {
+            function: name.to_owned(),
+            offset,
+            reason,
+        };
+
+        let mut offset = 0;
+        while offset < self.code.len() {
+            let op = OpCode::try_from(self.code[offset])
+                .map_err(|byte| invalid(offset, format!("unknown opcode {byte}")))?;
+            let next = offset + 1 + op.operands_len();
+            let operands = self
+                .code
+                .get(offset + 1..next)
+                .ok_or_else(|| invalid(offset, format!("{op} is missing operands")))?;
+            let arg = operands
+                .iter()
+                .rev()
+                .fold(0, |arg, byte| (arg << 8) | *byte as usize);
+
+            match op {
+                OpCode::Const | OpCode::ConstLong if arg >= self.constants.len() => {
+                    return Err(invalid(offset, format!("constant {arg} does not exist")));
+                }
+                OpCode::DefGlobal
+                | OpCode::DefGlobalLong
+                | OpCode::LoadGlobal
+                | OpCode::LoadGlobalLong
+                | OpCode::StoreGlobal
+                | OpCode::StoreGlobalLong
+                | OpCode::LoadNative
+                | OpCode::LoadNativeLong
+                    if !matches!(self.constants.get(arg), Some(Value::String(_))) =>
+                {
+                    return Err(invalid(offset, format!("constant {arg} is not a name")));
+                }
+                OpCode::Jmp | OpCode::JmpIfFalse if next + arg > self.code.len() => {
+                    return Err(invalid(offset, "jump past the end of code".to_owned()));
+                }
+                OpCode::Cast if NumTy::try_from(arg as u8).is_err() => {
+                    return Err(invalid(offset, format!("{arg} is not a number type")));
+                }
+                OpCode::Loop if arg > next => {
+                    return Err(invalid(offset, "loop before the start of code".to_owned()));
+                }
+                _ => {}
+            }
+
+            offset = next;
+        }
+
+        let spans_sorted = self.spans.windows(2).all(|w| w[0].0 < w[1].0);
+        let spans_in_code = self.spans.iter().all(|(start, _)| *start <= self.code.len());
+        if !spans_sorted || !spans_in_code {
+            return Err(invalid(0, "span table is out of order".to_owned()));
+        }
+
+        for constant in self.constants.iter() {
+            if let Value::Function(fun) = constant {
+                fun.chunk.validate(&fun.name)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn chunk(&mut self, chunk: &Chunk) -> BinaryResult {
+        self.u32(chunk.constants.len());
+        for constant in chunk.constants.iter() {
+            self.constant(constant)?;
+        }
+
+        self.u32(chunk.code.len());
+        self.bytes.extend_from_slice(&chunk.code);
+
+        self.u32(chunk.spans.len());
+        for (offset, span) in chunk.spans.iter() {
+            self.u32(*offset);
+            self.u32(span.start);
+            self.u32(span.end);
+        }
+
+        // Sorted so the same chunk always gives the same bytes
+        let mut symbols = chunk.symbols().collect::<Vec<_>>();
+        symbols.sort_unstable();
+        self.u32(symbols.len());
+        for (name, symbol) in symbols {
+            self.str(name);
+            self.str(&symbol.global);
+            self.str(&symbol.ty);
+            self.bytes.push(symbol.mutable as u8);
+        }
+
+        Ok(())
+    }
+
+    fn constant(&mut self, constant: &Value) -> BinaryResult {
+        match constant {
+            Value::I8(v) => self.number(TAG_I8, &v.to_le_bytes()),
+            Value::I16(v) => self.number(TAG_I16, &v.to_le_bytes()),
+            Value::I32(v) => self.number(TAG_I32, &v.to_le_bytes()),
+            Value::I64(v) => self.number(TAG_I64, &v.to_le_bytes()),
+            Value::U8(v) => self.number(TAG_U8, &v.to_le_bytes()),
+            Value::U16(v) => self.number(TAG_U16, &v.to_le_bytes()),
+            Value::U32(v) => self.number(TAG_U32, &v.to_le_bytes()),
+            Value::U64(v) => self.number(TAG_U64, &v.to_le_bytes()),
+            Value::F32(v) => self.number(TAG_F32, &v.to_le_bytes()),
+            Value::F64(v) => self.number(TAG_F64, &v.to_le_bytes()),
+            Value::Bool(v) => {
+                self.bytes.push(TAG_BOOL);
+                self.bytes.push(*v as u8);
+            }
+            Value::String(v) => {
+                self.bytes.push(TAG_STRING);
+                self.str(v);
+            }
+            Value::Function(fun) => {
+                self.bytes.push(TAG_FUNCTION);
+                self.str(&fun.name);
+                self.bytes.push(fun.arity);
+                self.chunk(&fun.chunk)?;
+            }
+            Value::Void => self.bytes.push(TAG_VOID),
+            Value::Object(_) => return Err(BinaryError::Unserializable("Heap object")),
+        }
+
+        Ok(())
+    }
+
+    fn number(&mut self, tag: u8, bytes: &[u8]) {
+        self.bytes.push(tag);
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    fn str(&mut self, s: &str) {
+        self.u32(s.len());
+        self.bytes.extend_from_slice(s.as_bytes());
+    }
+
+    fn u32(&mut self, v: usize) {
+        self.bytes.extend_from_slice(&(v as u32).to_le_bytes());
+    }
+}
+
+struct Reader<'b> {
+    bytes: &'b [u8],
+    pos: usize,
+    // Functions being read
+    depth: usize,
+}
+
+impl<'b> Reader<'b> {
+    fn new(bytes: &'b [u8]) -> Self {
+        Self {
+            bytes,
+            pos: 0,
+            depth: 0,
+        }
+    }
+
+    fn chunk(&mut self) -> BinaryResult<Chunk> {
+        let mut chunk = Chunk::default();
+
+        let constants = self.u32()?;
+        for _ in 0..constants {
+            let constant = self.constant()?;
+            chunk.add_const(constant);
+        }
+
+        let code_len = self.u32()? as usize;
+        chunk.code = self.take(code_len)?.to_vec();
+
+        let spans = self.u32()?;
+        for _ in 0..spans {
+            let offset = self.u32()? as usize;
+            let start = self.u32()? as usize;
+            let end = self.u32()? as usize;
+            chunk.spans.push((offset, start..end));
+        }
+
+        let symbols = self.u32()?;
+        for _ in 0..symbols {
+            let name = self.str()?;
+            let symbol = Symbol {
+                global: self.str()?,
+                ty: self.str()?,
+                mutable: self.u8()? != 0,
+            };
+            chunk.add_symbol(name, symbol);
+        }
+
+        Ok(chunk)
+    }
+
+    fn constant(&mut self) -> BinaryResult<Value> {
+        let value = match self.u8()? {
+            TAG_I8 => Value::I8(i8::from_le_bytes(self.array()?)),
+            TAG_I16 => Value::I16(i16::from_le_bytes(self.array()?)),
+            TAG_I32 => Value::I32(i32::from_le_bytes(self.array()?)),
+            TAG_I64 => Value::I64(i64::from_le_bytes(self.array()?)),
+            TAG_U8 => Value::U8(u8::from_le_bytes(self.array()?)),
+            TAG_U16 => Value::U16(u16::from_le_bytes(self.array()?)),
+            TAG_U32 => Value::U32(u32::from_le_bytes(self.array()?)),
+            TAG_U64 => Value::U64(u64::from_le_bytes(self.array()?)),
+            TAG_F32 => Value::F32(f32::from_le_bytes(self.array()?)),
+            TAG_F64 => Value::F64(f64::from_le_bytes(self.array()?)),
+            TAG_BOOL => Value::Bool(self.u8()? != 0),
+            TAG_STRING => Value::String(self.str()?),
+            TAG_FUNCTION => {
+                if self.depth == MAX_NESTING {
+                    return Err(BinaryError::NestingTooDeep);
+                }
+                let name = self.str()?;
+                let arity = self.u8()?;
+                self.depth += 1;
+                let chunk = self.chunk();
+                self.depth -= 1;
+                let chunk = chunk?;
+                Value::Function(Rc::new(Function { name, arity, chunk }))
+            }
+            TAG_VOID => Value::Void,
+            tag => return Err(BinaryError::BadConstantTag(tag)),
+        };
+
+        Ok(value)
+    }
+
+    fn str(&mut self) -> BinaryResult<String> {
+        let len = self.u32()? as usize;
+        let bytes = self.take(len)?.to_vec();
+        String::from_utf8(bytes).map_err(|_| BinaryError::InvalidUtf8)
+    }
+
+    fn u8(&mut self) -> BinaryResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> BinaryResult<u16> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> BinaryResult<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn array<const N: usize>(&mut self) -> BinaryResult<[u8; N]> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn take(&mut self, len: usize) -> BinaryResult<&'b [u8]> {
+        let end = self.pos.checked_add(len).ok_or(BinaryError::UnexpectedEnd)?;
+        let bytes = self
+            .bytes
+            .get(self.pos..end)
+            .ok_or(BinaryError::UnexpectedEnd)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+}
+
+/// CRC-32 (IEEE)
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn function(name: &str, chunk: Chunk) -> Value {
+        Value::Function(Rc::new(Function {
+            name: name.to_owned(),
+            arity: 0,
+            chunk,
+        }))
+    }
+
+    fn sample() -> Chunk {
+        let mut sum = Chunk::default();
+        sum.mark_span(10..15);
+        sum.write_const(Value::I32(1));
+        sum.write_const(Value::U8(2));
+        sum.add_instr(OpCode::Sum);
+        sum.add_instr(OpCode::Ret);
+
+        let mut chunk = Chunk::default();
+        chunk.mark_span(0..5);
+        chunk.write_const(function("__sum0", sum));
+        let name = chunk.add_const(Value::String("__sum0".to_owned()));
+        chunk.add_instr_with_arg(OpCode::DefGlobal, OpCode::DefGlobalLong, name);
+        chunk.write_const(Value::Bool(true));
+        let jmp = chunk.add_jmp(OpCode::JmpIfFalse);
+        chunk.write_const(Value::F64(1.5));
+        chunk.add_instr(OpCode::Cast);
+        chunk.write(NumTy::F32 as u8);
+        chunk.add_instr(OpCode::Pop);
+        assert!(chunk.patch_jmp(jmp));
+        chunk.add_instr(OpCode::Void);
+        chunk.add_instr(OpCode::Ret);
+        let symbol = Symbol {
+            global: "__sum0".to_owned(),
+            ty: "fun".to_owned(),
+            mutable: false,
+        };
+        chunk.add_symbol("sum".to_owned(), symbol);
+        chunk
+    }
+
+    /// Bytes of a file with `payload`, the checksum matches it
+    fn file(payload: &[u8]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&crc32(payload).to_le_bytes());
+        bytes.extend_from_slice(payload);
+        bytes
+    }
+
+    fn invalid_reason(chunk: &Chunk) -> String {
+        match Chunk::from_bytes(&chunk.to_bytes().unwrap()) {
+            Err(BinaryError::Invalid { reason, .. }) => reason,
+            result => panic!("expected invalid bytecode, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let chunk = sample();
+        let bytes = chunk.to_bytes().unwrap();
+        let loaded = Chunk::from_bytes(&bytes).unwrap();
+
+        assert_eq!(loaded.code, chunk.code);
+        assert_eq!(loaded.spans, chunk.spans);
+        assert_eq!(loaded.symbol("sum"), chunk.symbol("sum"));
+        match loaded.get_const(0) {
+            Some(Value::Function(fun)) => {
+                assert_eq!(fun.name, "__sum0");
+                assert_eq!(fun.chunk.spans, vec![(0, 10..15)]);
+                assert!(matches!(fun.chunk.get_const(1), Some(Value::U8(2))));
+            }
+            constant => panic!("expected function, got {constant:?}"),
+        }
+        assert_eq!(loaded.to_bytes().unwrap(), bytes);
+    }
+
+    #[test]
+    fn bad_magic() {
+        let mut bytes = sample().to_bytes().unwrap();
+        bytes[0] = b'X';
+        assert_eq!(Chunk::from_bytes(&bytes).unwrap_err(), BinaryError::BadMagic);
+    }
+
+    #[test]
+    fn other_version() {
+        let mut bytes = sample().to_bytes().unwrap();
+        bytes[4..6].copy_from_slice(&(VERSION - 1).to_le_bytes());
+        assert_eq!(
+            Chunk::from_bytes(&bytes).unwrap_err(),
+            BinaryError::UnsupportedVersion(VERSION - 1)
+        );
+    }
+
+    #[test]
+    fn bad_checksum() {
+        let mut bytes = sample().to_bytes().unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert_eq!(Chunk::from_bytes(&bytes).unwrap_err(), BinaryError::ChecksumMismatch);
+    }
+
+    #[test]
+    fn truncated() {
+        let bytes = sample().to_bytes().unwrap();
+        assert_eq!(
+            Chunk::from_bytes(&bytes[..HEADER_LEN + 2]).unwrap_err(),
+            BinaryError::UnexpectedEnd
+        );
+
+        // Payload cut short with a matching checksum
+        let payload = &bytes[HEADER_LEN..bytes.len() - 1];
+        assert_eq!(Chunk::from_bytes(&file(payload)).unwrap_err(), BinaryError::UnexpectedEnd);
+    }
+
+    #[test]
+    fn bad_constant_tag() {
+        let mut payload = 1u32.to_le_bytes().to_vec();
+        payload.push(0xff);
+        assert_eq!(
+            Chunk::from_bytes(&file(&payload)).unwrap_err(),
+            BinaryError::BadConstantTag(0xff)
+        );
+    }
+
+    #[test]
+    fn bad_jump() {
+        let mut chunk = Chunk::default();
+        chunk.add_instr(OpCode::Jmp);
+        chunk.write(10);
+        chunk.write(0);
+        chunk.add_instr(OpCode::Ret);
+        assert_eq!(invalid_reason(&chunk), "jump past the end of code");
+
+        let mut chunk = Chunk::default();
+        chunk.add_instr(OpCode::Void);
+        assert!(chunk.add_loop(0));
+        chunk.code[2] += 1;
+        assert_eq!(invalid_reason(&chunk), "loop before the start of code");
+    }
+
+    #[test]
+    fn bad_operand() {
+        let mut chunk = Chunk::default();
+        chunk.add_instr(OpCode::Const);
+        chunk.write(3);
+        assert_eq!(invalid_reason(&chunk), "constant 3 does not exist");
+
+        let mut chunk = Chunk::default();
+        let index = chunk.add_const(Value::I32(1));
+        chunk.add_instr_with_arg(OpCode::LoadGlobal, OpCode::LoadGlobalLong, index);
+        assert_eq!(invalid_reason(&chunk), "constant 0 is not a name");
+
+        let mut chunk = Chunk::default();
+        chunk.write_const(Value::I32(1));
+        chunk.add_instr(OpCode::Cast);
+        chunk.write(0xff);
+        assert_eq!(invalid_reason(&chunk), "255 is not a number type");
+
+        let mut chunk = Chunk::default();
+        chunk.add_instr(OpCode::Call);
+        assert_eq!(invalid_reason(&chunk), "OP_CALL is missing operands");
+
+        let mut chunk = Chunk::default();
+        chunk.write(0xff);
+        assert_eq!(invalid_reason(&chunk), "unknown opcode 255");
+    }
+
+    #[test]
+    fn invalid_nested_function() {
+        let mut inner = Chunk::default();
+        inner.add_instr(OpCode::Const);
+        inner.write(0);
+        let mut chunk = Chunk::default();
+        chunk.write_const(function("inner", inner));
+
+        match Chunk::from_bytes(&chunk.to_bytes().unwrap()) {
+            Err(BinaryError::Invalid { function, .. }) => assert_eq!(function, "inner"),
+            result => panic!("expected invalid bytecode, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let nested = |depth: usize| {
+            let mut chunk = Chunk::default();
+            for _ in 0..depth {
+                let mut outer = Chunk::default();
+                outer.write_const(function("nested", chunk));
+                chunk = outer;
+            }
+            chunk
+        };
+
+        assert!(Chunk::from_bytes(&nested(MAX_NESTING).to_bytes().unwrap()).is_ok());
+        assert_eq!(
+            Chunk::from_bytes(&nested(MAX_NESTING + 1).to_bytes().unwrap()).unwrap_err(),
+            BinaryError::NestingTooDeep
+        );
+    }
+}
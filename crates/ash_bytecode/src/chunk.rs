@@ -1,12 +1,78 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    ops::Range,
+};
+
 use crate::{opcode::OpCode, prelude::Value};
 
-#[derive(Default)]
+pub type Span = Range<usize>;
+
+#[derive(Debug, Default)]
 pub struct Chunk {
     pub(crate) constants: Vec<Value>,
     pub code: Vec<u8>,
+    // Offset of the first instruction that comes from the span.
+    // Span covers every instruction up to the next entry
+    pub(crate) spans: Vec<(usize, Span)>,
+    // Source names of root declarations mapped to their globals
+    pub(crate) symbols: HashMap<String, Symbol>,
+}
+
+/// Global of a root declaration
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Symbol {
+    pub global: String,
+    /// Declared type as named by [`Value::type_name`]
+    pub ty: String,
+    pub mutable: bool,
 }
 
 impl Chunk {
+    /// Marks next instructions as coming from `span`
+    pub fn mark_span(&mut self, span: Span) {
+        // Generated code has no place in source
+        if span.is_empty() {
+            return;
+        }
+
+        match self.spans.last_mut() {
+            Some((_, last)) if *last == span => {}
+            Some((offset, last)) if *offset == self.code.len() => *last = span,
+            _ => self.spans.push((self.code.len(), span)),
+        }
+    }
+
+    pub fn add_symbol(&mut self, name: String, symbol: Symbol) {
+        self.symbols.insert(name, symbol);
+    }
+
+    /// Returns global of the declaration `name` in source
+    pub fn symbol(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.get(name)
+    }
+
+    /// Returns name declared in source of the global, it is the reverse of [`Chunk::symbol`]
+    pub fn source_name(&self, global: &str) -> Option<&str> {
+        self.global_symbol(global).map(|(name, _)| name)
+    }
+
+    /// Returns source name and symbol of the global
+    pub fn global_symbol(&self, global: &str) -> Option<(&str, &Symbol)> {
+        self.symbols().find(|(_, symbol)| symbol.global == global)
+    }
+
+    pub fn symbols(&self) -> impl Iterator<Item = (&str, &Symbol)> {
+        self.symbols
+            .iter()
+            .map(|(name, symbol)| (name.as_str(), symbol))
+    }
+
+    pub fn span_at(&self, offset: usize) -> Option<&Span> {
+        let index = self.spans.partition_point(|(start, _)| *start <= offset);
+        index.checked_sub(1).map(|index| &self.spans[index].1)
+    }
+
     pub fn add_instr(&mut self, op: OpCode) {
         self.write(op as u8);
     }
@@ -33,6 +99,39 @@ impl Chunk {
         }
     }
 
+    pub fn add_jmp(&mut self, op: OpCode) -> usize {
+        self.add_instr(op);
+        self.write(0xff);
+        self.write(0xff);
+        self.len() - 2
+    }
+
+    /// Points jump at `offset` to the end of the chunk.
+    /// Returns false if the jump is too long
+    pub fn patch_jmp(&mut self, offset: usize) -> bool {
+        let jmp = self.len() - offset - 2;
+        if jmp > u16::MAX as usize {
+            return false;
+        }
+
+        self.code[offset] = (jmp & 0xff) as u8;
+        self.code[offset + 1] = ((jmp >> 8) & 0xff) as u8;
+        true
+    }
+
+    /// Returns false if the loop body is too long
+    pub fn add_loop(&mut self, start: usize) -> bool {
+        self.add_instr(OpCode::Loop);
+        let jmp = self.len() - start + 2;
+        if jmp > u16::MAX as usize {
+            return false;
+        }
+
+        self.write((jmp & 0xff) as u8);
+        self.write(((jmp >> 8) & 0xff) as u8);
+        true
+    }
+
     pub fn write(&mut self, byte: u8) {
         self.code.push(byte);
     }
@@ -43,27 +142,50 @@ impl Chunk {
     }
 
     #[inline]
-    pub fn get_byte(&self, offset: usize) -> u8 {
-        self.code[offset]
+    pub fn get_byte(&self, offset: usize) -> Option<u8> {
+        self.code.get(offset).copied()
     }
 
     #[inline]
-    pub fn get_const(&self, index: usize) -> &Value {
-        &self.constants[index]
+    pub fn get_const(&self, index: usize) -> Option<&Value> {
+        self.constants.get(index)
     }
 
     pub fn print(&self, name: &str) {
-        println!("-= {name} =-");
+        // Printing to stdout can only fail if it is closed
+        let _ = self.disassemble(name, &mut io::stdout());
+    }
+
+    pub fn disassemble(&self, name: &str, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "-= {name} =-")?;
 
         let mut offset = 0;
         while offset < self.code.len() {
-            offset = self.print_instruction(offset);
+            offset = self.disassemble_instruction(offset, out)?;
         }
+
+        for constant in self.constants.iter() {
+            if let Value::Function(fun) = constant {
+                fun.chunk.disassemble(&fun.name, out)?;
+            }
+        }
+
+        Ok(())
     }
 
-    fn print_instruction(&self, offset: usize) -> usize {
-        print!("{:0>5} ", offset);
-        let op: OpCode = self.code[offset].into();
-        op.print(self, offset)
+    /// Writes instruction at `offset`, returns offset of the next one
+    pub fn disassemble_instruction(&self, offset: usize, out: &mut dyn Write) -> io::Result<usize> {
+        write!(out, "{:0>5} ", offset)?;
+        match self.spans.iter().find(|(start, _)| *start == offset) {
+            Some((_, span)) => write!(out, "{:>9} ", format!("{}..{}", span.start, span.end))?,
+            None => write!(out, "{:>9} ", "|")?,
+        }
+        match OpCode::try_from(self.code[offset]) {
+            Ok(op) => op.disassemble(self, offset, out),
+            Err(byte) => {
+                writeln!(out, "OP_UNKNOWN {byte}")?;
+                Ok(offset + 1)
+            }
+        }
     }
 }
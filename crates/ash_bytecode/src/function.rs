@@ -0,0 +1,8 @@
+use crate::prelude::Chunk;
+
+#[derive(Debug, Default)]
+pub struct Function {
+    pub name: String,
+    pub arity: u8,
+    pub chunk: Chunk,
+}
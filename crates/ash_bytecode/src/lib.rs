@@ -1,4 +1,7 @@
+pub mod binary;
 pub mod chunk;
+pub mod function;
+pub mod native;
 pub mod opcode;
 pub mod prelude;
 pub mod value;
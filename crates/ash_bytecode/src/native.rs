@@ -0,0 +1,93 @@
+use std::fmt;
+
+use crate::prelude::Value;
+
+/// Types which can cross the boundary between Ash and native functions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NativeTy {
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,
+    Bool,
+    Str,
+    Void,
+}
+
+impl NativeTy {
+    /// Returns type of a marshaled value
+    pub fn of(value: &Value) -> Option<Self> {
+        let ty = match value {
+            Value::I8(_) => Self::I8,
+            Value::I16(_) => Self::I16,
+            Value::I32(_) => Self::I32,
+            Value::I64(_) => Self::I64,
+            Value::U8(_) => Self::U8,
+            Value::U16(_) => Self::U16,
+            Value::U32(_) => Self::U32,
+            Value::U64(_) => Self::U64,
+            Value::F32(_) => Self::F32,
+            Value::F64(_) => Self::F64,
+            Value::Bool(_) => Self::Bool,
+            Value::String(_) => Self::Str,
+            Value::Void => Self::Void,
+            Value::Function(_) | Value::Object(_) => return None,
+        };
+
+        Some(ty)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::I8 => "i8",
+            Self::I16 => "i16",
+            Self::I32 => "i32",
+            Self::I64 => "i64",
+            Self::U8 => "u8",
+            Self::U16 => "u16",
+            Self::U32 => "u32",
+            Self::U64 => "u64",
+            Self::F32 => "f32",
+            Self::F64 => "f64",
+            Self::Bool => "bool",
+            Self::Str => "str",
+            Self::Void => "void",
+        }
+    }
+}
+
+impl fmt::Display for NativeTy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NativeSignature {
+    pub name: String,
+    pub params: Vec<NativeTy>,
+    pub ret: NativeTy,
+}
+
+impl fmt::Display for NativeSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params = self
+            .params
+            .iter()
+            .map(NativeTy::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(f, "fun {}({})", self.name, params)?;
+        if self.ret != NativeTy::Void {
+            write!(f, " > {}", self.ret)?;
+        }
+
+        Ok(())
+    }
+}
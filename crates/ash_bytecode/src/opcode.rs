@@ -1,9 +1,12 @@
-use std::fmt;
+use std::{
+    fmt,
+    io::{self, Write},
+};
 
-use crate::prelude::Chunk;
+use crate::prelude::{Chunk, NumTy};
 
 #[repr(u8)]
-#[derive(PartialEq, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum OpCode {
     Ret = 0,
     Const = 1,
@@ -37,6 +40,20 @@ pub enum OpCode {
     JmpIfFalse = 29,
     Jmp = 30,
     Loop = 31,
+    Void = 32,
+    Call = 33,
+    LoadNative = 34,
+    LoadNativeLong = 35,
+    ToStr = 36,
+    Cast = 37,
+    BitAnd = 38,
+    BitOr = 39,
+    BitXor = 40,
+    BitNot = 41,
+    Shl = 42,
+    Shr = 43,
+    // Pushes the function of the current call frame, nested functions call themselves with it
+    LoadCallee = 44,
 }
 
 impl fmt::Display for OpCode {
@@ -74,15 +91,30 @@ impl fmt::Display for OpCode {
             Self::JmpIfFalse => "OP_JMP_IF_FALSE",
             Self::Jmp => "OP_JMP",
             Self::Loop => "OP_LOOP",
+            Self::Void => "OP_VOID",
+            Self::Call => "OP_CALL",
+            Self::LoadNative => "OP_LOAD_NATIVE",
+            Self::LoadNativeLong => "OP_LOAD_NATIVE_LONG",
+            Self::ToStr => "OP_TO_STR",
+            Self::Cast => "OP_CAST",
+            Self::BitAnd => "OP_BIT_AND",
+            Self::BitOr => "OP_BIT_OR",
+            Self::BitXor => "OP_BIT_XOR",
+            Self::BitNot => "OP_BIT_NOT",
+            Self::Shl => "OP_SHL",
+            Self::Shr => "OP_SHR",
+            Self::LoadCallee => "OP_LOAD_CALLEE",
         };
 
         f.write_str(s)
     }
 }
 
-impl From<u8> for OpCode {
-    fn from(b: u8) -> Self {
-        match b {
+impl TryFrom<u8> for OpCode {
+    type Error = u8;
+
+    fn try_from(b: u8) -> Result<Self, Self::Error> {
+        let op = match b {
             0 => Self::Ret,
             1 => Self::Const,
             2 => Self::ConstLong,
@@ -115,13 +147,53 @@ impl From<u8> for OpCode {
             29 => Self::JmpIfFalse,
             30 => Self::Jmp,
             31 => Self::Loop,
-            _ => unreachable!("Operation does not exist: {b}"),
-        }
+            32 => Self::Void,
+            33 => Self::Call,
+            34 => Self::LoadNative,
+            35 => Self::LoadNativeLong,
+            36 => Self::ToStr,
+            37 => Self::Cast,
+            38 => Self::BitAnd,
+            39 => Self::BitOr,
+            40 => Self::BitXor,
+            41 => Self::BitNot,
+            42 => Self::Shl,
+            43 => Self::Shr,
+            44 => Self::LoadCallee,
+            _ => return Err(b),
+        };
+
+        Ok(op)
     }
 }
 
 impl OpCode {
-    pub fn print(&self, chunk: &Chunk, offset: usize) -> usize {
+    /// Amount of bytes following the opcode
+    pub fn operands_len(&self) -> usize {
+        match self {
+            Self::Const
+            | Self::DefGlobal
+            | Self::LoadGlobal
+            | Self::StoreGlobal
+            | Self::LoadNative
+            | Self::LoadLocal
+            | Self::StoreLocal
+            | Self::Call
+            | Self::Cast => 1,
+            Self::JmpIfFalse | Self::Jmp | Self::Loop => 2,
+            Self::ConstLong
+            | Self::DefGlobalLong
+            | Self::LoadGlobalLong
+            | Self::StoreGlobalLong
+            | Self::LoadNativeLong
+            | Self::LoadLocalLong
+            | Self::StoreLocalLong => 3,
+            _ => 0,
+        }
+    }
+
+    /// Writes disassembled instruction, returns offset of the next one
+    pub fn disassemble(&self, chunk: &Chunk, offset: usize, out: &mut dyn Write) -> io::Result<usize> {
         let read_long = || {
             let c1 = chunk.code[offset + 1] as usize;
             let c2 = chunk.code[offset + 2] as usize;
@@ -155,34 +227,61 @@ impl OpCode {
             | Self::Lt
             | Self::Gte
             | Self::Lte
-            | Self::Pop => {
-                println!("{}", self.to_string());
-                offset + 1
+            | Self::Pop
+            | Self::Void
+            | Self::ToStr
+            | Self::BitAnd
+            | Self::BitOr
+            | Self::BitXor
+            | Self::BitNot
+            | Self::Shl
+            | Self::Shr
+            | Self::LoadCallee => {
+                writeln!(out, "{}", self)?;
+                Ok(offset + 1)
             }
-            Self::Const | Self::DefGlobal | Self::LoadGlobal | Self::StoreGlobal => {
+            Self::Const
+            | Self::DefGlobal
+            | Self::LoadGlobal
+            | Self::StoreGlobal
+            | Self::LoadNative => {
                 let index = chunk.code[offset + 1];
                 let value = &chunk.constants[index as usize];
-                println!("{} `{}` at {}", self.to_string(), value.to_string(), index);
-                offset + 2
+                writeln!(out, "{} `{}` at {}", self, value, index)?;
+                Ok(offset + 2)
             }
             Self::ConstLong
             | Self::DefGlobalLong
             | Self::LoadGlobalLong
-            | Self::StoreGlobalLong => {
+            | Self::StoreGlobalLong
+            | Self::LoadNativeLong => {
                 let index = read_long();
                 let value = &chunk.constants[index];
-                println!("{} `{}` at {}", self.to_string(), value.to_string(), index,);
-                offset + 4
+                writeln!(out, "{} `{}` at {}", self, value, index)?;
+                Ok(offset + 4)
             }
             Self::LoadLocal | Self::StoreLocal => {
                 let slot = chunk.code[offset + 1];
-                println!("{} slot {}", self.to_string(), slot);
-                offset + 2
+                writeln!(out, "{} slot {}", self, slot)?;
+                Ok(offset + 2)
             }
             Self::LoadLocalLong | Self::StoreLocalLong => {
                 let slot = read_long();
-                println!("{} slot {}", self.to_string(), slot);
-                offset + 2
+                writeln!(out, "{} slot {}", self, slot)?;
+                Ok(offset + 4)
+            }
+            Self::Call => {
+                let arg_count = chunk.code[offset + 1];
+                writeln!(out, "{} args {}", self, arg_count)?;
+                Ok(offset + 2)
+            }
+            Self::Cast => {
+                let byte = chunk.code[offset + 1];
+                match NumTy::try_from(byte) {
+                    Ok(ty) => writeln!(out, "{} {}", self, ty)?,
+                    Err(byte) => writeln!(out, "{} <bad type {}>", self, byte)?,
+                }
+                Ok(offset + 2)
             }
             Self::JmpIfFalse | Self::Jmp | Self::Loop => {
                 let jmp = read_short() as i64;
@@ -191,8 +290,8 @@ impl OpCode {
                 } else {
                     1
                 };
-                println!("{} {} -> {}", self.to_string(), offset, (offset as i64) + 3 + sign * jmp);
-                offset + 3
+                writeln!(out, "{} {} -> {}", self, offset, (offset as i64) + 3 + sign * jmp)?;
+                Ok(offset + 3)
             }
         }
     }
@@ -1,3 +1,6 @@
+pub use crate::binary::*;
 pub use crate::chunk::*;
+pub use crate::function::*;
+pub use crate::native::*;
 pub use crate::opcode::*;
 pub use crate::value::*;
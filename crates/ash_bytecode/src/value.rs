@@ -1,23 +1,220 @@
 use std::{
+    cmp::Ordering,
     fmt,
-    ops::{Add, Div, Mul, Neg, Not, Rem, Sub},
+    ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Neg, Not, Rem, Shl, Shr, Sub},
+    rc::Rc,
 };
 
+use crate::prelude::Function;
+
 #[derive(Debug, Clone)]
 pub enum Value {
+    I8(i8),
+    I16(i16),
     I32(i32),
+    I64(i64),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    F32(f32),
     F64(f64),
     Bool(bool),
-    String(String), // TODO: Use GCObject
+    // Only used by constants and native calls, VM keeps strings as objects
+    String(String),
+    Function(Rc<Function>),
+    Object(ObjRef),
+    Void,
+}
+
+/// Handle to an object owned by the VM heap
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjRef(usize);
+
+impl ObjRef {
+    pub fn new(index: usize) -> Self {
+        Self(index)
+    }
+
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+/// Number type, the operand of `OpCode::Cast`
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumTy {
+    I8 = 0,
+    I16 = 1,
+    I32 = 2,
+    I64 = 3,
+    U8 = 4,
+    U16 = 5,
+    U32 = 6,
+    U64 = 7,
+    F32 = 8,
+    F64 = 9,
+}
+
+impl NumTy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::I8 => "i8",
+            Self::I16 => "i16",
+            Self::I32 => "i32",
+            Self::I64 => "i64",
+            Self::U8 => "u8",
+            Self::U16 => "u16",
+            Self::U32 => "u32",
+            Self::U64 => "u64",
+            Self::F32 => "f32",
+            Self::F64 => "f64",
+        }
+    }
+}
+
+impl fmt::Display for NumTy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl TryFrom<u8> for NumTy {
+    type Error = u8;
+
+    fn try_from(b: u8) -> Result<Self, Self::Error> {
+        let ty = match b {
+            0 => Self::I8,
+            1 => Self::I16,
+            2 => Self::I32,
+            3 => Self::I64,
+            4 => Self::U8,
+            5 => Self::U16,
+            6 => Self::U32,
+            7 => Self::U64,
+            8 => Self::F32,
+            9 => Self::F64,
+            _ => return Err(b),
+        };
+
+        Ok(ty)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueError {
+    TypeMismatch,
+    DivisionByZero,
+    Overflow,
+}
+
+pub type ValueResult<T = Value> = Result<T, ValueError>;
+
+/// Matches numbers of the same type, binding them to `$a` and `$b`
+macro_rules! match_numbers {
+    ($values:expr, ($a:ident, $b:ident) => $then:expr, _ => $otherwise:expr) => {
+        match $values {
+            (Value::I8($a), Value::I8($b)) => $then,
+            (Value::I16($a), Value::I16($b)) => $then,
+            (Value::I32($a), Value::I32($b)) => $then,
+            (Value::I64($a), Value::I64($b)) => $then,
+            (Value::U8($a), Value::U8($b)) => $then,
+            (Value::U16($a), Value::U16($b)) => $then,
+            (Value::U32($a), Value::U32($b)) => $then,
+            (Value::U64($a), Value::U64($b)) => $then,
+            (Value::F32($a), Value::F32($b)) => $then,
+            (Value::F64($a), Value::F64($b)) => $then,
+            _ => $otherwise,
+        }
+    };
+}
+
+/// Applies checked integer method `$int` or float operator `$float` to numbers of the same type
+macro_rules! numeric_op {
+    ($values:expr, $int:ident, $float:tt) => {
+        numeric_op!(@ $values, $int, $float, [I8, I16, I32, I64, U8, U16, U32, U64], [F32, F64])
+    };
+    (@ $values:expr, $int:ident, $float:tt, [$($i:ident),*], [$($f:ident),*]) => {
+        match $values {
+            $((Value::$i(a), Value::$i(b)) => checked(a.$int(b), b == 0).map(Value::$i),)*
+            $((Value::$f(a), Value::$f(b)) => Ok(Value::$f(a $float b)),)*
+            _ => Err(ValueError::TypeMismatch),
+        }
+    };
+}
+
+/// Applies bitwise operator `$op` to integers of the same type
+macro_rules! bitwise_op {
+    ($values:expr, $op:tt) => {
+        bitwise_op!(@ $values, $op, [I8, I16, I32, I64, U8, U16, U32, U64])
+    };
+    (@ $values:expr, $op:tt, [$($i:ident),*]) => {
+        match $values {
+            $((Value::$i(a), Value::$i(b)) => Ok(Value::$i(a $op b)),)*
+            _ => Err(ValueError::TypeMismatch),
+        }
+    };
+}
+
+/// Shifts integer `$value` by `$amount` bits with checked method `$shift`, keeping its type
+macro_rules! shift_op {
+    ($value:expr, $amount:expr, $shift:ident) => {
+        shift_op!(@ $value, $amount, $shift, [I8, I16, I32, I64, U8, U16, U32, U64])
+    };
+    (@ $value:expr, $amount:expr, $shift:ident, [$($i:ident),*]) => {
+        match $value {
+            $(Value::$i(v) => checked(v.$shift($amount), false).map(Value::$i),)*
+            _ => Err(ValueError::TypeMismatch),
+        }
+    };
+}
+
+/// Converts primitive number `$v` to a value of type `$ty`
+macro_rules! cast {
+    ($v:expr, $ty:expr) => {
+        match $ty {
+            NumTy::I8 => Value::I8($v as i8),
+            NumTy::I16 => Value::I16($v as i16),
+            NumTy::I32 => Value::I32($v as i32),
+            NumTy::I64 => Value::I64($v as i64),
+            NumTy::U8 => Value::U8($v as u8),
+            NumTy::U16 => Value::U16($v as u16),
+            NumTy::U32 => Value::U32($v as u32),
+            NumTy::U64 => Value::U64($v as u64),
+            NumTy::F32 => Value::F32($v as f32),
+            NumTy::F64 => Value::F64($v as f64),
+        }
+    };
+}
+
+/// Checked operations fail on overflow or on division by zero. Adding,
+/// subtracting or multiplying by zero never fails, so zero divisor is enough to tell them apart
+fn checked<T>(v: Option<T>, zero_divisor: bool) -> ValueResult<T> {
+    match zero_divisor {
+        true => v.ok_or(ValueError::DivisionByZero),
+        false => v.ok_or(ValueError::Overflow),
+    }
 }
 
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s = match self {
+            Self::I8(v) => v.to_string(),
+            Self::I16(v) => v.to_string(),
             Self::I32(v) => v.to_string(),
+            Self::I64(v) => v.to_string(),
+            Self::U8(v) => v.to_string(),
+            Self::U16(v) => v.to_string(),
+            Self::U32(v) => v.to_string(),
+            Self::U64(v) => v.to_string(),
+            Self::F32(v) => format!("{:.2}", v),
             Self::F64(v) => format!("{:.2}", v),
             Self::Bool(v) => format!("{v}"),
             Self::String(v) => v.clone(),
+            Self::Function(v) => format!("<fun {}>", v.name),
+            Self::Object(v) => format!("<object {}>", v.index()),
+            Self::Void => "void".to_owned(),
         };
 
         f.write_str(&s)
@@ -25,146 +222,344 @@ impl fmt::Display for Value {
 }
 
 impl Not for Value {
-    type Output = Self;
+    type Output = ValueResult;
 
     fn not(self) -> Self::Output {
         match self {
-            Self::Bool(v) => Self::Bool(!v),
-            _ => unreachable!(),
+            Self::Bool(v) => Ok(Self::Bool(!v)),
+            _ => Err(ValueError::TypeMismatch),
         }
     }
 }
 
 impl Neg for Value {
-    type Output = Self;
+    type Output = ValueResult;
 
     fn neg(self) -> Self::Output {
         match self {
-            Self::I32(v) => Self::I32(-v),
-            Self::F64(v) => Self::F64(-v),
-            _ => unreachable!(),
+            Self::I8(v) => checked(v.checked_neg(), false).map(Self::I8),
+            Self::I16(v) => checked(v.checked_neg(), false).map(Self::I16),
+            Self::I32(v) => checked(v.checked_neg(), false).map(Self::I32),
+            Self::I64(v) => checked(v.checked_neg(), false).map(Self::I64),
+            Self::F32(v) => Ok(Self::F32(-v)),
+            Self::F64(v) => Ok(Self::F64(-v)),
+            _ => Err(ValueError::TypeMismatch),
         }
     }
 }
 
 impl Add for Value {
-    type Output = Self;
+    type Output = ValueResult;
 
     fn add(self, rhs: Self) -> Self::Output {
         match (self, rhs) {
-            (Self::I32(v1), Self::I32(v2)) => Self::I32(v1 + v2),
-            (Self::F64(v1), Self::F64(v2)) => Self::F64(v1 + v2),
-            (Self::String(v1), Self::String(v2)) => Self::String(v1 + &v2),
-            _ => unreachable!(),
+            (Self::String(v1), Self::String(v2)) => Ok(Self::String(v1 + &v2)),
+            values => numeric_op!(values, checked_add, +),
         }
     }
 }
 
 impl Sub for Value {
-    type Output = Self;
+    type Output = ValueResult;
 
     fn sub(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Self::I32(v1), Self::I32(v2)) => Self::I32(v1 - v2),
-            (Self::F64(v1), Self::F64(v2)) => Self::F64(v1 - v2),
-            _ => unreachable!(),
-        }
+        numeric_op!((self, rhs), checked_sub, -)
     }
 }
 
 impl Mul for Value {
-    type Output = Value;
+    type Output = ValueResult;
 
     fn mul(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Self::I32(v1), Self::I32(v2)) => Self::I32(v1 * v2),
-            (Self::F64(v1), Self::F64(v2)) => Self::F64(v1 * v2),
-            _ => unreachable!(),
-        }
+        numeric_op!((self, rhs), checked_mul, *)
     }
 }
 
 impl Div for Value {
-    type Output = Value;
+    type Output = ValueResult;
 
     fn div(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Self::I32(v1), Self::I32(v2)) => Self::I32(v1 / v2),
-            (Self::F64(v1), Self::F64(v2)) => Self::F64(v1 / v2),
-            _ => unreachable!(),
-        }
+        numeric_op!((self, rhs), checked_div, /)
     }
 }
 
 impl Rem for Value {
-    type Output = Self;
+    type Output = ValueResult;
 
     fn rem(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Self::I32(v1), Self::I32(v2)) => Self::I32(v1 % v2),
-            (Self::F64(v1), Self::F64(v2)) => Self::F64(v1 % v2),
-            _ => unreachable!(),
-        }
+        numeric_op!((self, rhs), checked_rem, %)
     }
 }
 
-impl PartialEq for Value {
-    fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Self::I32(l0), Self::I32(r0)) => l0 == r0,
-            (Self::F64(l0), Self::F64(r0)) => l0 == r0,
-            (Self::Bool(l0), Self::Bool(r0)) => l0 == r0,
-            _ => unreachable!(),
-        }
+impl BitAnd for Value {
+    type Output = ValueResult;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        bitwise_op!((self, rhs), &)
     }
 }
 
-impl PartialOrd for Value {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        match (self, other) {
-            (Self::I32(v1), Self::I32(v2)) => v1.partial_cmp(v2),
-            (Self::F64(v1), Self::F64(v2)) => v1.partial_cmp(v2),
-            _ => unreachable!(),
-        }
+impl BitOr for Value {
+    type Output = ValueResult;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        bitwise_op!((self, rhs), |)
+    }
+}
+
+impl BitXor for Value {
+    type Output = ValueResult;
+
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        bitwise_op!((self, rhs), ^)
+    }
+}
+
+impl Shl for Value {
+    type Output = ValueResult;
+
+    fn shl(self, rhs: Self) -> Self::Output {
+        shift_op!(self, rhs.shift_amount()?, checked_shl)
+    }
+}
+
+/// Signed integers are shifted arithmetically, keeping their sign
+impl Shr for Value {
+    type Output = ValueResult;
+
+    fn shr(self, rhs: Self) -> Self::Output {
+        shift_op!(self, rhs.shift_amount()?, checked_shr)
     }
 }
 
 impl Value {
-    pub fn eq(self, other: Self) -> Self {
-        Self::Bool(self == other)
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::I8(_) => "i8",
+            Self::I16(_) => "i16",
+            Self::I32(_) => "i32",
+            Self::I64(_) => "i64",
+            Self::U8(_) => "u8",
+            Self::U16(_) => "u16",
+            Self::U32(_) => "u32",
+            Self::U64(_) => "u64",
+            Self::F32(_) => "f32",
+            Self::F64(_) => "f64",
+            Self::Bool(_) => "bool",
+            Self::String(_) => "str",
+            Self::Function(_) => "fun",
+            Self::Object(_) => "object",
+            Self::Void => "void",
+        }
+    }
+
+    pub fn eq(self, other: Self) -> ValueResult {
+        let eq = match (&self, &other) {
+            (Self::Bool(v1), Self::Bool(v2)) => v1 == v2,
+            (Self::String(v1), Self::String(v2)) => v1 == v2,
+            (Self::Function(v1), Self::Function(v2)) => Rc::ptr_eq(v1, v2),
+            // Strings are interned so comparing references is enough
+            (Self::Object(v1), Self::Object(v2)) => v1 == v2,
+            (Self::Void, Self::Void) => true,
+            values => match_numbers!(values, (v1, v2) => v1 == v2, _ => return Err(ValueError::TypeMismatch)),
+        };
+
+        Ok(Self::Bool(eq))
+    }
+
+    pub fn neq(self, other: Self) -> ValueResult {
+        self.eq(other)?.not()
     }
 
-    pub fn neq(self, other: Self) -> Self {
-        self.eq(other).not()
+    pub fn gt(self, other: Self) -> ValueResult {
+        self.compare(other, Ordering::is_gt)
     }
 
-    pub fn gt(self, other: Self) -> Self {
-        Self::Bool(self > other)
+    pub fn lt(self, other: Self) -> ValueResult {
+        self.compare(other, Ordering::is_lt)
     }
 
-    pub fn lt(self, other: Self) -> Self {
-        Self::Bool(self < other)
+    pub fn gte(self, other: Self) -> ValueResult {
+        self.compare(other, Ordering::is_ge)
     }
 
-    pub fn gte(self, other: Self) -> Self {
-        Self::Bool(self >= other)
+    pub fn lte(self, other: Self) -> ValueResult {
+        self.compare(other, Ordering::is_le)
     }
 
-    pub fn lte(self, other: Self) -> Self {
-        Self::Bool(self <= other)
+    fn compare<F>(self, other: Self, f: F) -> ValueResult
+    where
+        F: FnOnce(Ordering) -> bool,
+    {
+        let ord = match (&self, &other) {
+            (Self::String(v1), Self::String(v2)) => v1.partial_cmp(v2),
+            values => match_numbers!(
+                values,
+                (v1, v2) => v1.partial_cmp(v2),
+                _ => return Err(ValueError::TypeMismatch)
+            ),
+        };
+
+        // NaN is not comparable
+        Ok(Self::Bool(ord.map(f).unwrap_or(false)))
     }
 
-    pub fn string_value(self) -> String {
+    /// Converts a number to type `ty`. Integers are truncated to the bits of
+    /// narrower types and extended to wider ones, floats become integers
+    /// rounded toward zero and saturated to the range of the type, NaN becomes 0.
+    /// Conversions to floats round to the nearest value
+    pub fn cast(self, ty: NumTy) -> ValueResult {
+        let value = match self {
+            Self::I8(v) => cast!(v, ty),
+            Self::I16(v) => cast!(v, ty),
+            Self::I32(v) => cast!(v, ty),
+            Self::I64(v) => cast!(v, ty),
+            Self::U8(v) => cast!(v, ty),
+            Self::U16(v) => cast!(v, ty),
+            Self::U32(v) => cast!(v, ty),
+            Self::U64(v) => cast!(v, ty),
+            Self::F32(v) => cast!(v, ty),
+            Self::F64(v) => cast!(v, ty),
+            _ => return Err(ValueError::TypeMismatch),
+        };
+
+        Ok(value)
+    }
+
+    /// Flips every bit of an integer, `Not` is the logical not of bools
+    pub fn bit_not(self) -> ValueResult {
+        let value = match self {
+            Self::I8(v) => Self::I8(!v),
+            Self::I16(v) => Self::I16(!v),
+            Self::I32(v) => Self::I32(!v),
+            Self::I64(v) => Self::I64(!v),
+            Self::U8(v) => Self::U8(!v),
+            Self::U16(v) => Self::U16(!v),
+            Self::U32(v) => Self::U32(!v),
+            Self::U64(v) => Self::U64(!v),
+            _ => return Err(ValueError::TypeMismatch),
+        };
+
+        Ok(value)
+    }
+
+    /// The right operand of a shift can be of any integer type. Shifting
+    /// by a negative amount or by the bit width of the type or more overflows
+    fn shift_amount(self) -> ValueResult<u32> {
+        let amount = match self {
+            Self::I8(v) => u32::try_from(v).ok(),
+            Self::I16(v) => u32::try_from(v).ok(),
+            Self::I32(v) => u32::try_from(v).ok(),
+            Self::I64(v) => u32::try_from(v).ok(),
+            Self::U8(v) => Some(u32::from(v)),
+            Self::U16(v) => Some(u32::from(v)),
+            Self::U32(v) => Some(v),
+            Self::U64(v) => u32::try_from(v).ok(),
+            _ => return Err(ValueError::TypeMismatch),
+        };
+
+        checked(amount, false)
+    }
+
+    pub fn string_value(self) -> ValueResult<String> {
         match self {
-            Self::String(v) => v,
-            _ => unreachable!(),
+            Self::String(v) => Ok(v),
+            _ => Err(ValueError::TypeMismatch),
         }
     }
 
-    pub fn bool_value(self) -> bool {
+    pub fn bool_value(self) -> ValueResult<bool> {
         match self {
-            Self::Bool(v) => v,
-            _ => unreachable!(),
+            Self::Bool(v) => Ok(v),
+            _ => Err(ValueError::TypeMismatch),
         }
     }
 }
+
+macro_rules! impl_conversions {
+    ($($ty:ty => $variant:ident),*) => {
+        $(
+            impl From<$ty> for Value {
+                fn from(v: $ty) -> Self {
+                    Self::$variant(v)
+                }
+            }
+
+            impl TryFrom<Value> for $ty {
+                type Error = ValueError;
+
+                fn try_from(value: Value) -> ValueResult<Self> {
+                    match value {
+                        Value::$variant(v) => Ok(v),
+                        _ => Err(ValueError::TypeMismatch),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_conversions!(
+    i8 => I8,
+    i16 => I16,
+    i32 => I32,
+    i64 => I64,
+    u8 => U8,
+    u16 => U16,
+    u32 => U32,
+    u64 => U64,
+    f32 => F32,
+    f64 => F64,
+    bool => Bool,
+    String => String
+);
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Self::String(v.to_owned())
+    }
+}
+
+impl From<()> for Value {
+    fn from(_: ()) -> Self {
+        Self::Void
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bitwise_operators() {
+        assert!(matches!(Value::U8(0b1100) & Value::U8(0b1010), Ok(Value::U8(0b1000))));
+        assert!(matches!(Value::I32(0b1100) | Value::I32(0b1010), Ok(Value::I32(0b1110))));
+        assert!(matches!(Value::U64(0b1100) ^ Value::U64(0b1010), Ok(Value::U64(0b0110))));
+        assert!(matches!(Value::I8(0).bit_not(), Ok(Value::I8(-1))));
+        assert!(matches!(Value::U16(0).bit_not(), Ok(Value::U16(u16::MAX))));
+
+        assert!(matches!(Value::U8(1) & Value::I32(1), Err(ValueError::TypeMismatch)));
+        assert!(matches!(Value::F64(1.0) | Value::F64(1.0), Err(ValueError::TypeMismatch)));
+        assert!(matches!(Value::Bool(true).bit_not(), Err(ValueError::TypeMismatch)));
+    }
+
+    #[test]
+    fn shifts() {
+        assert!(matches!(Value::U8(1) << Value::I64(7), Ok(Value::U8(128))));
+        // Bits shifted out are dropped
+        assert!(matches!(Value::U8(0b1100_0000) << Value::U8(1), Ok(Value::U8(0b1000_0000))));
+        assert!(matches!(Value::I32(-8) >> Value::U8(1), Ok(Value::I32(-4))));
+        assert!(matches!(Value::I8(i8::MIN) >> Value::I32(7), Ok(Value::I8(-1))));
+        assert!(matches!(Value::U32(u32::MAX) >> Value::U64(31), Ok(Value::U32(1))));
+    }
+
+    #[test]
+    fn shift_amounts() {
+        assert!(matches!(Value::U8(1) << Value::I32(8), Err(ValueError::Overflow)));
+        assert!(matches!(Value::I64(1) >> Value::I32(64), Err(ValueError::Overflow)));
+        assert!(matches!(Value::I32(1) << Value::I32(-1), Err(ValueError::Overflow)));
+        assert!(matches!(Value::I32(1) << Value::U64(u64::MAX), Err(ValueError::Overflow)));
+        assert!(matches!(Value::I32(1) << Value::F32(1.0), Err(ValueError::TypeMismatch)));
+        assert!(matches!(Value::F64(1.0) >> Value::I32(1), Err(ValueError::TypeMismatch)));
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+
+use ash_bytecode::prelude::NativeSignature;
+use chumsky::prelude::Simple;
+
+use crate::ashery::{analyze_modules, entry_location};
+use crate::core::{Context, Id, SourceMap, Span};
+use crate::lexer::{
+    token::Token,
+    trivia::{Trivia, TriviaToken},
+    Lexer,
+};
+
+/// What the front end knows about sources, used by editor integrations.
+/// Spans are positions in the `SourceMap`
+pub struct Analysis {
+    errors: Vec<Simple<String>>,
+    symbols: HashMap<Id, Symbol>,
+    // Declarations and references with the declaration they refer to
+    sites: Vec<(Span, Id)>,
+}
+
+/// Declared variable, parameter or function
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    id: Id,
+    pub name: String,
+    /// `None` when checking stopped before the type was known
+    pub ty: Option<String>,
+    /// Name of the symbol in its declaration
+    pub span: Span,
+    pub is_function: bool,
+    /// Text of `///` comments before the declaration
+    pub doc: Option<String>,
+}
+
+impl Analysis {
+    /// Runs every stage before code generation, sources are analyzed as modules of a project
+    pub fn run(sources: &SourceMap, natives: &[NativeSignature]) -> Self {
+        let mut context = Context::new(entry_location(sources));
+        let errors = match analyze_modules(&mut context, sources, natives) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors,
+        };
+
+        let tokens = scan(sources);
+        let names = tokens
+            .iter()
+            .filter_map(|token| match &token.token {
+                (Token::Identifier { value, .. }, span) => Some((value, span)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let mut symbols = HashMap::new();
+        let mut sites = Vec::new();
+        for (id, decl_span) in context.sites() {
+            let decl_id = context.decl_id(id);
+            if decl_id != id {
+                sites.push((decl_span.clone(), decl_id));
+                continue;
+            }
+
+            let name = context.name_of(id).unwrap_or_default().to_owned();
+            // Declarations span whole statements, the name is the first identifier matching it
+            let within = |span: &Span| decl_span.start <= span.start && span.end <= decl_span.end;
+            let span = names
+                .iter()
+                .find(|(ident, span)| **ident == name && within(span))
+                .map(|(_, span)| (*span).clone())
+                .unwrap_or_else(|| decl_span.clone());
+            // Parameters share the span of their function, only the function gets its doc
+            let first = names.iter().find(|(_, span)| within(span)).map(|(_, span)| *span);
+            let doc = match first == Some(&span) {
+                true => doc_comment(&tokens, decl_span.start),
+                false => None,
+            };
+            sites.push((span.clone(), id));
+            symbols.insert(
+                id,
+                Symbol {
+                    id,
+                    name,
+                    ty: context.ty_of(id).map(|ty| ty.to_string()),
+                    span,
+                    is_function: context.is_function(id),
+                    doc,
+                },
+            );
+        }
+
+        Self {
+            errors,
+            symbols,
+            sites,
+        }
+    }
+
+    /// Errors of the first failing stage
+    pub fn errors(&self) -> &[Simple<String>] {
+        &self.errors
+    }
+
+    /// Symbol declared or referenced at `offset`, the end of a name counts as a part of it
+    pub fn symbol_at(&self, offset: usize) -> Option<&Symbol> {
+        self.sites
+            .iter()
+            .filter(|(span, _)| span.start <= offset && offset <= span.end)
+            .min_by_key(|(span, _)| span.end - span.start)
+            .and_then(|(_, id)| self.symbols.get(id))
+    }
+
+    /// Declaration and every reference of the symbol in source order
+    pub fn references(&self, symbol: &Symbol) -> Vec<Span> {
+        let mut spans = self
+            .sites
+            .iter()
+            .filter(|(_, id)| *id == symbol.id)
+            .map(|(span, _)| span.clone())
+            .collect::<Vec<_>>();
+        spans.sort_by_key(|span| span.start);
+        spans
+    }
+
+    /// Functions in source order
+    pub fn functions(&self) -> Vec<&Symbol> {
+        let mut functions = self
+            .symbols
+            .values()
+            .filter(|symbol| symbol.is_function)
+            .collect::<Vec<_>>();
+        functions.sort_by_key(|symbol| symbol.span.start);
+        functions
+    }
+}
+
+/// Tokens of every source, sources which can not be scanned are skipped
+fn scan(sources: &SourceMap) -> Vec<TriviaToken> {
+    let lexer = Lexer::new();
+    sources
+        .iter()
+        .filter_map(|(offset, _, source)| lexer.scan_lossless(source.inner(), offset).ok())
+        .flat_map(|lexed| lexed.tokens)
+        .collect()
+}
+
+/// Doc comments before the declaration starting at `start`, `pub` and annotations are skipped
+fn doc_comment(tokens: &[TriviaToken], start: usize) -> Option<String> {
+    let mut i = tokens.iter().position(|token| token.token.1.start == start)?;
+    loop {
+        match tokens[..i].iter().map(|token| &token.token.0).collect::<Vec<_>>()[..] {
+            [.., Token::Pub] => i -= 1,
+            [.., Token::At, Token::LBracket, Token::Identifier { .. }, Token::RBracket] => i -= 4,
+            _ => break,
+        }
+    }
+
+    let lines = tokens[i]
+        .leading
+        .iter()
+        .filter_map(|(trivia, _)| match trivia {
+            Trivia::LineComment(text) if trivia.is_doc() => {
+                let text = text.trim_start_matches("///");
+                Some(text.strip_prefix(' ').unwrap_or(text).to_owned())
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    (!lines.is_empty()).then(|| lines.join("\n"))
+}
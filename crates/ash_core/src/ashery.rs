@@ -1,48 +1,422 @@
 use std::fs::{self, File};
-use std::io::Write;
-use std::path::PathBuf;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
-use ash_bytecode::prelude::Chunk;
+use ash_bytecode::prelude::{Chunk, NativeSignature, Symbol, EXTENSION};
+use chumsky::prelude::Simple;
 
-use crate::core::{AshResult, Context, Source, StringError};
-use crate::hir::Desugarer;
-use crate::lexer::Lexer;
-use crate::parser::parser::Parser;
-use crate::resolver::Resolver;
+use crate::codegen::CodeGen;
+use crate::core::{AshResult, Context, Id, Source, SourceMap, Spanned, StringError};
+use crate::hir::{self, Desugarer};
+use crate::lexer::{token::Token, Lexer};
+use crate::parser::{self, parser::Parser};
+use crate::resolver::{sort_modules, Module, Modules, Resolver, Scope};
+use crate::ty::{Ty, Typing};
 
-pub fn build(source: &Source) -> AshResult<(), String> {
+/// Compiles source, `@[builtin]` functions are checked against `natives`
+pub fn build(source: &Source, natives: &[NativeSignature]) -> AshResult<Chunk, String> {
+    let (ast, errors) = parse_recovery(source.inner(), 0);
+    generate(analyze(ast, errors, source.location(), natives)?, CodeGen::run)
+}
+
+/// Same as [`build`] for programs which functions are called by a host, `main` is optional
+pub fn build_library(source: &Source, natives: &[NativeSignature]) -> AshResult<Chunk, String> {
+    let (ast, errors) = parse_recovery(source.inner(), 0);
+    generate(analyze(ast, errors, source.location(), natives)?, CodeGen::run_library)
+}
+
+/// Compiles modules of a project as one program, the first source is the entry module.
+/// Error spans are positions in the `sources` map
+pub fn build_project(sources: &SourceMap, natives: &[NativeSignature]) -> AshResult<Chunk, String> {
+    generate(analyze_project(sources, natives)?, CodeGen::run)
+}
+
+/// Runs every stage before code generation and returns all errors of the first failing one.
+/// Syntax errors are returned together with resolver errors of the code around them
+pub fn check(source: &Source, natives: &[NativeSignature]) -> AshResult<(), String> {
+    let (ast, errors) = parse_recovery(source.inner(), 0);
+    analyze(ast, errors, source.location(), natives).map(|_| ())
+}
+
+/// Same as [`check`] for modules of a project
+pub fn check_project(sources: &SourceMap, natives: &[NativeSignature]) -> AshResult<(), String> {
+    analyze_project(sources, natives).map(|_| ())
+}
+
+/// Root declarations exported as symbols of the chunk, with their names in source
+type Symbols = Vec<(Id, String)>;
+
+/// Type checked program ready for code generation
+struct Analyzed {
+    context: Context,
+    hir: Vec<Spanned<hir::Stmt>>,
+    symbols: Symbols,
+}
+
+fn analyze_project(sources: &SourceMap, natives: &[NativeSignature]) -> AshResult<Analyzed, String> {
+    let mut context = Context::new(entry_location(sources));
+    let (hir, symbols) = analyze_modules(&mut context, sources, natives)?;
+
+    Ok(Analyzed { context, hir, symbols })
+}
+
+pub(crate) fn entry_location(sources: &SourceMap) -> String {
+    sources
+        .iter()
+        .next()
+        .map(|(_, _, source)| source.location())
+        .unwrap_or_default()
+}
+
+/// Parses, resolves, desugars and type checks modules of a project,
+/// the context keeps everything known about declarations even when it fails
+pub(crate) fn analyze_modules(
+    context: &mut Context,
+    sources: &SourceMap,
+    natives: &[NativeSignature],
+) -> AshResult<(Vec<Spanned<hir::Stmt>>, Symbols), String> {
+    let mut modules = Vec::new();
+    let mut errors = Vec::new();
+    for (offset, name, source) in sources.iter() {
+        let (ast, mut errs) = parse_recovery(source.inner(), offset);
+        errors.append(&mut errs);
+        modules.push(Module { name: name.to_owned(), ast });
+    }
+
+    let entry = sources
+        .iter()
+        .next()
+        .map(|(_, name, _)| name.to_owned())
+        .unwrap_or_default();
+    let mut resolved = Modules::new();
+    let mut hir = Vec::new();
+    let mut symbols = Vec::new();
+    let modules = match sort_modules(modules) {
+        Ok(modules) => modules,
+        Err(errs) => return Err([errors, errs].concat()),
+    };
+    for Module { name, ast } in modules {
+        // Modules with syntax errors are resolved too, until the first one failing
+        let scope = match Resolver::new(context, natives).with_modules(&resolved).run(&ast) {
+            Ok(scope) => scope,
+            Err(mut errs) => {
+                errors.append(&mut errs);
+                return Err(errors);
+            }
+        };
+
+        // Items of other modules are qualified by the module name
+        let is_entry = name == entry;
+        symbols.extend(declarations(&ast).into_iter().map(|(id, item)| match is_entry {
+            true => (id, item),
+            false => (id, format!("{name}.{item}")),
+        }));
+        if errors.is_empty() {
+            hir.append(&mut Desugarer::run_module(context, ast, is_entry));
+        }
+        resolved.insert(name, scope);
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Typing::run(context, &hir)?;
+
+    Ok((hir, symbols))
+}
+
+pub(crate) fn parse(code: &str, offset: usize) -> AshResult<Vec<Spanned<parser::Stmt>>, String> {
     let lexer = Lexer::new();
-    let tokens = lexer.scan(source.inner()).string_err()?;
+    let tokens = lexer.scan_at(code, offset).string_err()?;
     let parser = Parser::new();
-    let ast = parser.parse(tokens).string_err()?;
-    let mut context = Context::new(source.location());
+    parser.parse(tokens).string_err()
+}
+
+/// Parses as much of the code as it can and returns every syntax error,
+/// statements which failed to parse are `Stmt::Error`
+pub(crate) fn parse_recovery(code: &str, offset: usize) -> (Vec<Spanned<parser::Stmt>>, Vec<Simple<String>>) {
+    let (tokens, lexer_errors) = Lexer::new().scan_recovery(code, offset);
+    let mut errors = lexer_errors
+        .into_iter()
+        .map(|err| err.map(|c| c.to_string()))
+        .collect::<Vec<_>>();
+    let Some(tokens) = tokens else {
+        return (Vec::new(), errors);
+    };
+
+    let (ast, parser_errors) = Parser::new().parse_recovery(tokens);
+    // Invalid code scanned as `Token::Error` is already reported by the lexer
+    errors.extend(
+        parser_errors
+            .into_iter()
+            .filter(|err| err.found() != Some(&Token::Error))
+            .map(|err| err.map(|tok| tok.to_string())),
+    );
+
+    (ast, errors)
+}
+
+/// Code with syntax errors is only resolved
+fn analyze(
+    ast: Vec<Spanned<parser::Stmt>>,
+    syntax_errors: Vec<Simple<String>>,
+    location: String,
+    natives: &[NativeSignature],
+) -> AshResult<Analyzed, String> {
+    let mut context = Context::new(location);
 
-    let resolver = Resolver::new(&mut context);
-    resolver.run(&ast)?;
+    let resolver = Resolver::new(&mut context, natives);
+    match resolver.run(&ast) {
+        Ok(_) if syntax_errors.is_empty() => {}
+        Ok(_) => return Err(syntax_errors),
+        Err(errors) => return Err([syntax_errors, errors].concat()),
+    }
+    let symbols = declarations(&ast);
     let hir = Desugarer::run(&mut context, ast);
-    dbg!(&hir);
-    // let type_system = TypeSystem::new(&mut context);
-    // let typed_ast = type_system.run(ast)?;
-    // let ir = IR::new(&mut context);
-    // let bytes = ir.run(typed_ast);
-
-    Ok(())
-}
-
-
-fn write_out(source: &Source, bytes: &[u8]) -> AshResult<(), String> {
-    // let mut src_path = PathBuf::from(source.location());
-    // let mut path = src_path.parent()
-    //     .unwrap()
-    //     .to_path_buf();
-    // path.push("out");
-    // fs::create_dir(path).map_err(|e| vec![chumsky])?; 
-    
-    // src_path.set_extension("cash");
-    // path.push(src_path.file_name().unwrap());
-    
-    // let file = File::create(path).map_err(|e| e.to_string())?;
-    // file.write_all(bytes);
-
-    Ok(())
+    Typing::run(&mut context, &hir)?;
+
+    Ok(Analyzed { context, hir, symbols })
+}
+
+fn generate(
+    Analyzed { context, hir, symbols }: Analyzed,
+    codegen: fn(Vec<Spanned<hir::Stmt>>) -> AshResult<Chunk, String>,
+) -> AshResult<Chunk, String> {
+    let mut chunk = codegen(hir)?;
+    for (id, name) in symbols {
+        let local = context.get_local(id);
+        if let (Some(global), Some(ty)) = (local.mangle_name.clone(), &local.ty) {
+            let ty = match ty {
+                Ty::Fun(..) => "fun".to_owned(),
+                ty => ty.to_string(),
+            };
+            let symbol = Symbol {
+                global,
+                ty,
+                mutable: local.mutable,
+            };
+            chunk.add_symbol(name, symbol);
+        }
+    }
+
+    Ok(chunk)
+}
+
+/// Root declarations with their names as written in the source
+fn declarations(ast: &[Spanned<parser::Stmt>]) -> Symbols {
+    ast.iter()
+        .filter_map(|(stmt, _)| stmt.declaration())
+        .map(|(id, name)| (id, name.to_owned()))
+        .collect()
+}
+
+/// Compiles inputs of an interactive session.
+/// Globals declared by an input are visible to the following inputs once it is committed
+pub struct Session {
+    context: Context,
+    globals: Scope,
+    pending: Option<Scope>,
+    natives: Vec<NativeSignature>,
+}
+
+impl Session {
+    pub fn new(natives: Vec<NativeSignature>) -> Self {
+        Self {
+            context: Context::new("<repl>".to_owned()),
+            globals: Scope::default(),
+            pending: None,
+            natives,
+        }
+    }
+
+    /// Input can not be complete yet, because it has unclosed delimiters
+    pub fn is_incomplete(&self, input: &str) -> bool {
+        Lexer::new().is_unclosed(input)
+    }
+
+    /// Compiles input to a chunk returning the value of its trailing expression.
+    /// Returns the chunk and type of the value, `None` when input ends with a statement
+    pub fn compile(&mut self, source: &Source) -> AshResult<(Chunk, Option<String>), String> {
+        let (hir, ty, globals) = self.check(source)?;
+        let chunk = CodeGen::run_repl(hir)?;
+        self.pending = Some(globals);
+
+        Ok((chunk, ty))
+    }
+
+    /// Makes globals declared by the last compiled input visible to the following inputs
+    pub fn commit(&mut self) {
+        if let Some(globals) = self.pending.take() {
+            self.globals = globals;
+        }
+    }
+
+    /// Type of the trailing expression of the input, nothing is declared
+    pub fn type_of(&mut self, source: &Source) -> AshResult<Option<String>, String> {
+        let (_, ty, _) = self.check(source)?;
+        Ok(ty)
+    }
+
+    fn check(&mut self, source: &Source) -> AshResult<Checked, String> {
+        let ast = parse(source.inner(), 0).or_else(|errs| {
+            // Trailing expression does not need a semicolon
+            let code = source.inner().trim_end();
+            match code.ends_with(';') {
+                true => Err(errs),
+                false => parse(&format!("{code};"), 0).map_err(|_| errs),
+            }
+        })?;
+
+        let resolver = Resolver::new(&mut self.context, &self.natives);
+        let globals = resolver.run_repl(&ast, self.globals.clone())?;
+        let hir = Desugarer::run(&mut self.context, ast);
+        let ty = Typing::run(&mut self.context, &hir)?;
+
+        Ok((hir, ty.map(|ty| ty.to_string()), globals))
+    }
+}
+
+/// Desugared input, type of its trailing expression and globals it declares
+type Checked = (Vec<Spanned<hir::Stmt>>, Option<String>, Scope);
+
+
+/// Writes compiled chunk as `<out_dir>/<source name>.ashc`.
+/// Defaults to `out` directory next to the source
+pub fn write_out(source: &Source, chunk: &Chunk, out_dir: Option<&Path>) -> io::Result<PathBuf> {
+    let src_path = PathBuf::from(source.location());
+    let out_dir = match out_dir {
+        Some(out_dir) => out_dir.to_path_buf(),
+        None => src_path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join("out"),
+    };
+
+    let file_name = src_path
+        .file_name()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("script"));
+    let path = out_dir.join(file_name).with_extension(EXTENSION);
+    write_chunk(chunk, &path)?;
+
+    Ok(path)
+}
+
+/// Writes compiled chunk to `path`, parent directories are created
+pub fn write_chunk(chunk: &Chunk, path: &Path) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let bytes = chunk
+        .to_bytes()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    File::create(path)?.write_all(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use chumsky::error::SimpleReason;
+
+    use super::{build_library, check, parse_recovery};
+    use crate::core::{error_code, Source};
+
+    #[test]
+    fn lexer_errors_do_not_stop_parsing() {
+        let code = "\
+fun main() {
+    val a = 1u7;
+    val b = \"\\q\";
+    val c = 1 +;
+    val d = a # b;
+}
+";
+        let (ast, errors) = parse_recovery(code, 0);
+        assert_eq!(ast.len(), 1);
+
+        let errors = errors
+            .iter()
+            .map(|error| {
+                let msg = match error.reason() {
+                    SimpleReason::Custom(msg) => msg.clone(),
+                    _ => "syntax error".to_owned(),
+                };
+                (error_code(error), msg, &code[error.span()])
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            [
+                ("E0003", "Invalid suffix `u7` of number literal".to_owned(), "1u7"),
+                ("E0003", "Unknown escape sequence `\\q`".to_owned(), "q"),
+                ("E0001", "Unexpected character `#`".to_owned(), "#"),
+                ("E0001", "syntax error".to_owned(), ";"),
+            ]
+        );
+    }
+
+    #[test]
+    fn syntax_errors_of_every_statement_are_reported() {
+        let code = "\
+fun first() {
+    val a = 1 +;
+    val b = 2;
+    if b > 1 {
+        val c = ;
+    }
+}
+
+fun second() > i32 {
+    while true {
+        val d = (1 + 2;
+    }
+    return 1;
+}
+
+val e = 3 3;
+
+fun third() > i32 {
+    return missing;
+}
+";
+        let (ast, _) = parse_recovery(code, 0);
+        assert_eq!(ast.len(), 4);
+
+        let errors = check(&Source::from_string(code), &[]).unwrap_err();
+        let errors = errors
+            .iter()
+            .map(|error| {
+                let line = code[..error.span().start].lines().count();
+                (error_code(error), line, &code[error.span()])
+            })
+            .collect::<Vec<_>>();
+        // Code after the syntax errors is still resolved
+        assert_eq!(
+            errors,
+            [
+                ("E0001", 2, ";"),
+                ("E0001", 5, ";"),
+                ("E0001", 11, ";"),
+                ("E0001", 16, "3"),
+                ("E0200", 19, "missing"),
+            ]
+        );
+    }
+
+    #[test]
+    fn jumps_over_too_much_code_are_reported_at_their_statement() {
+        let params = (0..200).map(|i| format!("p{i}: i32")).collect::<Vec<_>>();
+        let call = format!("f({})", vec!["1"; 200].join(", "));
+        // Right operand of `&&` is far longer than a jump can skip
+        let code = format!(
+            "fun f({}) > i32 {{\n    return 0;\n}}\n\nval x = false && {} > 0;\n",
+            params.join(", "),
+            vec![call; 200].join(" + ")
+        );
+
+        let errors = build_library(&Source::from_string(code.as_str()), &[]).unwrap_err();
+        let errors = errors
+            .iter()
+            .map(|error| (error_code(error), code[error.span()].starts_with("val x = false &&")))
+            .collect::<Vec<_>>();
+        assert_eq!(errors, [("E0400", true)]);
+    }
 }
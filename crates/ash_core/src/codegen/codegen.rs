@@ -0,0 +1,496 @@
+use std::rc::Rc;
+
+use ash_bytecode::prelude::{self as bytecode, Chunk, OpCode};
+use chumsky::{prelude::Simple, Error as _};
+
+use crate::{
+    core::{error_code, Spanned},
+    hir::{Body, Expr, Stmt},
+    parser::{
+        operator::{BinaryOp, UnaryOp},
+        If, IfInner,
+    },
+    prelude::{AshResult, Span},
+    ty::{
+        function::{Function, ProtoFunction},
+        Ty, Value,
+    },
+};
+
+/// Function `ash run` calls once globals are defined
+const ENTRY_FUNCTION: &str = "main";
+
+struct Local {
+    name: String,
+    depth: usize,
+}
+
+struct BreakTarget {
+    // Amount of locals alive when entering the breakable statement
+    local_count: usize,
+    jmps: Vec<usize>,
+}
+
+#[derive(Default)]
+struct FunctionState {
+    chunk: Chunk,
+    // Name of a nested function, it is not a global so calls to itself load the callee
+    nested_name: Option<String>,
+    locals: Vec<Local>,
+    depth: usize,
+    breaks: Vec<BreakTarget>,
+}
+
+pub(crate) struct CodeGen {
+    functions: Vec<FunctionState>,
+    errors: Vec<Simple<String>>,
+}
+
+impl CodeGen {
+    /// Compiles program run by `ash`, it has to define the entry function
+    pub fn run(hir: Vec<Spanned<Stmt>>) -> AshResult<Chunk, String> {
+        let mut codegen = Self::new();
+        codegen.check_entry(&hir);
+        codegen.root(hir, false);
+        codegen.finish()
+    }
+
+    /// Compiles program which functions are called by a host, `main` is optional
+    pub fn run_library(hir: Vec<Spanned<Stmt>>) -> AshResult<Chunk, String> {
+        let mut codegen = Self::new();
+        codegen.root(hir, false);
+        codegen.finish()
+    }
+
+    /// Compiles interactive input, the chunk returns value of the trailing expression
+    pub fn run_repl(hir: Vec<Spanned<Stmt>>) -> AshResult<Chunk, String> {
+        let mut codegen = Self::new();
+        codegen.root(hir, true);
+        codegen.finish()
+    }
+
+    fn new() -> Self {
+        Self {
+            functions: vec![FunctionState::default()],
+            errors: Vec::new(),
+        }
+    }
+
+    fn finish(mut self) -> AshResult<Chunk, String> {
+        if !self.errors.is_empty() {
+            return Err(self.errors);
+        }
+
+        Ok(self.functions.pop().unwrap().chunk)
+    }
+
+    fn check_entry(&mut self, hir: &[Spanned<Stmt>]) {
+        let has_entry = hir.iter().any(|(stmt, _)| match stmt {
+            Stmt::Fun(fun) => fun.proto.0.name == ENTRY_FUNCTION,
+            _ => false,
+        });
+        if !has_entry {
+            self.new_error(format!("Function `{ENTRY_FUNCTION}` is not defined"), 0..0);
+        }
+    }
+
+    /// Top level chunk only defines globals, the VM calls entry function afterwards
+    fn root(&mut self, hir: Vec<Spanned<Stmt>>, returns_last: bool) {
+        // Functions are defined first so global initializers can call them
+        let (funs, mut stmts): (Vec<_>, Vec<_>) = hir
+            .into_iter()
+            .partition(|(stmt, _)| matches!(stmt, Stmt::Fun(_) | Stmt::Proto(_)));
+
+        let last = match stmts.last() {
+            Some((Stmt::Expr(_), _)) if returns_last => stmts.pop(),
+            _ => None,
+        };
+
+        self.multiple_stmt(funs);
+        self.multiple_stmt(stmts);
+        match last {
+            Some((Stmt::Expr(expr), span)) => {
+                self.chunk().mark_span(span.clone());
+                self.ret(Some((expr, span)));
+            }
+            _ => self.ret(None),
+        }
+    }
+
+    fn multiple_stmt(&mut self, stmts: Body) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, (stmt, span): Spanned<Stmt>) {
+        self.chunk().mark_span(span.clone());
+        match stmt {
+            Stmt::Fun(fun) => self.fun(*fun),
+            Stmt::Proto(proto) => self.proto(proto),
+            Stmt::DeclVar { name, value, .. } => self.decl_var(name, value, &span),
+            Stmt::StoreVar { name, value, .. } => self.store_var(name.0, value, &span),
+            Stmt::While(cond, body) => self.stmt_while(cond, body, span),
+            Stmt::If(data) => self.stmt_if(data, span),
+            Stmt::Block(body) => self.block(body),
+            Stmt::ExprBlock(body) => self.expr_block(body, span),
+            Stmt::Break => self.br(span),
+            Stmt::Ret(expr) => self.ret(expr.map(|expr| (expr, span))),
+            Stmt::Expr(expr) => {
+                self.expr(expr, &span);
+                self.chunk().add_instr(OpCode::Pop);
+            }
+        }
+    }
+
+    /// Expressions have no spans of their own, `span` is the one of the statement they are part of
+    fn expr(&mut self, expr: Expr, span: &Span) {
+        match expr {
+            Expr::LoadVar(_, name) => self.load_var(name),
+            Expr::Literal(value) => self.literal(value),
+            Expr::ToStr(expr) => {
+                self.expr(*expr, span);
+                self.chunk().add_instr(OpCode::ToStr);
+            }
+            Expr::Call { callee, args } => self.call(*callee, args, span),
+            Expr::Unary { op, right } => self.unary(op, *right, span),
+            Expr::Cast { expr, ty } => self.cast(*expr, ty, span),
+            Expr::Binary { left, op, right } => self.binary(*left, op, *right, span),
+        }
+    }
+
+    fn fun(&mut self, fun: Function<Body>) {
+        let is_void = fun.ret_ty() == Ty::Void;
+        let (proto, _) = fun.proto;
+        let (body, _) = fun.body;
+
+        let nested_name = (!self.is_root()).then(|| proto.name.clone());
+        self.functions.push(FunctionState {
+            nested_name,
+            ..FunctionState::default()
+        });
+        self.enter_scope();
+        for (_, name, _) in proto.params.iter() {
+            self.declare_local(name.clone());
+        }
+        let has_ret = matches!(body.last(), Some((stmt, _)) if stmt.is_ret());
+        self.multiple_stmt(body);
+        // The type checker rejects other functions not returning on every path
+        if is_void && !has_ret {
+            self.chunk().add_instr(OpCode::Void);
+            self.chunk().add_instr(OpCode::Ret);
+        }
+        let state = self.functions.pop().unwrap();
+
+        let function = bytecode::Function {
+            name: proto.name.clone(),
+            arity: proto.params.len() as u8,
+            chunk: state.chunk,
+        };
+        self.chunk()
+            .write_const(bytecode::Value::Function(Rc::new(function)));
+        self.define_var(proto.name);
+    }
+
+    fn proto(&mut self, proto: ProtoFunction) {
+        // Builtins are linked with native functions by name when loaded
+        let index = self.name_const(proto.name.clone());
+        self.chunk()
+            .add_instr_with_arg(OpCode::LoadNative, OpCode::LoadNativeLong, index);
+        self.define_var(proto.name);
+    }
+
+    fn decl_var(&mut self, name: String, value: Option<Expr>, span: &Span) {
+        match value {
+            Some(value) => self.expr(value, span),
+            // Temporary variables are initialized later
+            None => self.chunk().add_instr(OpCode::Void),
+        }
+        self.define_var(name);
+    }
+
+    fn store_var(&mut self, name: String, value: Expr, span: &Span) {
+        self.expr(value, span);
+        match self.resolve_local(&name) {
+            Some(slot) => {
+                self.chunk()
+                    .add_instr_with_arg(OpCode::StoreLocal, OpCode::StoreLocalLong, slot)
+            }
+            None => {
+                let index = self.name_const(name);
+                self.chunk()
+                    .add_instr_with_arg(OpCode::StoreGlobal, OpCode::StoreGlobalLong, index)
+            }
+        }
+    }
+
+    fn load_var(&mut self, name: String) {
+        match self.resolve_local(&name) {
+            Some(slot) => {
+                self.chunk()
+                    .add_instr_with_arg(OpCode::LoadLocal, OpCode::LoadLocalLong, slot)
+            }
+            // The resolver rejects any other local of enclosing functions
+            None if self.current().nested_name.as_ref() == Some(&name) => {
+                self.chunk().add_instr(OpCode::LoadCallee)
+            }
+            None => {
+                let index = self.name_const(name);
+                self.chunk()
+                    .add_instr_with_arg(OpCode::LoadGlobal, OpCode::LoadGlobalLong, index)
+            }
+        }
+    }
+
+    fn define_var(&mut self, name: String) {
+        if self.is_root() {
+            let index = self.name_const(name);
+            self.chunk()
+                .add_instr_with_arg(OpCode::DefGlobal, OpCode::DefGlobalLong, index);
+        } else {
+            // Value stays on the stack
+            self.declare_local(name);
+        }
+    }
+
+    fn stmt_while(&mut self, (cond, cond_span): Spanned<Expr>, body: Body, span: Span) {
+        let start = self.chunk().len();
+        self.chunk().mark_span(cond_span.clone());
+        self.expr(cond, &cond_span);
+        let exit_jmp = self.chunk().add_jmp(OpCode::JmpIfFalse);
+        self.chunk().add_instr(OpCode::Pop);
+
+        self.enter_break_target();
+        self.block(body);
+        if !self.chunk().add_loop(start) {
+            self.new_error("Loop body is too large", span.clone());
+        }
+
+        self.patch_jmp(exit_jmp, cond_span);
+        self.chunk().add_instr(OpCode::Pop);
+        self.leave_break_target(span);
+    }
+
+    fn stmt_if(&mut self, data: If<Expr, Stmt>, span: Span) {
+        let mut end_jmps = Vec::new();
+        let branches = std::iter::once(*data.then).chain(data.else_ifs);
+        for IfInner { condition, body } in branches {
+            let (cond, cond_span) = condition;
+            self.chunk().mark_span(cond_span.clone());
+            self.expr(cond, &cond_span);
+            let next_jmp = self.chunk().add_jmp(OpCode::JmpIfFalse);
+            self.chunk().add_instr(OpCode::Pop);
+            self.block(body);
+            end_jmps.push(self.chunk().add_jmp(OpCode::Jmp));
+
+            self.patch_jmp(next_jmp, cond_span);
+            self.chunk().add_instr(OpCode::Pop);
+        }
+
+        self.block(data.otherwise);
+        for jmp in end_jmps {
+            self.patch_jmp(jmp, span.clone());
+        }
+    }
+
+    fn block(&mut self, body: Body) {
+        self.enter_scope();
+        self.multiple_stmt(body);
+        self.leave_scope();
+    }
+
+    fn expr_block(&mut self, body: Body, span: Span) {
+        self.enter_break_target();
+        self.block(body);
+        self.leave_break_target(span);
+    }
+
+    fn br(&mut self, span: Span) {
+        let target = match self.current().breaks.last() {
+            Some(target) => target.local_count,
+            None => {
+                self.new_error("Nothing to break out of", span);
+                return;
+            }
+        };
+
+        // Locals of the scopes being left
+        for _ in target..self.current().locals.len() {
+            self.chunk().add_instr(OpCode::Pop);
+        }
+        let jmp = self.chunk().add_jmp(OpCode::Jmp);
+        self.current_mut().breaks.last_mut().unwrap().jmps.push(jmp);
+    }
+
+    fn ret(&mut self, expr: Option<Spanned<Expr>>) {
+        match expr {
+            Some((expr, span)) => self.expr(expr, &span),
+            None => self.chunk().add_instr(OpCode::Void),
+        }
+        self.chunk().add_instr(OpCode::Ret);
+    }
+
+    fn literal(&mut self, value: Value) {
+        let value = match value {
+            Value::Bool(true) => return self.chunk().add_instr(OpCode::True),
+            Value::Bool(false) => return self.chunk().add_instr(OpCode::False),
+            Value::String(v) => bytecode::Value::String(v),
+            Value::I8(v) => bytecode::Value::I8(v),
+            Value::I16(v) => bytecode::Value::I16(v),
+            Value::I32(v) => bytecode::Value::I32(v),
+            Value::I64(v) => bytecode::Value::I64(v),
+            Value::U8(v) => bytecode::Value::U8(v),
+            Value::U16(v) => bytecode::Value::U16(v),
+            Value::U32(v) => bytecode::Value::U32(v),
+            Value::U64(v) => bytecode::Value::U64(v),
+            Value::F32(v) => bytecode::Value::F32(v),
+            Value::F64(v) => bytecode::Value::F64(v),
+        };
+        self.chunk().write_const(value);
+    }
+
+    fn call(&mut self, callee: Expr, args: Vec<Expr>, span: &Span) {
+        let arg_count = args.len();
+        self.expr(callee, span);
+        for arg in args {
+            self.expr(arg, span);
+        }
+        self.chunk().add_instr(OpCode::Call);
+        self.chunk().write(arg_count as u8);
+    }
+
+    fn unary(&mut self, op: UnaryOp, right: Expr, span: &Span) {
+        self.expr(right, span);
+        let op = match op {
+            UnaryOp::Neg => OpCode::Neg,
+            UnaryOp::Not => OpCode::Not,
+            UnaryOp::BitNot => OpCode::BitNot,
+        };
+        self.chunk().add_instr(op);
+    }
+
+    fn cast(&mut self, expr: Expr, ty: Ty, span: &Span) {
+        self.expr(expr, span);
+        // Casts to other types are rejected by typing
+        let ty = ty.num_ty().expect("Cast to a number type");
+        self.chunk().add_instr(OpCode::Cast);
+        self.chunk().write(ty as u8);
+    }
+
+    fn binary(&mut self, left: Expr, op: BinaryOp, right: Expr, span: &Span) {
+        let op = match op {
+            BinaryOp::LogicAnd => return self.logic_and(left, right, span),
+            BinaryOp::LogicOr => return self.logic_or(left, right, span),
+            BinaryOp::Sum => OpCode::Sum,
+            BinaryOp::Sub => OpCode::Sub,
+            BinaryOp::Mul => OpCode::Mul,
+            BinaryOp::Div => OpCode::Div,
+            BinaryOp::Rem => OpCode::Rem,
+            BinaryOp::Equal => OpCode::Eq,
+            BinaryOp::NotEqual => OpCode::Neq,
+            BinaryOp::Gt => OpCode::Gt,
+            BinaryOp::Lt => OpCode::Lt,
+            BinaryOp::Gte => OpCode::Gte,
+            BinaryOp::Lte => OpCode::Lte,
+            BinaryOp::BitAnd => OpCode::BitAnd,
+            BinaryOp::BitOr => OpCode::BitOr,
+            BinaryOp::BitXor => OpCode::BitXor,
+            BinaryOp::Shl => OpCode::Shl,
+            BinaryOp::Shr => OpCode::Shr,
+        };
+
+        self.expr(left, span);
+        self.expr(right, span);
+        self.chunk().add_instr(op);
+    }
+
+    fn logic_and(&mut self, left: Expr, right: Expr, span: &Span) {
+        self.expr(left, span);
+        let end_jmp = self.chunk().add_jmp(OpCode::JmpIfFalse);
+        self.chunk().add_instr(OpCode::Pop);
+        self.expr(right, span);
+        self.patch_jmp(end_jmp, span.clone());
+    }
+
+    fn logic_or(&mut self, left: Expr, right: Expr, span: &Span) {
+        self.expr(left, span);
+        let else_jmp = self.chunk().add_jmp(OpCode::JmpIfFalse);
+        let end_jmp = self.chunk().add_jmp(OpCode::Jmp);
+        self.patch_jmp(else_jmp, span.clone());
+        self.chunk().add_instr(OpCode::Pop);
+        self.expr(right, span);
+        self.patch_jmp(end_jmp, span.clone());
+    }
+
+    fn enter_scope(&mut self) {
+        self.current_mut().depth += 1;
+    }
+
+    fn leave_scope(&mut self) {
+        let state = self.current_mut();
+        state.depth -= 1;
+        while matches!(state.locals.last(), Some(local) if local.depth > state.depth) {
+            state.locals.pop();
+            state.chunk.add_instr(OpCode::Pop);
+        }
+    }
+
+    fn enter_break_target(&mut self) {
+        let local_count = self.current().locals.len();
+        self.current_mut().breaks.push(BreakTarget {
+            local_count,
+            jmps: Vec::new(),
+        });
+    }
+
+    fn leave_break_target(&mut self, span: Span) {
+        let target = self.current_mut().breaks.pop().unwrap();
+        for jmp in target.jmps {
+            self.patch_jmp(jmp, span.clone());
+        }
+    }
+
+    fn declare_local(&mut self, name: String) {
+        let state = self.current_mut();
+        let depth = state.depth;
+        state.locals.push(Local { name, depth });
+    }
+
+    fn resolve_local(&self, name: &str) -> Option<usize> {
+        self.current()
+            .locals
+            .iter()
+            .rposition(|local| local.name == name)
+    }
+
+    fn name_const(&mut self, name: String) -> usize {
+        self.chunk().add_const(bytecode::Value::String(name))
+    }
+
+    fn patch_jmp(&mut self, offset: usize, span: Span) {
+        if !self.chunk().patch_jmp(offset) {
+            self.new_error("Too much code to jump over", span);
+        }
+    }
+
+    /// Declarations made here are globals
+    fn is_root(&self) -> bool {
+        self.functions.len() == 1 && self.current().depth == 0
+    }
+
+    fn current(&self) -> &FunctionState {
+        self.functions.last().unwrap()
+    }
+
+    fn current_mut(&mut self) -> &mut FunctionState {
+        self.functions.last_mut().unwrap()
+    }
+
+    fn chunk(&mut self) -> &mut Chunk {
+        &mut self.current_mut().chunk
+    }
+
+    fn new_error<S: ToString>(&mut self, err_msg: S, span: Span) {
+        self.errors.push(Simple::custom(span, err_msg).with_label(error_code::CODEGEN));
+    }
+}
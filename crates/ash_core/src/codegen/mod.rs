@@ -0,0 +1,3 @@
+pub(crate) mod codegen;
+
+pub(crate) use codegen::*;
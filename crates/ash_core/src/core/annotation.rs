@@ -12,6 +12,10 @@ impl Annotation {
         }
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub fn is_builtin(&self) -> bool {
         self.name == Self::BUILT_IN_NAME
     }
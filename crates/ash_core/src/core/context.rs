@@ -1,6 +1,6 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use crate::{parser::Expr, ty::Ty};
+use crate::{parser::Expr, prelude::Span, ty::Ty};
 
 use super::{Env, Id};
 
@@ -9,6 +9,10 @@ pub struct Context {
     location: String,
     locals: HashMap<Id, Local>,
     var_nodes: HashMap<Id, VarNode>,
+    // Where declarations and references to them are in the source
+    sites: HashMap<Id, Span>,
+    // Declarations made by `fun`
+    functions: HashSet<Id>,
 }
 
 #[derive(Debug, Clone)]
@@ -49,6 +53,8 @@ impl Context {
             location,
             locals,
             var_nodes: HashMap::new(),
+            sites: HashMap::new(),
+            functions: HashSet::new(),
         }
     }
 
@@ -82,21 +88,32 @@ impl Context {
         self.locals.get_mut(&id).unwrap()
     }
 
-    pub(crate) fn var_data(&self, id: Id) -> Option<Local> {
-        let local = self.locals.get(&id)?;
-        if local.ty.is_none() {
-            let points_to = local.points_to?;
-            if id != points_to {
-                return self.var_data(points_to);
-            } else {
-                unreachable!()
-            }
+    /// Id of the declaration `id` refers to
+    pub(crate) fn decl_id(&self, id: Id) -> Id {
+        match self.locals.get(&id).and_then(|local| local.points_to) {
+            Some(points_to) if points_to != id => self.decl_id(points_to),
+            _ => id,
         }
+    }
 
-        Some(local.clone())
+    /// Type of the declaration `id` refers to, `None` until it is known
+    pub(crate) fn ty_of(&self, id: Id) -> Option<Ty> {
+        self.locals.get(&self.decl_id(id))?.ty.clone()
+    }
+
+    pub(crate) fn set_ty(&mut self, id: Id, ty: Ty) {
+        let id = self.decl_id(id);
+        if let Some(local) = self.locals.get_mut(&id) {
+            local.ty = Some(ty);
+        }
     }
 
-    pub(crate) fn new_var(&mut self, id: Id, name: String, ty: Option<Ty>) {
+    /// Name of the declaration `id` refers to, as written in the source
+    pub(crate) fn name_of(&self, id: Id) -> Option<&str> {
+        self.locals.get(&self.decl_id(id))?.name.as_deref()
+    }
+
+    pub(crate) fn new_var(&mut self, id: Id, name: String, ty: Option<Ty>, mutable: bool) {
         self.locals.insert(
             id,
             Local {
@@ -104,7 +121,7 @@ impl Context {
                 name: Some(name.clone()),
                 mangle_name: Some(format!("__{name}{id}")),
                 ty,
-                mutable: true,
+                mutable,
                 points_to: None,
             },
         );
@@ -129,7 +146,14 @@ impl Context {
         Vec::new()
     }
 
-    pub(crate) fn resolve_new_var(&mut self, id: Id, name: String, value: Expr, deps: Vec<Id>) {
+    pub(crate) fn resolve_new_var(
+        &mut self,
+        id: Id,
+        name: String,
+        value: Expr,
+        deps: Vec<Id>,
+        mutable: bool,
+    ) {
         self.var_nodes.insert(
             id,
             VarNode {
@@ -147,7 +171,7 @@ impl Context {
                 mangle_name: Some(format!("__{name}{id}")),
                 ty: None,
                 points_to: None,
-                mutable: true,
+                mutable,
             },
         );
     }
@@ -168,6 +192,22 @@ impl Context {
         );
     }
 
+    pub(crate) fn add_site(&mut self, id: Id, span: Span) {
+        self.sites.insert(id, span);
+    }
+
+    pub(crate) fn sites(&self) -> impl Iterator<Item = (Id, &Span)> {
+        self.sites.iter().map(|(id, span)| (*id, span))
+    }
+
+    pub(crate) fn mark_function(&mut self, id: Id) {
+        self.functions.insert(id);
+    }
+
+    pub(crate) fn is_function(&self, id: Id) -> bool {
+        self.functions.contains(&id)
+    }
+
     pub fn location(&self) -> &str {
         &self.location
     }
@@ -0,0 +1,22 @@
+use std::hash::Hash;
+
+use chumsky::{error::SimpleReason, prelude::Simple};
+
+// Custom errors carry the code of the stage reporting them as their label
+pub(crate) const UNKNOWN: &str = "E0000";
+pub(crate) const UNEXPECTED_TOKEN: &str = "E0001";
+pub(crate) const UNCLOSED_DELIMITER: &str = "E0002";
+pub(crate) const INVALID_LITERAL: &str = "E0003";
+pub(crate) const IMPORT: &str = "E0100";
+pub(crate) const RESOLVE: &str = "E0200";
+pub(crate) const TYPE: &str = "E0300";
+pub(crate) const CODEGEN: &str = "E0400";
+
+/// Stable code identifying the kind of the error
+pub fn error_code<T: Hash + Eq>(err: &Simple<T>) -> &'static str {
+    match err.reason() {
+        SimpleReason::Unexpected => UNEXPECTED_TOKEN,
+        SimpleReason::Unclosed { .. } => UNCLOSED_DELIMITER,
+        SimpleReason::Custom(_) => err.label().unwrap_or(UNKNOWN),
+    }
+}
@@ -4,12 +4,14 @@ pub use annotation::*;
 use chumsky::error::Simple;
 pub use context::*;
 pub use env::*;
+pub use error_code::error_code;
 pub use id::*;
 pub use source::*;
 
 pub mod annotation;
 pub mod context;
 pub mod env;
+pub mod error_code;
 pub mod id;
 pub mod source;
 
@@ -1,11 +1,6 @@
 use std::path::PathBuf;
 use std::{fs, io};
 
-use once_cell::sync::OnceCell;
-use regex::Regex;
-
-static COMMENT_REGEX: OnceCell<Regex> = OnceCell::new();
-
 pub struct Source {
     location: Option<String>,
     inner: String,
@@ -31,6 +26,11 @@ impl Source {
         src.prepare()
     }
 
+    pub fn with_location<S: Into<String>>(mut self, location: S) -> Self {
+        self.location = Some(location.into());
+        self
+    }
+
     pub fn inner(&self) -> &str {
         return &self.inner;
     }
@@ -43,13 +43,49 @@ impl Source {
             .to_owned();
     }
 
+    /// Comments are kept, the lexer skips them, so spans match the original code
     fn prepare(mut self) -> Self {
-        let comments = COMMENT_REGEX.get_or_init(|| Regex::new(r"(//.*)|(/\*(\n|.)*\*/)").unwrap());
-
         // Hack for making sure dedent is detected
         self.inner += "\n";
-        self.inner = comments.replace_all(&self.inner, "").to_string();
 
         self
     }
 }
+
+/// Sources compiled together, each one is a module named by its path.
+/// Each source starts where the previous one ends, so every span points into exactly one of them
+#[derive(Default)]
+pub struct SourceMap {
+    sources: Vec<(usize, String, Source)>,
+    len: usize,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds source of module `module`, e.g. `util.math`. Returns offset of the added source
+    pub fn add<S: Into<String>>(&mut self, module: S, source: Source) -> usize {
+        let offset = self.len;
+        self.len += source.inner().chars().count();
+        self.sources.push((offset, module.into(), source));
+        offset
+    }
+
+    /// Offsets, module names and sources in the order they were added
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &str, &Source)> {
+        self.sources
+            .iter()
+            .map(|(offset, module, source)| (*offset, module.as_str(), source))
+    }
+
+    /// Source containing `offset` together with the offset it starts at
+    pub fn locate(&self, offset: usize) -> Option<(usize, &Source)> {
+        self.sources
+            .iter()
+            .rev()
+            .find(|(start, _, _)| *start <= offset)
+            .map(|(start, _, source)| (*start, source))
+    }
+}
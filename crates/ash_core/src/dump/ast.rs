@@ -0,0 +1,437 @@
+use crate::core::{Context, Id, Span, Spanned};
+use crate::format::Layout;
+use crate::lexer::string::escape;
+use crate::parser::{Expr, If, Stmt, StrPart};
+use crate::ty::{function::ProtoFunction, Ty, Value};
+
+use super::Printer;
+
+/// Prints AST as source code. With a context every name is followed by
+/// `#id` of the declaration it resolves to. With layout comments and
+/// blank lines of the original code are printed too
+pub(crate) struct AstPrinter<'a> {
+    printer: Printer,
+    context: Option<&'a Context>,
+    layout: Option<Layout>,
+    // Position in the original code after the last printed item
+    cursor: usize,
+    // Nothing is printed in the current block yet
+    block_start: bool,
+    // `pub` of annotated declarations comes before the annotation
+    annotated: bool,
+}
+
+impl<'a> AstPrinter<'a> {
+    pub fn new(context: Option<&'a Context>) -> Self {
+        Self {
+            printer: Printer::default(),
+            context,
+            layout: None,
+            cursor: 0,
+            block_start: true,
+            annotated: false,
+        }
+    }
+
+    pub fn with_layout(mut self, layout: Layout) -> Self {
+        self.layout = Some(layout);
+        self
+    }
+
+    pub fn print(mut self, ast: &[Spanned<Stmt>]) -> String {
+        self.stmts(ast);
+        self.comments_before(usize::MAX);
+        self.printer.finish()
+    }
+
+    fn stmts(&mut self, stmts: &[Spanned<Stmt>]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, (stmt, span): &Spanned<Stmt>) {
+        self.comments_before(span.start);
+        self.separate(span.start);
+        match stmt {
+            Stmt::Annotation((annotation, annotation_span), stmt) => {
+                if stmt.0.is_public() {
+                    self.write("pub ");
+                }
+                self.write(format!("@[{}]", annotation.name()));
+                self.end_line();
+                self.cursor = self.cursor.max(annotation_span.end);
+                self.annotated = true;
+                self.stmt(stmt);
+                self.annotated = false;
+                return;
+            }
+            Stmt::ProtoFunction(proto) => self.proto(proto),
+            Stmt::Function(fun) => {
+                self.proto(&fun.proto.0);
+                match &fun.body.0 {
+                    Stmt::Block(stmts) => {
+                        self.write(" ");
+                        self.block(stmts);
+                    }
+                    body => {
+                        self.write(" => ");
+                        if let Stmt::Expression(expr) = body {
+                            self.expr(expr);
+                        }
+                        self.write(";");
+                    }
+                }
+            }
+            Stmt::If(data) => self.r#if(data),
+            Stmt::While((cond, _), body) => {
+                self.write("while ");
+                self.expr(cond);
+                self.write(" ");
+                self.block(body);
+            }
+            Stmt::VariableDecl {
+                id,
+                name,
+                ty,
+                value,
+                mutable,
+                public,
+            } => {
+                if *public {
+                    self.write("pub ");
+                }
+                self.write(if *mutable { "var " } else { "val " });
+                self.decl_name(*id, name);
+                if let Some(ty) = ty {
+                    self.write(format!(": {ty}"));
+                }
+                self.write(" = ");
+                self.expr(value);
+                self.write(";");
+            }
+            Stmt::VariableAssign { id, name, value } => {
+                self.name(*id, &name.0);
+                self.write(" = ");
+                self.expr(value);
+                self.write(";");
+            }
+            Stmt::Block(stmts) => self.block(stmts),
+            Stmt::Break(expr) => self.keyword_expr("break", expr.as_ref()),
+            Stmt::Return(expr) => self.keyword_expr("return", expr.as_ref()),
+            Stmt::Import(import) => {
+                self.write(format!("import {}", import.module_name()));
+                if let Some(items) = &import.items {
+                    let items = items
+                        .iter()
+                        .map(|(item, _)| item.as_str())
+                        .collect::<Vec<_>>();
+                    self.write(format!(".{{{}}}", items.join(", ")));
+                }
+                self.write(";");
+            }
+            Stmt::Expression(expr) => {
+                self.expr(expr);
+                self.write(";");
+            }
+            Stmt::Error => unreachable!("Code with syntax errors is not printed"),
+        }
+        self.cursor = self.cursor.max(span.end);
+        self.comment_after();
+        self.end_line();
+    }
+
+    fn proto(&mut self, proto: &ProtoFunction) {
+        if proto.public && !self.annotated {
+            self.write("pub ");
+        }
+        self.write("fun ");
+        self.decl_name(proto.id, &proto.name);
+        self.write("(");
+        for (i, (id, name, ty)) in proto.params.iter().enumerate() {
+            if i > 0 {
+                self.write(", ");
+            }
+            self.decl_name(*id, name);
+            self.write(format!(": {ty}"));
+        }
+        self.write(")");
+        match proto.ty.fun_return_ty() {
+            Ty::Void => {}
+            ty => self.write(format!(" > {ty}")),
+        }
+    }
+
+    fn r#if(&mut self, data: &If<Expr, Stmt>) {
+        for (i, inner) in [&*data.then].into_iter().chain(&data.else_ifs).enumerate() {
+            self.write(if i == 0 { "if " } else { " else if " });
+            self.expr(&inner.condition.0);
+            self.write(" ");
+            self.block(&inner.body);
+        }
+        if !data.otherwise.is_empty() || self.has_commented_else() {
+            self.write(" else ");
+            self.block(&data.otherwise);
+        }
+    }
+
+    fn keyword_expr(&mut self, keyword: &str, expr: Option<&Expr>) {
+        self.write(keyword);
+        if let Some(expr) = expr {
+            self.write(" ");
+            self.expr(expr);
+        }
+        self.write(";");
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        let leaf = match expr {
+            Expr::Variable(_, (_, span)) | Expr::Path(_, (_, span)) | Expr::Literal(_, span) => {
+                Some(span)
+            }
+            _ => None,
+        };
+        if let Some(span) = leaf {
+            self.comments_inline(span.start);
+        }
+
+        match expr {
+            Expr::Variable(id, (name, _)) => self.name(*id, name),
+            Expr::Path(id, (path, _)) => self.name(*id, &path.join(".")),
+            Expr::Literal(value, span) if value.ty().is_number() => self.number(value, span),
+            Expr::Literal(Value::String(text), span) => {
+                self.write("\"");
+                self.text(text, span);
+                self.write("\"");
+            }
+            Expr::Literal(value, _) => self.write(value.to_string()),
+            Expr::Interpolated(parts) => {
+                self.write("\"");
+                for part in parts {
+                    match part {
+                        StrPart::Text(text, span) => self.text(text, span),
+                        StrPart::Expr(expr) => {
+                            self.write("${");
+                            self.expr(expr);
+                            self.write("}");
+                        }
+                    }
+                }
+                self.write("\"");
+            }
+            Expr::Call { callee, args } => {
+                self.expr(callee);
+                self.write("(");
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        self.write(", ");
+                    }
+                    self.expr(arg);
+                }
+                self.write(")");
+            }
+            Expr::Block(stmts) => self.block(stmts),
+            Expr::If(data) => self.r#if(data),
+            Expr::Group(expr) => {
+                self.write("(");
+                self.expr(expr);
+                self.write(")");
+            }
+            Expr::Unary { op, right } => {
+                self.write(op.to_string());
+                self.expr(right);
+            }
+            Expr::Cast { expr, ty } => {
+                self.expr(expr);
+                self.space();
+                self.write(format!("as {ty}"));
+            }
+            Expr::Binary { left, op, right } => {
+                self.expr(left);
+                self.space();
+                self.write(format!("{op} "));
+                self.expr(right);
+            }
+            Expr::Error => unreachable!("Code with syntax errors is not printed"),
+        }
+
+        if let Some(span) = leaf {
+            self.cursor = self.cursor.max(span.end);
+            self.comments_trailing(span.end);
+        }
+    }
+
+    fn block(&mut self, stmts: &[Spanned<Stmt>]) {
+        if let Some(open) = self.brace('{') {
+            self.cursor = open + 1;
+        }
+        if stmts.is_empty() && !self.has_comment_before('}') {
+            self.write("{}");
+            if let Some(close) = self.brace('}') {
+                self.cursor = close + 1;
+            }
+            return;
+        }
+
+        self.printer.open_block();
+        self.block_start = true;
+        self.stmts(stmts);
+        let close = self.brace('}');
+        if let Some(close) = close {
+            self.comments_before(close);
+            self.cursor = close + 1;
+        }
+        self.printer.close_block();
+        self.block_start = false;
+    }
+
+    /// Numbers are written as in the original code, so their base and separators are kept
+    fn number(&mut self, value: &Value, span: &Span) {
+        match self.layout.as_ref().and_then(|layout| layout.literal(span)) {
+            Some(text) => {
+                // Sign of negative literals is a separate token
+                let text = match value.is_negative() {
+                    true => format!("-{text}"),
+                    false => text.to_owned(),
+                };
+                self.write(text);
+            }
+            None => self.write(value.to_string()),
+        }
+    }
+
+    /// Text of strings is written as in the original code, so its escape sequences are kept
+    fn text(&mut self, text: &str, span: &Span) {
+        let text = match self.layout.as_ref().and_then(|layout| layout.literal(span)) {
+            Some(raw) => raw.to_owned(),
+            None => escape(text),
+        };
+        self.write(text);
+    }
+
+    /// Position of the next `brace` in the original code
+    fn brace(&self, brace: char) -> Option<usize> {
+        self.layout.as_ref()?.brace(brace, self.cursor)
+    }
+
+    fn has_comment_before(&self, brace: char) -> bool {
+        self.layout.as_ref().is_some_and(|layout| {
+            let pos = layout.brace(brace, self.cursor).unwrap_or(usize::MAX);
+            layout.has_comment_before(pos)
+        })
+    }
+
+    /// Original code has an empty `else` block with comments in it
+    fn has_commented_else(&self) -> bool {
+        self.layout
+            .as_ref()
+            .is_some_and(|layout| layout.is_else(self.cursor))
+            && self.has_comment_before('}')
+    }
+
+    /// Prints comments before `pos` on their own lines
+    fn comments_before(&mut self, pos: usize) {
+        while let Some((comment, span)) = self
+            .layout
+            .as_mut()
+            .and_then(|layout| layout.comment_before(pos))
+        {
+            self.separate(span.start);
+            self.write(comment);
+            self.end_line();
+            self.cursor = span.end;
+        }
+    }
+
+    /// Prints comments before `pos` inside an expression. Code after a line
+    /// comment continues on the next line, indented one level deeper
+    fn comments_inline(&mut self, pos: usize) {
+        while let Some((comment, span)) = self
+            .layout
+            .as_mut()
+            .and_then(|layout| layout.comment_before(pos))
+        {
+            if comment.starts_with("//") {
+                if !self.printer.line_start && !self.printer.out.ends_with(' ') {
+                    self.write(" ");
+                }
+                self.write(comment);
+                self.end_line();
+                self.write(Printer::INDENT);
+            } else {
+                self.write(format!("{comment} "));
+            }
+            self.cursor = span.end;
+        }
+    }
+
+    /// Prints comments following the token which ends at `pos` inside an expression,
+    /// so they stay before the operator or the delimiter after it
+    fn comments_trailing(&mut self, pos: usize) {
+        while let Some((comment, span)) = self
+            .layout
+            .as_mut()
+            .and_then(|layout| layout.comment_after(pos))
+        {
+            self.write(format!(" {comment}"));
+            if comment.starts_with("//") {
+                self.end_line();
+                self.write(Printer::INDENT);
+            }
+            self.cursor = span.end;
+        }
+    }
+
+    /// Prints comments on the same line as the last printed item
+    fn comment_after(&mut self) {
+        while let Some((comment, span)) = self
+            .layout
+            .as_mut()
+            .and_then(|layout| layout.comment_after(self.cursor))
+        {
+            self.write(format!(" {comment}"));
+            self.cursor = span.end;
+        }
+    }
+
+    /// Keeps an empty line before `pos` if the original code has one
+    fn separate(&mut self, pos: usize) {
+        let blank = self
+            .layout
+            .as_ref()
+            .is_some_and(|layout| layout.has_blank_line(self.cursor..pos));
+        if blank && !self.block_start {
+            self.end_line();
+        }
+        self.block_start = false;
+    }
+
+    fn decl_name(&mut self, id: Id, name: &str) {
+        match self.context {
+            Some(_) => self.write(format!("{name}#{id}")),
+            None => self.write(name),
+        }
+    }
+
+    fn name(&mut self, id: Id, name: &str) {
+        match self.context {
+            Some(context) => self.write(format!("{name}#{}", context.decl_id(id))),
+            None => self.write(name),
+        }
+    }
+
+    /// Separates the next item unless a comment ended the line before it
+    fn space(&mut self) {
+        if !self.printer.out.ends_with(' ') {
+            self.write(" ");
+        }
+    }
+
+    fn write<S: AsRef<str>>(&mut self, text: S) {
+        self.printer.write(text)
+    }
+
+    fn end_line(&mut self) {
+        self.printer.end_line()
+    }
+}
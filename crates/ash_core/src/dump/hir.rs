@@ -0,0 +1,176 @@
+use crate::core::{Context, Spanned};
+use crate::hir::hir::{Body, Expr, Stmt};
+use crate::parser::If;
+use crate::ty::{function::ProtoFunction, Ty};
+
+use super::Printer;
+
+/// Prints desugared code with mangled names. With a context
+/// variables are annotated with their inferred types
+pub(super) struct HirPrinter<'a> {
+    printer: Printer,
+    context: Option<&'a Context>,
+}
+
+impl<'a> HirPrinter<'a> {
+    pub fn new(context: Option<&'a Context>) -> Self {
+        Self {
+            printer: Printer::default(),
+            context,
+        }
+    }
+
+    pub fn print(mut self, hir: &[Spanned<Stmt>]) -> String {
+        self.stmts(hir);
+        self.printer.finish()
+    }
+
+    fn stmts(&mut self, stmts: &[Spanned<Stmt>]) {
+        for (stmt, _) in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Fun(fun) => {
+                self.proto(&fun.proto.0);
+                self.write(" ");
+                self.block(&fun.body.0);
+            }
+            Stmt::Proto(proto) => self.proto(proto),
+            Stmt::DeclVar { id, name, ty, value } => {
+                self.write(format!("var {name}"));
+                let ty = match self.context {
+                    Some(context) => context.ty_of(*id),
+                    None => ty.clone(),
+                };
+                if let Some(ty) = ty {
+                    self.write(format!(": {ty}"));
+                }
+                if let Some(value) = value {
+                    self.write(" = ");
+                    self.expr(value);
+                }
+                self.write(";");
+            }
+            Stmt::StoreVar { name, value, .. } => {
+                self.write(format!("{} = ", name.0));
+                self.expr(value);
+                self.write(";");
+            }
+            Stmt::While((cond, _), body) => {
+                self.write("while ");
+                self.expr(cond);
+                self.write(" ");
+                self.block(body);
+            }
+            Stmt::If(data) => self.r#if(data),
+            Stmt::Block(body) => self.block(body),
+            Stmt::ExprBlock(body) => {
+                // `break` jumps to the end of this block
+                self.write("expr_block ");
+                self.block(body);
+            }
+            Stmt::Break => self.write("break;"),
+            Stmt::Ret(expr) => {
+                self.write("return");
+                if let Some(expr) = expr {
+                    self.write(" ");
+                    self.expr(expr);
+                }
+                self.write(";");
+            }
+            Stmt::Expr(expr) => {
+                self.expr(expr);
+                self.write(";");
+            }
+        }
+        self.printer.end_line();
+    }
+
+    fn proto(&mut self, proto: &ProtoFunction) {
+        let params = proto
+            .params
+            .iter()
+            .map(|(_, name, ty)| format!("{name}: {ty}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.write(format!("fun {}({params})", proto.name));
+        match proto.ty.fun_return_ty() {
+            Ty::Void => {}
+            ty => self.write(format!(" > {ty}")),
+        }
+    }
+
+    fn r#if(&mut self, data: &If<Expr, Stmt>) {
+        for (i, inner) in [&*data.then].into_iter().chain(&data.else_ifs).enumerate() {
+            self.write(if i == 0 { "if " } else { " else if " });
+            self.expr(&inner.condition.0);
+            self.write(" ");
+            self.block(&inner.body);
+        }
+        if !data.otherwise.is_empty() {
+            self.write(" else ");
+            self.block(&data.otherwise);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::LoadVar(_, name) => self.write(name),
+            Expr::Literal(value) => self.write(value.to_string()),
+            Expr::ToStr(expr) => {
+                self.write("to_str(");
+                self.expr(expr);
+                self.write(")");
+            }
+            Expr::Call { callee, args } => {
+                self.expr(callee);
+                self.write("(");
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        self.write(", ");
+                    }
+                    self.expr(arg);
+                }
+                self.write(")");
+            }
+            Expr::Unary { op, right } => {
+                self.write(op.to_string());
+                self.operand(right);
+            }
+            Expr::Cast { expr, ty } => {
+                self.operand(expr);
+                self.write(format!(" as {ty}"));
+            }
+            Expr::Binary { left, op, right } => {
+                self.operand(left);
+                self.write(format!(" {op} "));
+                self.operand(right);
+            }
+        }
+    }
+
+    /// HIR has no groups, nested operations are parenthesized to keep the order visible
+    fn operand(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Binary { .. } | Expr::Cast { .. } => {
+                self.write("(");
+                self.expr(expr);
+                self.write(")");
+            }
+            _ => self.expr(expr),
+        }
+    }
+
+    fn block(&mut self, body: &Body) {
+        self.printer.open_block();
+        self.stmts(body);
+        self.printer.close_block();
+    }
+
+    fn write<S: AsRef<str>>(&mut self, text: S) {
+        self.printer.write(text)
+    }
+}
@@ -0,0 +1,180 @@
+use std::{fmt, str::FromStr};
+
+use ash_bytecode::prelude::NativeSignature;
+
+use crate::ashery::parse;
+use crate::codegen::CodeGen;
+use crate::core::{AshResult, Context, Source, StringError};
+use crate::hir::Desugarer;
+use crate::lexer::{token::Token, Lexer};
+use crate::resolver::Resolver;
+use crate::ty::Typing;
+
+use self::{ast::AstPrinter, hir::HirPrinter};
+
+pub(crate) mod ast;
+mod hir;
+
+/// Intermediate representation produced by one of the compiler stages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Tokens,
+    Ast,
+    // Ast with every name marked by id of its declaration
+    Resolved,
+    Hir,
+    // Hir with inferred types of variables
+    Typed,
+    Bytecode,
+}
+
+impl Stage {
+    const ALL: [Stage; 6] = [
+        Self::Tokens,
+        Self::Ast,
+        Self::Resolved,
+        Self::Hir,
+        Self::Typed,
+        Self::Bytecode,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Tokens => "tokens",
+            Self::Ast => "ast",
+            Self::Resolved => "resolved",
+            Self::Hir => "hir",
+            Self::Typed => "typed",
+            Self::Bytecode => "bytecode",
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Stage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|stage| stage.name() == s)
+            .ok_or_else(|| {
+                let stages = Self::ALL.map(|stage| stage.name()).join(", ");
+                format!("Unknown stage `{s}`, expected one of {stages}")
+            })
+    }
+}
+
+/// Runs compiler up to `stage` and pretty prints its output
+pub fn dump(source: &Source, natives: &[NativeSignature], stage: Stage) -> AshResult<String, String> {
+    if stage == Stage::Tokens {
+        let tokens = Lexer::new().scan(source.inner()).string_err()?;
+        return Ok(dump_tokens(source.inner(), &tokens));
+    }
+
+    let ast = parse(source.inner(), 0)?;
+    if stage == Stage::Ast {
+        return Ok(AstPrinter::new(None).print(&ast));
+    }
+
+    let mut context = Context::new(source.location());
+    Resolver::new(&mut context, natives).run(&ast)?;
+    if stage == Stage::Resolved {
+        return Ok(AstPrinter::new(Some(&context)).print(&ast));
+    }
+
+    let hir = Desugarer::run(&mut context, ast);
+    if stage == Stage::Hir {
+        return Ok(HirPrinter::new(None).print(&hir));
+    }
+
+    Typing::run(&mut context, &hir)?;
+    if stage == Stage::Typed {
+        return Ok(HirPrinter::new(Some(&context)).print(&hir));
+    }
+
+    // Modules of a project are dumped too, they do not define `main`
+    let chunk = CodeGen::run_library(hir)?;
+    let mut out = Vec::new();
+    // Writing to a vector can not fail
+    let _ = chunk.disassemble("script", &mut out);
+
+    Ok(String::from_utf8_lossy(&out).into_owned())
+}
+
+/// One token per line prefixed by its position
+fn dump_tokens(source: &str, tokens: &[(Token, std::ops::Range<usize>)]) -> String {
+    let mut out = String::new();
+    let (mut line, mut col, mut offset) = (1, 1, 0);
+    let mut chars = source.chars();
+    for (token, span) in tokens {
+        for c in chars.by_ref().take(span.start - offset) {
+            match c {
+                '\n' => (line, col) = (line + 1, 1),
+                _ => col += 1,
+            }
+        }
+        offset = span.start;
+
+        let pos = format!("{line}:{col}");
+        let token = match token {
+            Token::Identifier { value, .. } => format!("identifier {value}"),
+            Token::Str(text) => format!("str {:?}", text.value),
+            Token::StrStart(text) => format!("str start {:?}", text.value),
+            Token::StrMiddle(text) => format!("str middle {:?}", text.value),
+            Token::StrEnd(text) => format!("str end {:?}", text.value),
+            Token::Int(v) | Token::Float(v) => format!("{token} {v}"),
+            Token::Bool(v) => format!("bool {v}"),
+            _ => token.to_string(),
+        };
+        out += &format!("{pos:<8}{token}\n");
+    }
+
+    out
+}
+
+/// Writes indented lines
+#[derive(Default)]
+struct Printer {
+    out: String,
+    indent: usize,
+    line_start: bool,
+}
+
+impl Printer {
+    const INDENT: &'static str = "    ";
+
+    fn write<S: AsRef<str>>(&mut self, text: S) {
+        if self.line_start {
+            self.out += &Self::INDENT.repeat(self.indent);
+            self.line_start = false;
+        }
+        self.out += text.as_ref();
+    }
+
+    fn end_line(&mut self) {
+        self.out.push('\n');
+        self.line_start = true;
+    }
+
+    /// Writes `{`, lines are indented until the block is closed
+    fn open_block(&mut self) {
+        self.write("{");
+        self.end_line();
+        self.indent += 1;
+    }
+
+    fn close_block(&mut self) {
+        self.indent -= 1;
+        self.write("}");
+    }
+
+    fn finish(self) -> String {
+        self.out
+    }
+}
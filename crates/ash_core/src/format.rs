@@ -0,0 +1,290 @@
+use std::collections::VecDeque;
+
+use crate::core::{AshResult, Span, Spanned, StringError};
+use crate::dump::ast::AstPrinter;
+use crate::lexer::{
+    token::Token,
+    trivia::{Lexed, Trivia},
+    Lexer,
+};
+use crate::parser::parser::Parser;
+
+/// Prints code in the canonical style. Comments and single blank lines between
+/// statements are kept, code which does not parse is left to the caller
+pub fn format(code: &str) -> AshResult<String, String> {
+    let lexed = Lexer::new().scan_lossless(code, 0).string_err()?;
+    let tokens = lexed
+        .tokens
+        .iter()
+        .map(|token| token.token.clone())
+        .collect();
+    let ast = Parser::new().parse(tokens).string_err()?;
+
+    Ok(AstPrinter::new(None)
+        .with_layout(Layout::new(lexed))
+        .print(&ast))
+}
+
+/// Layout of the code which is not a part of the AST
+pub(crate) struct Layout {
+    // Comments in source order with the end of the token they trail,
+    // taken as the printer passes them
+    comments: VecDeque<(Spanned<String>, Option<usize>)>,
+    // Whitespace between the tokens with the number of line breaks in it
+    spaces: Vec<(Span, usize)>,
+    tokens: Vec<Spanned<Token>>,
+}
+
+impl Layout {
+    fn new(lexed: Lexed) -> Self {
+        let mut layout = Self {
+            comments: VecDeque::new(),
+            spaces: Vec::new(),
+            tokens: Vec::new(),
+        };
+
+        for token in lexed.tokens {
+            layout.add(token.leading, None);
+            layout.add(token.trailing, Some(token.token.1.end));
+            layout.tokens.push(token.token);
+        }
+        layout.add(lexed.trailing, None);
+
+        layout
+    }
+
+    fn add(&mut self, trivia: Vec<Spanned<Trivia>>, trails: Option<usize>) {
+        for (trivia, span) in trivia {
+            match trivia {
+                Trivia::Whitespace(text) => {
+                    let lines = text.chars().filter(|c| *c == '\n').count();
+                    self.spaces.push((span, lines));
+                }
+                comment => {
+                    let text = comment.text().trim_end().to_owned();
+                    self.comments.push_back(((text, span), trails));
+                }
+            }
+        }
+    }
+
+    /// Next comment if it starts before `pos`
+    pub fn comment_before(&mut self, pos: usize) -> Option<Spanned<String>> {
+        match self.comments.front() {
+            Some(((_, span), _)) if span.start < pos => {
+                self.comments.pop_front().map(|(comment, _)| comment)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn has_comment_before(&self, pos: usize) -> bool {
+        self.comments
+            .front()
+            .is_some_and(|((_, span), _)| span.start < pos)
+    }
+
+    /// Next comment if it is on the same line as a token ending at or before `pos`
+    pub fn comment_after(&mut self, pos: usize) -> Option<Spanned<String>> {
+        match self.comments.front() {
+            Some((_, Some(trails))) if *trails <= pos => {
+                self.comments.pop_front().map(|(comment, _)| comment)
+            }
+            _ => None,
+        }
+    }
+
+    /// Position of the first `brace` at or after `pos`
+    pub fn brace(&self, brace: char, pos: usize) -> Option<usize> {
+        let brace = match brace {
+            '{' => Token::LBrace,
+            _ => Token::RBrace,
+        };
+        self.tokens
+            .iter()
+            .find(|(token, span)| span.start >= pos && *token == brace)
+            .map(|(_, span)| span.start)
+    }
+
+    /// Source text of the literal token at `span`. Strings are
+    /// without their quotes and the braces of interpolation
+    pub fn literal(&self, span: &Span) -> Option<&str> {
+        let index = self
+            .tokens
+            .binary_search_by_key(&span.start, |(_, span)| span.start)
+            .ok()?;
+        let (token, token_span) = &self.tokens[index];
+        if token_span != span {
+            return None;
+        }
+
+        match token {
+            Token::Int(text) | Token::Float(text) => Some(text),
+            Token::Str(text) | Token::StrStart(text) | Token::StrMiddle(text) | Token::StrEnd(text) => {
+                Some(&text.raw)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whitespace between `span.start` and `span.end` has an empty line
+    pub fn has_blank_line(&self, span: Span) -> bool {
+        self.spaces
+            .iter()
+            .any(|(space, lines)| span.start <= space.start && space.end <= span.end && *lines > 1)
+    }
+
+    /// The first token after `pos` is `else`
+    pub fn is_else(&self, pos: usize) -> bool {
+        self.tokens
+            .iter()
+            .find(|(_, span)| span.start >= pos)
+            .is_some_and(|(token, _)| *token == Token::Else)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ashery::parse;
+    use crate::dump::ast::AstPrinter;
+
+    use super::format;
+
+    /// AST printed without the layout of the code, so only its structure and values are compared
+    fn ast(code: &str) -> String {
+        AstPrinter::new(None).print(&parse(code, 0).unwrap())
+    }
+
+    /// Formats code which has to keep its meaning and stay the same when formatted again
+    fn format_stable(code: &str) -> String {
+        let formatted = format(code).unwrap();
+        assert_eq!(ast(&formatted), ast(code), "meaning changed in\n{formatted}");
+        assert_eq!(format(&formatted).unwrap(), formatted, "formatted again differently");
+        formatted
+    }
+
+    #[test]
+    fn numbers_in_commented_expressions() {
+        let code = "\
+fun main() {
+    foo(1, // arg
+        2);
+    val a = 1 + /* two */ 2 * 0xff_ff;
+    if a == 3 {
+        bar(-128i8, // min
+            1_000, 2.5e3f32);
+    }
+}
+";
+        let formatted = format_stable(code);
+        for number in ["foo(1, ", "2)", "0xff_ff", "a == 3", "-128i8", "1_000", "2.5e3f32"] {
+            assert!(formatted.contains(number), "`{number}` is lost in\n{formatted}");
+        }
+    }
+
+    #[test]
+    fn comments_inside_expressions() {
+        let code = "\
+fun main() {
+    val a = 1 + /* inline */ 2;
+    foo(1, // first
+        2);
+    bar( // open
+        a);
+}
+";
+        assert_eq!(format_stable(code), code);
+    }
+
+    #[test]
+    fn comments_stay_after_their_token() {
+        let code = "\
+fun main() {
+    val a = b /* c */ + d;
+    val e = (f /* g */) * h /* i */;
+    foo(a /* first */, b);
+    val j = k // l
+        + m;
+}
+";
+        assert_eq!(format_stable(code), code);
+    }
+
+    #[test]
+    fn strings_keep_escape_sequences() {
+        let code = r#"fun main() {
+    println("\u{41}\u{1F600} \"quoted\"\t\\ \$ $5 😀");
+    println("\u{41}${a}\u{1f600}${b}\n");
+}
+"#;
+        assert_eq!(format_stable(code), code);
+    }
+
+    #[test]
+    fn line_comment_does_not_hide_code() {
+        let formatted = format_stable("fun main() {\n    foo(1, // first\n2, /* b */ 3);\n}\n");
+        assert_eq!(
+            formatted,
+            "fun main() {\n    foo(1, // first\n        2, /* b */ 3);\n}\n"
+        );
+    }
+
+    #[test]
+    fn blank_lines_are_kept() {
+        let code = "\
+val a = 1;
+
+val b = 2;
+// about c
+
+// before c
+val c = 3;
+
+fun main() {
+    foo();
+
+    bar();
+}
+";
+        assert_eq!(format_stable(code), code);
+        assert_eq!(
+            format_stable("val a = 1;\n\n\n\nval b = 2;\n"),
+            "val a = 1;\n\nval b = 2;\n"
+        );
+    }
+
+    #[test]
+    fn commented_empty_else() {
+        let code = "\
+fun main() {
+    if a {
+        foo();
+    } else {
+        // nothing to do
+    }
+    if a {
+        foo();
+    } else {}
+}
+";
+        assert_eq!(
+            format_stable(code),
+            code.replace(" else {}", "")
+        );
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let code = "\
+// header
+import std.{a,b};
+pub fun add(a:i32,b:i32)>i32=>a+b; // sum
+fun main(){
+var x=add(1,/* one */2) as i64;
+while x<10{x=x+1;}
+if x==1{foo();}else if x==2{bar(\"${x} items\");}
+}
+";
+        format_stable(code);
+    }
+}
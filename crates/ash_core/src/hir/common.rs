@@ -9,47 +9,45 @@ use crate::{core::{Spanned, Context}, parser::Stmt};
 // The current implementation of VM needs to know values of every variable that
 // is needed to initialize declared variable
 pub(crate) fn sort_root(ctx: &Context, ast: Vec<Spanned<Stmt>>) -> Vec<Spanned<Stmt>> {
+    let root_vars = ast
+        .iter()
+        .filter_map(|(stmt, _)| match stmt {
+            Stmt::VariableDecl { id, .. } => Some(*id),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+
     let mut sorted_ast = Vec::new();
     let mut postponed = Vec::new();
     let mut declared = HashSet::new();
     let mut unsorted_vars = VecDeque::new();
     for stmt in ast {
         match stmt.0 {
-            Stmt::VariableDecl { id, .. } => {
-                let deps = ctx.get_var_deps(id);
-                if deps.is_empty() {
-                    sorted_ast.push(stmt);
-                    declared.insert(id);
-                } else {
-                    for dep in deps {
-                        if !declared.contains(dep) {
-                            unsorted_vars.push_back((id, stmt, deps));
-                            break;
-                        }
-                    }
-                }
-            }
+            Stmt::VariableDecl { id, .. } => unsorted_vars.push_back((id, stmt)),
             _ => postponed.push(stmt),
         }
     }
 
-    // TODO: Make sure it doesn't loop forever
-    while let Some((id, v, deps)) = unsorted_vars.pop_front() {
-        let mut resolved = true;
-        for dep in deps {
-            if !declared.contains(dep) {
-                resolved = false;
-                unsorted_vars.push_back((id, v.clone(), deps));
-                break;
-            }
-        }
+    // Functions and globals defined by earlier code are available already.
+    // Initialization loops are reported by the resolver, so when no variable
+    // can be initialized the rest is kept in the source order
+    let mut stalled = 0;
+    while let Some((id, stmt)) = unsorted_vars.pop_front() {
+        let resolved = ctx
+            .get_var_deps(id)
+            .iter()
+            .all(|dep| !root_vars.contains(dep) || declared.contains(dep));
 
-        if resolved {
+        if resolved || stalled > unsorted_vars.len() {
             declared.insert(id);
-            sorted_ast.push(v);
+            sorted_ast.push(stmt);
+            stalled = 0;
+        } else {
+            unsorted_vars.push_back((id, stmt));
+            stalled += 1;
         }
     }
 
     sorted_ast.append(&mut postponed);
     sorted_ast
-}
\ No newline at end of file
+}
@@ -1,4 +1,4 @@
-use crate::{core::{Context, Spanned, Id, next_id, Annotation}, parser::{Stmt, Expr, If, IfInner, operator::{UnaryOp, BinaryOp}}, ty::{function::{Function, ProtoFunction}, Ty, Value}, prelude::Span};
+use crate::{core::{Context, Spanned, Id, next_id, Annotation}, parser::{Stmt, Expr, StrPart, If, IfInner, operator::{UnaryOp, BinaryOp}}, ty::{function::{Function, ProtoFunction}, Ty, Value}, prelude::Span};
 
 use super::{scope::Scope, hir::{Body, self}, common::sort_root};
 
@@ -7,15 +7,22 @@ pub(crate) struct Desugarer<'a> {
     scopes: Scope<Body>,
     tmp_vars: Vec<(Id, String)>,
     mangle_names: bool,
+    // Only `main` of the entry module is called by the VM
+    is_entry: bool,
 }
 
 impl<'a> Desugarer<'a> {
     pub fn run(ctx: &'a mut Context, ast: Vec<Spanned<Stmt>>) -> Vec<Spanned<hir::Stmt>> {
+        Self::run_module(ctx, ast, true)
+    }
+
+    pub fn run_module(ctx: &'a mut Context, ast: Vec<Spanned<Stmt>>, is_entry: bool) -> Vec<Spanned<hir::Stmt>> {
         let mut desugarer = Self {
             ctx,
             scopes: Scope::new(),
             tmp_vars: Vec::new(),
             mangle_names: true,
+            is_entry,
         };
 
         let ast = sort_root(&desugarer.ctx, ast); 
@@ -42,6 +49,8 @@ impl<'a> Desugarer<'a> {
             Stmt::While(cond, body) => self.stmt_while(cond, body, span),
             Stmt::Break(expr) => self.br(expr, span),
             Stmt::Return(expr) => self.ret(expr, span),
+            Stmt::Import(_) => {}
+            Stmt::Error => unreachable!("Syntax errors stop compilation before desugaring"),
         }
     }
 
@@ -52,14 +61,20 @@ impl<'a> Desugarer<'a> {
 
     fn expr(&mut self, expr: Expr) -> hir::Expr {
         match expr {
-            Expr::Variable(id, _) => self.var(id),
+            Expr::Variable(id, _) | Expr::Path(id, _) => self.var(id),
             Expr::Block(stmts) => self.expr_block(stmts),
             Expr::If(data) => self.expr_if(data),
-            Expr::Literal(v) => self.literal(v),
+            Expr::Literal(v, _) => self.literal(v),
+            Expr::Interpolated(parts) => self.interpolated(parts),
             Expr::Call { callee, args } => self.call(*callee, args),
             Expr::Group(expr) => self.expr(*expr),
             Expr::Unary { op, right } => self.unary(op, *right),
+            Expr::Cast { expr, ty } => hir::Expr::Cast {
+                expr: Box::new(self.expr(*expr)),
+                ty,
+            },
             Expr::Binary { left, op, right } => self.binary(*left, op, *right),
+            Expr::Error => unreachable!("Syntax errors stop compilation before desugaring"),
         }
     }
 
@@ -76,7 +91,7 @@ impl<'a> Desugarer<'a> {
             self.multiple_stmt(stmts);
         }
         let stmts = self.scopes.leave();
-        self.scope_add((hir::Stmt::Block(stmts), Span::default()));
+        self.scope_add((hir::Stmt::ExprBlock(stmts), Span::default()));
         
         self.tmp_var_load_rem()
     }
@@ -85,6 +100,27 @@ impl<'a> Desugarer<'a> {
         hir::Expr::Literal(value)
     }
 
+    /// Interpolated string is a concatenation of its parts converted to strings
+    fn interpolated(&mut self, parts: Vec<StrPart>) -> hir::Expr {
+        let parts = parts
+            .into_iter()
+            .filter(|part| !matches!(part, StrPart::Text(text, _) if text.is_empty()))
+            .map(|part| match part {
+                StrPart::Text(text, _) => hir::Expr::Literal(Value::String(text)),
+                StrPart::Expr(expr) => hir::Expr::ToStr(Box::new(self.expr(expr))),
+            })
+            .collect::<Vec<_>>();
+
+        parts
+            .into_iter()
+            .reduce(|left, right| hir::Expr::Binary {
+                left: Box::new(left),
+                op: BinaryOp::Sum,
+                right: Box::new(right),
+            })
+            .unwrap_or_else(|| hir::Expr::Literal(Value::String(String::new())))
+    }
+
     fn call(&mut self, callee: Expr, args: Vec<Expr>) -> hir::Expr {
         let callee = Box::new(self.expr(callee));
         let args = args
@@ -135,7 +171,7 @@ impl<'a> Desugarer<'a> {
         let mut proto = fun.proto.0;
         let proto_span = fun.proto.1;
         let prev = self.mangle_names;
-        self.mangle_names = proto.name != "main" && self.mangle_names;
+        self.mangle_names = !(self.is_entry && proto.name == "main") && self.mangle_names;
         proto.name = self.mangled_name(proto.id);
         self.mangle_names = prev;
 
@@ -246,7 +282,14 @@ impl<'a> Desugarer<'a> {
 
     fn expr_if(&mut self, data: If<Expr, Stmt>) -> hir::Expr {
         self.new_tmp_var();
-        self.stmt_if(data, Span::default());
+
+        self.scopes.enter();
+        {
+            self.stmt_if(data, Span::default());
+        }
+        let stmts = self.scopes.leave();
+        self.scope_add((hir::Stmt::ExprBlock(stmts), Span::default()));
+
         self.tmp_var_load_rem()
     }
 
@@ -282,7 +325,7 @@ impl<'a> Desugarer<'a> {
 
     fn new_tmp_var(&mut self) {
         let id = next_id();
-        self.ctx.new_var(id, "tmp_".to_string(), None);
+        self.ctx.new_var(id, "tmp_".to_string(), None, true);
         let name = self.mangled_name(id);
         let decl = hir::Stmt::DeclVar { 
             id,
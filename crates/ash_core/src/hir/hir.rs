@@ -20,6 +20,8 @@ pub(crate) enum Stmt {
     While(Spanned<Expr>, Body),
     If(If<Expr, Stmt>),
     Block(Body),
+    // Block which `break` jumps out of
+    ExprBlock(Body),
     Break,
     Ret(Option<Expr>),
     Expr(Expr)
@@ -35,6 +37,8 @@ impl Stmt {
 pub(crate) enum Expr {
     LoadVar(Id, String),
     Literal(Value),
+    // Converts the value to its string form
+    ToStr(Box<Expr>),
     Call {
         callee: Box<Expr>,
         args: Vec<Expr>,
@@ -43,6 +47,10 @@ pub(crate) enum Expr {
         op: UnaryOp,
         right: Box<Expr>,
     },
+    Cast {
+        expr: Box<Expr>,
+        ty: Ty,
+    },
     Binary {
         left: Box<Expr>,
         op: BinaryOp,
@@ -13,23 +13,34 @@ pub(super) fn basic_lexer() -> impl Parser<char, Token, Error = Simple<char>> {
     let and_and = just("&&").to(Token::AndAnd);
     let bar_bar = just("||").to(Token::BarBar);
 
-    let gt = just(">=").to(Token::Gte).or(just('>').to(Token::Gt));
-    let lt = just("<=").to(Token::Lte).or(just('<').to(Token::Lt));
+    let gt = just(">>")
+        .to(Token::Shr)
+        .or(just(">=").to(Token::Gte))
+        .or(just('>').to(Token::Gt));
+    let lt = just("<<")
+        .to(Token::Shl)
+        .or(just("<=").to(Token::Lte))
+        .or(just('<').to(Token::Lt));
 
-    let ops = one_of("+-*/%")
+    let ops = one_of("+-*/%&|^~")
         .map_with_span(|c, _span| match c {
             '+' => Token::Plus,
             '-' => Token::Minus,
             '/' => Token::Slash,
             '*' => Token::Asterisk,
             '%' => Token::Percent,
+            '&' => Token::Amp,
+            '|' => Token::Bar,
+            '^' => Token::Caret,
+            '~' => Token::Tilde,
             _ => unreachable!(),
         })
         .labelled("operators");
 
-    let other = one_of("=,@{}[]()!").map_with_span(|c, _span| match c {
+    let other = one_of("=,.@{}[]()!").map_with_span(|c, _span| match c {
         '=' => Token::Equal,
         ',' => Token::Comma,
+        '.' => Token::Dot,
         '@' => Token::At,
         '{' => Token::LBrace,
         '}' => Token::RBrace,
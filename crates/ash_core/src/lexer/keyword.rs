@@ -4,7 +4,8 @@ use crate::lexer::token::Token;
 
 pub(super) fn keyword_lexer() -> impl Parser<char, Token, Error = Simple<char>> {
     text::ident()
-        .then(filter(|c: &char| c.is_inline_whitespace()).repeated())
+        // Spaces are only looked at, the token ends with the identifier
+        .then(filter(|c: &char| c.is_inline_whitespace()).repeated().rewind())
         .map(|(ident, space): (String, Vec<_>)| match ident.as_str() {
             "true" => Token::Bool(true),
             "false" => Token::Bool(false),
@@ -16,6 +17,9 @@ pub(super) fn keyword_lexer() -> impl Parser<char, Token, Error = Simple<char>>
             "if" => Token::If,
             "else" => Token::Else,
             "while" => Token::While,
+            "import" => Token::Import,
+            "pub" => Token::Pub,
+            "as" => Token::As,
             _ => Token::Identifier {
                 value: ident,
                 space_sufix: !space.is_empty(),
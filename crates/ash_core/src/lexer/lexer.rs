@@ -1,19 +1,24 @@
-use crate::core::{AshResult, Span, Spanned};
+use crate::core::{error_code, AshResult, Span, Spanned};
 use crate::lexer::basic::basic_lexer;
 use crate::lexer::keyword::keyword_lexer;
 use crate::lexer::numeric::numeric_lexer;
 use crate::lexer::string::string_lexer;
 use crate::lexer::token::{Delim, Token, TokenTree};
+use crate::lexer::trivia::{attach_trivia, trivia_lexer, Lexed, Trivia};
 use chumsky::prelude::*;
 use chumsky::text::Character;
 use chumsky::{BoxStream, Flat, Stream};
 
-pub(crate) struct Lexer<'a>(BoxedParser<'a, char, Vec<Spanned<TokenTree>>, Simple<char>>);
+pub(crate) struct Lexer<'a> {
+    tokens: BoxedParser<'a, char, Vec<Spanned<TokenTree>>, Simple<char>>,
+    trivia: BoxedParser<'a, char, Vec<Spanned<Trivia>>, Simple<char>>,
+}
 
 impl<'a> Lexer<'a> {
     pub fn new() -> Self {
+        let trivia = trivia_lexer().repeated();
         let tt = recursive(|tt| {
-            let tt_list = tt.repeated();
+            let tt_list = tt.clone().repeated().then_ignore(trivia.clone());
 
             let delim_tree = |delim_l: char, delim_r: char, delim_t: Delim| {
                 tt_list
@@ -22,24 +27,53 @@ impl<'a> Lexer<'a> {
                     .map(move |tts| TokenTree::Tree(delim_t.clone(), tts))
             };
 
-            keyword_lexer()
-                .or(string_lexer())
+            // Any other char which can not start a token, delimiters are left for the trees
+            let error = none_of("()[]{}\"")
+                .validate(|c, span, emit| {
+                    emit(
+                        Simple::custom(span, format!("Unexpected character `{c}`"))
+                            .with_label(error_code::UNEXPECTED_TOKEN),
+                    );
+                    Token::Error
+                });
+            let token = keyword_lexer()
                 .or(numeric_lexer())
                 .or(basic_lexer())
+                .or(error)
                 .map(TokenTree::Token)
+                .or(string_lexer(tt.clone()))
                 .or(delim_tree('(', ')', Delim::Paren))
                 .or(delim_tree('{', '}', Delim::Brace))
                 .or(delim_tree('[', ']', Delim::Bracket))
-                .map_with_span(|tt, span| (tt, span))
-                .padded()
+                .map_with_span(|tt, span| (tt, span));
+
+            // Trivia is skipped, it is scanned separately by `scan_lossless`
+            trivia.clone().ignore_then(token)
         });
 
-        let parser = tt.repeated().then_ignore(end());
-        Self(parser.boxed())
+        let tokens = tt.repeated().then_ignore(trivia).then_ignore(end());
+        let trivia = trivia_lexer()
+            .map_with_span(|trivia, span| (trivia, span))
+            .repeated()
+            .then_ignore(end());
+        Self {
+            tokens: tokens.boxed(),
+            trivia: trivia.boxed(),
+        }
     }
 
     pub fn scan(&self, source: &str) -> AshResult<Vec<Spanned<Token>>, char> {
-        let result = self.0.parse(source)?;
+        self.scan_at(source, 0)
+    }
+
+    /// Scans source starting at `offset` of a `SourceMap`, spans are offset accordingly
+    pub fn scan_at(&self, source: &str, offset: usize) -> AshResult<Vec<Spanned<Token>>, char> {
+        let len = source.chars().count();
+        let chars = source
+            .chars()
+            .enumerate()
+            .map(|(i, c)| (c, offset + i..offset + i + 1));
+        let result = self.tokens.parse(Stream::from_iter(offset + len..offset + len, chars))?;
         let tokens = Self::flatten_token_trees(result)
             .fetch_tokens()
             .into_iter()
@@ -48,8 +82,80 @@ impl<'a> Lexer<'a> {
         Ok(tokens)
     }
 
+    /// Scans the source even if some of it is not valid, invalid code is
+    /// skipped or scanned as `Token::Error`. There are no tokens when a delimiter is not closed
+    pub fn scan_recovery(
+        &self,
+        source: &str,
+        offset: usize,
+    ) -> (Option<Vec<Spanned<Token>>>, Vec<Simple<char>>) {
+        let len = source.chars().count();
+        let chars = source
+            .chars()
+            .enumerate()
+            .map(|(i, c)| (c, offset + i..offset + i + 1));
+        let (result, errors) = self
+            .tokens
+            .parse_recovery(Stream::from_iter(offset + len..offset + len, chars));
+        let tokens = result.map(|tts| Self::flatten_token_trees(tts).fetch_tokens().collect());
+
+        (tokens, errors)
+    }
+
+    /// Tokens with comments and whitespace around them, nothing of the source is lost.
+    /// Spans start at `offset` the same as in `scan_at`
+    pub fn scan_lossless(&self, source: &str, offset: usize) -> AshResult<Lexed, char> {
+        let tokens = self.scan_at(source, offset)?;
+        let chars = source.chars().collect::<Vec<_>>();
+        let end = offset + chars.len();
+
+        // Gaps between the tokens contain only trivia
+        let mut trivia = Vec::new();
+        let mut start = offset;
+        let spans = tokens
+            .iter()
+            .map(|(_, span)| span.clone())
+            .chain(std::iter::once(end..end));
+        for span in spans {
+            let gap = chars[start - offset..span.start - offset]
+                .iter()
+                .enumerate()
+                .map(|(i, c)| (*c, start + i..start + i + 1));
+            let eoi = span.start..span.start;
+            trivia.extend(self.trivia.parse(Stream::from_iter(eoi, gap))?);
+            start = span.end;
+        }
+
+        Ok(attach_trivia(tokens, trivia))
+    }
+
+    /// Source ends before every `(`, `{` and `[` is closed
+    pub fn is_unclosed(&self, source: &str) -> bool {
+        let tokens = match self.scan(source) {
+            Ok(tokens) => tokens,
+            Err(_) => return false,
+        };
+
+        let mut closing = Vec::new();
+        for (token, _) in tokens {
+            match token {
+                Token::LParen => closing.push(Token::RParen),
+                Token::LBrace => closing.push(Token::RBrace),
+                Token::LBracket => closing.push(Token::RBracket),
+                // Mismatched delimiters are left for the parser to report
+                Token::RParen | Token::RBrace | Token::RBracket
+                    if closing.pop().as_ref() != Some(&token) =>
+                {
+                    return false;
+                }
+                _ => {}
+            }
+        }
+
+        !closing.is_empty()
+    }
+
     fn flatten_token_trees(tts: Vec<Spanned<TokenTree>>) -> BoxStream<'static, Token, Span> {
-        use std::iter::once;
         let eoi = if let Some(tok) = tts.last() {
             let span = tok.1.end;
             span..span
@@ -61,24 +167,21 @@ impl<'a> Lexer<'a> {
 
         Stream::from_nested(eoi, tts.into_iter(), move |(tt, span)| match tt {
             TokenTree::Token(tok) => Flat::Single((tok, span)),
-            TokenTree::Tree(Delim::Brace, tt) => Flat::Many(
-                once((
-                    Token::LBrace.to_tree(),
-                    span_at(span.start.saturating_sub(1)),
-                ))
-                .chain(tt.into_iter())
-                .chain(once((Token::RBrace.to_tree(), span_at(span.end)))),
-            ),
-            TokenTree::Tree(Delim::Paren, tt) => Flat::Many(
-                once((Token::LParen.to_tree(), span_at(span.start)))
-                    .chain(tt.into_iter())
-                    .chain(once((Token::RParen.to_tree(), span_at(span.end - 1)))),
-            ),
-            TokenTree::Tree(Delim::Bracket, tt) => Flat::Many(
-                once((Token::LBracket.to_tree(), span_at(span.start)))
-                    .chain(tt.into_iter())
-                    .chain(once((Token::RBracket.to_tree(), span_at(span.end - 1)))),
-            ),
+            TokenTree::Tree(delim, tt) => {
+                let (open, close) = match delim {
+                    Delim::Paren => (Some(Token::LParen), Some(Token::RParen)),
+                    Delim::Brace => (Some(Token::LBrace), Some(Token::RBrace)),
+                    Delim::Bracket => (Some(Token::LBracket), Some(Token::RBracket)),
+                    // Text parts of interpolated strings are tokens themselves
+                    Delim::Str => (None, None),
+                };
+                Flat::Many(
+                    open.map(|tok| (tok.to_tree(), span_at(span.start)))
+                        .into_iter()
+                        .chain(tt)
+                        .chain(close.map(|tok| (tok.to_tree(), span_at(span.end - 1)))),
+                )
+            }
         })
     }
 }
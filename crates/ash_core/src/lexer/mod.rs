@@ -4,5 +4,6 @@ mod basic;
 mod keyword;
 pub mod lexer;
 mod numeric;
-mod string;
+pub(crate) mod string;
 pub mod token;
+pub(crate) mod trivia;
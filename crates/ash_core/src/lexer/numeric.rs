@@ -1,15 +1,166 @@
 use chumsky::prelude::*;
 
-use crate::lexer::token::Token;
+use crate::{core::error_code, lexer::token::Token};
 
+/// Number literals keep their source text, they are converted to values by
+/// the parser. Digits can be separated by `_` and followed by a type suffix
 pub(super) fn numeric_lexer() -> impl Parser<char, Token, Error = Simple<char>> {
-    let int = text::int(10).map(Token::I32).labelled("i32");
+    let digit_or_separator = |radix: u32| filter(move |c: &char| c.is_digit(radix) || *c == '_');
+    let digits = move |radix: u32| {
+        filter(move |c: &char| c.is_digit(radix))
+            .chain(digit_or_separator(radix).repeated())
+            .collect::<String>()
+    };
+    // Separators are checked by the parser, `0x_` is a misplaced one rather than `0` with suffix `x_`
+    let prefixed_digits = move |radix: u32| {
+        digit_or_separator(radix)
+            .repeated()
+            .at_least(1)
+            .collect::<String>()
+    };
+    let decimal = digits(10);
+    let prefixed = just("0x")
+        .then(prefixed_digits(16))
+        .or(just("0o").then(prefixed_digits(8)))
+        .or(just("0b").then(prefixed_digits(2)))
+        .map(|(prefix, digits)| format!("{prefix}{digits}"));
 
-    let double = text::int(10)
-        .chain::<char, _, _>(just('.').chain(text::digits(10)))
-        .collect::<String>()
-        .map(Token::F64)
-        .labelled("f64");
+    let fraction = just('.')
+        .ignore_then(digits(10))
+        .map(|digits| format!(".{digits}"));
+    let exponent = one_of("eE")
+        .then(one_of("+-").or_not())
+        .then(digits(10))
+        .map(|((e, sign), digits)| format!("{e}{}{digits}", sign.map(String::from).unwrap_or_default()));
+    let int_suffix = choice((
+        just("i8"),
+        just("i16"),
+        just("i32"),
+        just("i64"),
+        just("u8"),
+        just("u16"),
+        just("u32"),
+        just("u64"),
+    ));
+    let float_suffix = just("f32").or(just("f64"));
 
-    double.or(int)
+    // `1e3` and `1f32` are floats too
+    let float = decimal
+        .then(
+            fraction
+                .then(exponent.clone().or_not())
+                .map(|(fraction, exponent)| fraction + &exponent.unwrap_or_default())
+                .or(exponent)
+                .then(float_suffix.or_not())
+                .map(|(number, suffix)| number + suffix.unwrap_or_default())
+                .or(float_suffix.map(String::from)),
+        )
+        .map(|(int, rest)| Token::Float(int + &rest))
+        .labelled("float");
+    let int = prefixed
+        .or(decimal)
+        .then(int_suffix.or_not())
+        .map(|(digits, suffix)| Token::Int(digits + suffix.unwrap_or_default()))
+        .labelled("integer");
+
+    // Anything a literal can not end with, like `u7` of `1u7` or `2` of `0b12`
+    let invalid_suffix = filter(|c: &char| c.is_alphanumeric() || *c == '_')
+        .repeated()
+        .at_least(1)
+        .collect::<String>();
+
+    // The literal is scanned without the invalid suffix, so the rest of the code is checked as well
+    float
+        .or(int)
+        .then(invalid_suffix.or_not())
+        .validate(|(token, suffix), span, emit| {
+            if let Some(suffix) = suffix {
+                emit(
+                    Simple::custom(span, format!("Invalid suffix `{suffix}` of number literal"))
+                        .with_label(error_code::INVALID_LITERAL),
+                );
+            }
+            token
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use chumsky::error::SimpleReason;
+
+    use crate::lexer::{token::Token, Lexer};
+
+    /// Tokens of `code` assigned to a variable, without the tokens around it
+    fn scan(code: &str) -> Result<Vec<Token>, Vec<String>> {
+        match Lexer::new().scan(&format!("val a = {code};")) {
+            Ok(tokens) => {
+                let tokens = tokens
+                    .into_iter()
+                    .map(|(token, _)| token)
+                    .collect::<Vec<_>>();
+                Ok(tokens[3..tokens.len() - 1].to_vec())
+            }
+            Err(errors) => Err(errors
+                .iter()
+                .map(|error| match error.reason() {
+                    SimpleReason::Custom(msg) => msg.clone(),
+                    _ => error.to_string(),
+                })
+                .collect()),
+        }
+    }
+
+    #[test]
+    fn integers() {
+        let cases = [
+            "1_000",
+            "0xff_ff",
+            "0o17",
+            "0b1010_0101",
+            "255u8",
+            "0x7fi64",
+        ];
+        for code in cases {
+            assert_eq!(scan(code), Ok(vec![Token::Int(code.to_owned())]));
+        }
+    }
+
+    #[test]
+    fn floats() {
+        let cases = [
+            "1.5",
+            "1e3",
+            "2E+10",
+            "1.5e-3",
+            "1e3f32",
+            "1.5e-3f64",
+            "1f32",
+            "1_0.0_1",
+        ];
+        for code in cases {
+            assert_eq!(scan(code), Ok(vec![Token::Float(code.to_owned())]));
+        }
+    }
+
+    #[test]
+    fn separators_stay_in_the_literal() {
+        // Misplaced ones are reported by the parser
+        for code in ["1__0", "1_", "1_u8", "0x_", "0x_1", "0b1__0"] {
+            assert_eq!(scan(code), Ok(vec![Token::Int(code.to_owned())]));
+        }
+        for code in ["1.0_", "1e1_", "1_e3"] {
+            assert_eq!(scan(code), Ok(vec![Token::Float(code.to_owned())]));
+        }
+    }
+
+    #[test]
+    fn invalid_suffixes() {
+        for code in ["1u7", "1.5i32", "1e", "0x", "1e3u8", "0b12", "0o8"] {
+            let errors = scan(code).expect_err(code);
+            assert!(
+                errors.iter().any(|error| error.contains("Invalid suffix")),
+                "`{code}` failed with {errors:?}"
+            );
+        }
+    }
 }
@@ -1,12 +1,170 @@
 use chumsky::prelude::*;
 
-use crate::lexer::token::Token;
+use crate::core::{error_code, Spanned};
+use crate::lexer::token::{Delim, StrText, Token, TokenTree};
+use crate::lexer::trivia::trivia_lexer;
 
-pub(super) fn string_lexer() -> impl Parser<char, Token, Error = Simple<char>> {
-    just('"')
-        .ignore_then(filter(|c| *c != '"').repeated())
-        .then_ignore(just('"'))
+/// Plain string or interpolated string like `"sum = ${a + b}"`. Code of
+/// interpolated strings is lexed by `tt` between the text parts of the string
+pub(super) fn string_lexer<'a, P>(tt: P) -> impl Parser<char, TokenTree, Error = Simple<char>> + 'a
+where
+    P: Parser<char, Spanned<TokenTree>, Error = Simple<char>> + Clone + 'a,
+{
+    let text = text_lexer();
+    // Code ends at the `}` which is not closing a brace of the code
+    let code = recursive(|code| {
+        let braced = code
+            .delimited_by(just('{'), just('}'))
+            .map(|tts| TokenTree::Tree(Delim::Brace, tts))
+            .map_with_span(|tt, span| (tt, span));
+        let token = none_of("{}").rewind().ignore_then(tt);
+
+        trivia_lexer()
+            .repeated()
+            .ignore_then(braced.or(token))
+            .repeated()
+            .then_ignore(trivia_lexer().repeated())
+    });
+    let part = |open: char, close: &'static str, token: fn(StrText) -> Token| {
+        just(open)
+            .ignore_then(text.clone())
+            .then_ignore(just(close))
+            .map_with_span(move |text, span| (token(text), span))
+    };
+
+    let interpolated = part('"', "${", Token::StrStart)
+        .then(code.clone())
+        .then(part('}', "${", Token::StrMiddle).then(code).repeated())
+        .then(part('}', "\"", Token::StrEnd))
+        .map(|(((start, code), middle), end)| {
+            let mut tts = vec![(start.0.to_tree(), start.1)];
+            tts.extend(code);
+            for ((token, span), code) in middle {
+                tts.push((token.to_tree(), span));
+                tts.extend(code);
+            }
+            tts.push((end.0.to_tree(), end.1));
+            TokenTree::Tree(Delim::Str, tts)
+        });
+    let plain = text
+        .delimited_by(just('"'), just('"'))
+        .map(|text| Token::Str(text).to_tree());
+
+    interpolated.or(plain).labelled("str")
+}
+
+/// Text of a string with escape sequences replaced, each char comes with its source text
+fn text_lexer() -> impl Parser<char, StrText, Error = Simple<char>> + Clone {
+    let unicode = filter(char::is_ascii_hexdigit)
+        .repeated()
+        .at_least(1)
+        .at_most(6)
         .collect::<String>()
-        .map(Token::Str)
-        .labelled("str")
+        .delimited_by(just('{'), just('}'))
+        .validate(|digits, span, emit| {
+            let c = u32::from_str_radix(&digits, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .unwrap_or_else(|| {
+                    emit(
+                        Simple::custom(span, format!("`{digits}` is not a unicode character"))
+                            .with_label(error_code::INVALID_LITERAL),
+                    );
+                    char::REPLACEMENT_CHARACTER
+                });
+            (c, format!("\\u{{{digits}}}"))
+        });
+    let escaped = |c: char, value: char| just(c).to((value, format!("\\{c}")));
+    let escape = just('\\').ignore_then(
+        one_of("\\\"$")
+            .map(|c| (c, format!("\\{c}")))
+            .or(escaped('n', '\n'))
+            .or(escaped('t', '\t'))
+            .or(escaped('r', '\r'))
+            .or(escaped('0', '\0'))
+            .or(just('u').ignore_then(unicode))
+            .or(any().validate(|c, span, emit| {
+                emit(
+                    Simple::custom(span, format!("Unknown escape sequence `\\{c}`"))
+                        .with_label(error_code::INVALID_LITERAL),
+                );
+                (c, format!("\\{c}"))
+            })),
+    );
+    // `$` starts interpolation only when followed by `{`
+    let dollar = just('$').then_ignore(just('{').not().rewind());
+
+    none_of("\\\"$")
+        .or(dollar)
+        .map(|c| (c, c.to_string()))
+        .or(escape)
+        .repeated()
+        .map(|chars| StrText {
+            value: chars.iter().map(|(c, _)| c).collect(),
+            raw: chars.iter().map(|(_, raw)| raw.as_str()).collect(),
+        })
+}
+
+/// Writes `text` as the content of a string literal, so it is lexed back the same
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => escaped += "\\\\",
+            '"' => escaped += "\\\"",
+            '\n' => escaped += "\\n",
+            '\t' => escaped += "\\t",
+            '\r' => escaped += "\\r",
+            '\0' => escaped += "\\0",
+            '$' if chars.peek() == Some(&'{') => escaped += "\\$",
+            c if c.is_control() => escaped += &format!("\\u{{{:x}}}", c as u32),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lexer::{token::Token, Lexer};
+
+    /// Decoded and source text of the string parts in `code`
+    fn texts(code: &str) -> Vec<(String, String)> {
+        Lexer::new()
+            .scan(code)
+            .unwrap()
+            .into_iter()
+            .filter_map(|(token, _)| match token {
+                Token::Str(text)
+                | Token::StrStart(text)
+                | Token::StrMiddle(text)
+                | Token::StrEnd(text) => Some((text.value, text.raw)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn escapes_keep_source_text() {
+        let code = r#""\u{41}\u{1F600} \"a\"\t\\ \$ $5 \0""#;
+        let raw = &code[1..code.len() - 1];
+        assert_eq!(
+            texts(code),
+            [("A\u{1F600} \"a\"\t\\ $ $5 \0".to_owned(), raw.to_owned())]
+        );
+    }
+
+    #[test]
+    fn interpolated_parts_keep_source_text() {
+        assert_eq!(
+            texts(r#""\u{41}${a}\n${b}end""#),
+            [
+                ("A".to_owned(), r"\u{41}".to_owned()),
+                ("\n".to_owned(), r"\n".to_owned()),
+                ("end".to_owned(), "end".to_owned()),
+            ]
+        );
+    }
 }
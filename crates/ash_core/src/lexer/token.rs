@@ -7,6 +7,12 @@ pub(crate) enum Token {
     At,
     AndAnd,
     BarBar,
+    Amp,
+    Bar,
+    Caret,
+    Tilde,
+    Shl,
+    Shr,
     LParen,
     RParen,
     LBrace,
@@ -28,6 +34,7 @@ pub(crate) enum Token {
     Slash,
     Arrow,
     Comma,
+    Dot,
     Colon,
     SemiColon,
     Ret,
@@ -39,10 +46,23 @@ pub(crate) enum Token {
     While,
     Val,
     Var,
-    Str(String),
-    I32(String),
-    F64(String),
+    Import,
+    Pub,
+    As,
+    Str(StrText),
+    /// `"text${` starting an interpolated string
+    StrStart(StrText),
+    /// `}text${` between interpolated expressions
+    StrMiddle(StrText),
+    /// `}text"` ending an interpolated string
+    StrEnd(StrText),
+    /// Source text of the literal like `0xff_u8`
+    Int(String),
+    /// Source text of the literal like `1.5e3f32`
+    Float(String),
     Bool(bool),
+    // Code which is not a token, the lexer reports it
+    Error,
 }
 
 impl fmt::Display for Token {
@@ -51,6 +71,12 @@ impl fmt::Display for Token {
             Token::At => "@",
             Token::AndAnd => "&&",
             Token::BarBar => "||",
+            Token::Amp => "&",
+            Token::Bar => "|",
+            Token::Caret => "^",
+            Token::Tilde => "~",
+            Token::Shl => "<<",
+            Token::Shr => ">>",
             Token::LParen => "(",
             Token::RParen => ")",
             Token::LBrace => "{",
@@ -72,6 +98,7 @@ impl fmt::Display for Token {
             Token::Percent => "%",
             Token::Arrow => "=>",
             Token::Comma => ",",
+            Token::Dot => ".",
             Token::Colon => ":",
             Token::SemiColon => ";",
             Token::Ret => "return",
@@ -83,16 +110,28 @@ impl fmt::Display for Token {
             Token::While => "while",
             Token::Val => "val",
             Token::Var => "var",
-            Token::Str(_) => "str",
-            Token::I32(_) => "i32",
-            Token::F64(_) => "f64",
+            Token::Import => "import",
+            Token::Pub => "pub",
+            Token::As => "as",
+            Token::Str(_) | Token::StrStart(_) | Token::StrMiddle(_) | Token::StrEnd(_) => "str",
+            Token::Int(_) => "integer",
+            Token::Float(_) => "float",
             Token::Bool(_) => "bool",
+            Token::Error => "error",
         };
 
         f.write_str(tok)
     }
 }
 
+/// Text of a string literal with escape sequences replaced
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct StrText {
+    pub value: String,
+    // Text as written in the source, like `\u{41}` for `A`
+    pub raw: String,
+}
+
 impl Token {
     pub fn to_tree(self) -> TokenTree {
         TokenTree::Token(self)
@@ -104,6 +143,8 @@ pub(crate) enum Delim {
     Paren,
     Brace,
     Bracket,
+    // Interpolated string, its text parts surround the code
+    Str,
 }
 
 #[derive(Clone, Debug)]
@@ -0,0 +1,202 @@
+use chumsky::prelude::*;
+
+use crate::core::Spanned;
+
+use super::token::Token;
+
+/// Code between tokens. Parsers skip it, tools printing code back keep it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Trivia {
+    Whitespace(String),
+    /// `// ...` without the line break, `/// ...` is a doc comment
+    LineComment(String),
+    /// `/* ... */`, comments are not nested
+    BlockComment(String),
+}
+
+impl Trivia {
+    pub fn is_doc(&self) -> bool {
+        matches!(self, Self::LineComment(comment) if comment.starts_with("///"))
+    }
+
+    pub fn text(&self) -> &str {
+        match self {
+            Self::Whitespace(text) | Self::LineComment(text) | Self::BlockComment(text) => text,
+        }
+    }
+}
+
+/// Token with the trivia around it
+#[derive(Debug, Clone)]
+pub(crate) struct TriviaToken {
+    pub token: Spanned<Token>,
+    /// Trivia between the previous token and this one, except the trailing trivia of the previous token
+    pub leading: Vec<Spanned<Trivia>>,
+    /// Trivia after the token up to the end of its line
+    pub trailing: Vec<Spanned<Trivia>>,
+}
+
+/// Every character of the source is either in a token or in trivia
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Lexed {
+    pub tokens: Vec<TriviaToken>,
+    /// Trivia after the last token
+    pub trailing: Vec<Spanned<Trivia>>,
+}
+
+pub(super) fn trivia_lexer() -> impl Parser<char, Trivia, Error = Simple<char>> + Clone {
+    let whitespace = filter(|c: &char| c.is_whitespace())
+        .repeated()
+        .at_least(1)
+        .collect()
+        .map(Trivia::Whitespace);
+    let line_comment = just("//")
+        .ignore_then(filter(|c| *c != '\n').repeated())
+        .collect::<String>()
+        .map(|text| Trivia::LineComment(format!("//{text}")));
+    let block_comment = just("/*")
+        .ignore_then(take_until(just("*/")))
+        .map(|(text, _)| Trivia::BlockComment(format!("/*{}*/", String::from_iter(text))))
+        .labelled("comment");
+
+    whitespace.or(line_comment).or(block_comment)
+}
+
+/// Attaches trivia found between the tokens to them. `trivia` is every
+/// trivia of the source in order, it fills gaps between the tokens
+pub(super) fn attach_trivia(tokens: Vec<Spanned<Token>>, trivia: Vec<Spanned<Trivia>>) -> Lexed {
+    let mut trivia = trivia.into_iter().peekable();
+    let mut lexed = Lexed::default();
+    for token in tokens {
+        let mut leading = Vec::new();
+        while let Some(item) = trivia.next_if(|(_, span)| span.end <= token.1.start) {
+            leading.push(item);
+        }
+        if let Some(prev) = lexed.tokens.last_mut() {
+            prev.trailing = split_trailing(&mut leading);
+        }
+        lexed.tokens.push(TriviaToken {
+            token,
+            leading,
+            trailing: Vec::new(),
+        });
+    }
+
+    let mut rest = trivia.collect();
+    if let Some(last) = lexed.tokens.last_mut() {
+        last.trailing = split_trailing(&mut rest);
+    }
+    lexed.trailing = rest;
+
+    lexed
+}
+
+/// Removes and returns trivia before the first line break
+fn split_trailing(trivia: &mut Vec<Spanned<Trivia>>) -> Vec<Spanned<Trivia>> {
+    let split = trivia
+        .iter()
+        .position(|(trivia, _)| trivia.text().contains('\n'))
+        .unwrap_or(trivia.len());
+    let rest = trivia.split_off(split);
+    std::mem::replace(trivia, rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::Span;
+    use crate::lexer::Lexer;
+
+    use super::*;
+
+    fn lex(source: &str, offset: usize) -> Lexed {
+        Lexer::new().scan_lossless(source, offset).unwrap()
+    }
+
+    /// Spans and texts of the trivia
+    fn pieces(trivia: &[Spanned<Trivia>]) -> Vec<(Span, Option<&str>)> {
+        trivia
+            .iter()
+            .map(|(trivia, span)| (span.clone(), Some(trivia.text())))
+            .collect()
+    }
+
+    fn texts(trivia: &[Spanned<Trivia>]) -> Vec<&str> {
+        trivia.iter().map(|(trivia, _)| trivia.text()).collect()
+    }
+
+    #[test]
+    fn covers_every_character() {
+        let sources = [
+            "",
+            "  \n\n",
+            "// only a comment",
+            "val a = 1;",
+            "/// doc\nfun main() {\n    // inside\n\n    foo(1, // arg\n        2);\n}\n",
+            "val s = \"ä // not a comment\"; /* é\n */ val b = /* inline */ 2;  ",
+        ];
+
+        for source in sources {
+            for offset in [0, 7] {
+                let lexed = lex(source, offset);
+                let mut spans = Vec::new();
+                for token in lexed.tokens.iter() {
+                    spans.extend(pieces(&token.leading));
+                    spans.push((token.token.1.clone(), None));
+                    spans.extend(pieces(&token.trailing));
+                }
+                spans.extend(pieces(&lexed.trailing));
+
+                let chars = source.chars().collect::<Vec<_>>();
+                let mut start = offset;
+                for (span, text) in spans {
+                    assert_eq!(span.start, start, "gap before {span:?} in {source:?}");
+                    if let Some(text) = text {
+                        let expected = &chars[span.start - offset..span.end - offset];
+                        assert_eq!(text, String::from_iter(expected), "in {source:?}");
+                    }
+                    start = span.end;
+                }
+                assert_eq!(start, offset + chars.len(), "end of {source:?} is lost");
+            }
+        }
+    }
+
+    #[test]
+    fn trailing_ends_at_line_break() {
+        let lexed = lex("a // one\n// two\nb /* three */\n", 0);
+        let [a, b] = &lexed.tokens[..] else {
+            panic!("expected 2 tokens, got {:?}", lexed.tokens);
+        };
+
+        assert!(a.leading.is_empty());
+        assert_eq!(texts(&a.trailing), [" ", "// one"]);
+        assert_eq!(texts(&b.leading), ["\n", "// two", "\n"]);
+        assert_eq!(texts(&b.trailing), [" ", "/* three */"]);
+        assert_eq!(texts(&lexed.trailing), ["\n"]);
+    }
+
+    #[test]
+    fn multiline_comment_is_leading() {
+        let lexed = lex("  a /* one\ntwo */ b", 0);
+        let [a, b] = &lexed.tokens[..] else {
+            panic!("expected 2 tokens, got {:?}", lexed.tokens);
+        };
+
+        assert_eq!(texts(&a.leading), ["  "]);
+        assert_eq!(texts(&a.trailing), [" "]);
+        assert_eq!(texts(&b.leading), ["/* one\ntwo */", " "]);
+        assert!(b.trailing.is_empty());
+        assert!(lexed.trailing.is_empty());
+    }
+
+    #[test]
+    fn doc_comments() {
+        let lexed = lex("/// doc\n// plain\nfun", 0);
+        let docs = lexed.tokens[0]
+            .leading
+            .iter()
+            .map(|(trivia, _)| trivia.is_doc())
+            .collect::<Vec<_>>();
+        assert_eq!(docs, [true, false, false, false]);
+    }
+}
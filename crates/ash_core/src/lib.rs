@@ -1,6 +1,10 @@
 pub mod prelude;
+mod analysis;
 mod ashery;
+mod codegen;
 mod core;
+mod dump;
+mod format;
 mod hir;
 mod mir;
 mod lexer;
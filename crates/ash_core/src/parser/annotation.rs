@@ -6,7 +6,7 @@ use chumsky::prelude::*;
 
 use super::{
     common::ident_parser,
-    function::{function_parser, function_proto_parser},
+    function::function_parser,
     Stmt, StmtRecursive,
 };
 
@@ -16,7 +16,7 @@ pub(super) fn annotation_parser<'a>(
     just(Token::At)
         .ignore_then(ident_parser().delimited_by(just(Token::LBracket), just(Token::RBracket)))
         .map_with_span(|name, span| (name, span))
-        .then(function_parser(stmt).or(function_proto_parser())) // Support only for functions for now
+        .then(function_parser(stmt)) // Support only for functions for now
         .map_with_span(|((name, name_span), stmt), span| {
             (
                 Stmt::Annotation((Annotation::new(name), name_span), Box::new(stmt)),
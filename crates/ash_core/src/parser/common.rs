@@ -39,25 +39,110 @@ pub(super) fn expr_block_parser<'a>(
     stmt: StmtRecursive<'a>,
 ) -> impl Parser<Token, Expr, Error = Simple<Token>> + 'a + Clone {
     just(Token::LBrace)
-        .ignore_then(stmt.repeated())
+        .ignore_then(stmt_list_parser(stmt))
         .then_ignore(just(Token::RBrace))
         .map(Expr::Block)
-    // .recover_with(skip_until([Token::LBrace, Token::RBrace], |_| Expr::Block(Vec::new())))
-    // .recover_with(nested_delimiters(Token::LBrace, Token::RBrace, [], |_| Expr::Block(Vec::new())))
 }
 
 pub(super) fn stmt_block_parser<'a>(
     stmt: StmtRecursive<'a>,
 ) -> impl Parser<Token, Spanned<Stmt>, Error = Simple<Token>> + 'a + Clone {
     just(Token::LBrace)
-        .ignore_then(stmt.repeated())
+        .ignore_then(stmt_list_parser(stmt))
         .then_ignore(just(Token::RBrace))
         .map_with_span(|stmts, span| (Stmt::Block(stmts), span))
-    // .recover_with(skip_until([Token::LBrace, Token::RBrace], |_| Expr::Block(Vec::new())))
-    // .recover_with(nested_delimiters(Token::LBrace, Token::RBrace, [], |_| Expr::Block(Vec::new())))
 }
 
-pub(super) fn type_parser() -> impl Parser<Token, Ty, Error = Simple<Token>> {
+/// Statements of a block or of a module. Statement with a syntax error becomes
+/// `Stmt::Error`, so the statements following it are still parsed
+pub(super) fn stmt_list_parser<'a, P>(
+    stmt: P,
+) -> impl Parser<Token, Vec<Spanned<Stmt>>, Error = Simple<Token>> + 'a + Clone
+where
+    P: Parser<Token, Spanned<Stmt>, Error = Simple<Token>> + Clone + 'a,
+{
+    recover(stmt, skip_stmt_parser(), |span| (Stmt::Error, span)).repeated()
+}
+
+/// Reports the error of `parser` and skips the tokens matched by `skip` when it fails,
+/// `fallback` creates the output for the skipped span. Fails with the error of `parser`
+/// when there is nothing to skip
+// Errors of `parser` are `Simple<Token>`, kept in the output until the skipped tokens are consumed
+#[allow(clippy::result_large_err)]
+pub(super) fn recover<'a, O, P, S>(
+    parser: P,
+    skip: S,
+    fallback: fn(Span) -> O,
+) -> impl Parser<Token, O, Error = Simple<Token>> + 'a + Clone
+where
+    O: Clone + 'a,
+    P: Parser<Token, O, Error = Simple<Token>> + Clone + 'a,
+    S: Parser<Token, (), Error = Simple<Token>> + Clone + 'a,
+{
+    parser
+        .map(Ok)
+        .or_else(|err| Ok(Err(err)))
+        .then_with(move |result| match result {
+            Ok(out) => empty().to(Ok(out)).boxed(),
+            Err(err) => skip
+                .clone()
+                .map_err({
+                    let err = err.clone();
+                    move |_| err.clone()
+                })
+                .to(Err(err))
+                .boxed(),
+        })
+        .validate(move |result, span, emit| match result {
+            Ok(out) => out,
+            Err(err) => {
+                emit(err);
+                fallback(span)
+            }
+        })
+}
+
+/// Rest of a statement with a syntax error, up to its `;` or its braced body
+/// with `else` branches. `}` closing the block the statement is in is kept
+pub(super) fn skip_stmt_parser() -> impl Parser<Token, (), Error = Simple<Token>> + Clone {
+    let line = none_of([Token::SemiColon, Token::LBrace, Token::RBrace]).repeated();
+    let body = braced_parser()
+        .then(
+            just(Token::Else)
+                .then(line.clone())
+                .then(braced_parser())
+                .repeated(),
+        )
+        .then(just(Token::SemiColon).or_not())
+        .ignored();
+    let end = just(Token::SemiColon).ignored().or(body);
+
+    line.at_least(1)
+        .then(end.clone().or_not())
+        .ignored()
+        .or(end)
+}
+
+/// Rest of an expression with a syntax error, up to the `;` or `}` ending its statement
+pub(super) fn skip_expr_parser() -> impl Parser<Token, (), Error = Simple<Token>> + Clone {
+    braced_parser()
+        .or(none_of([Token::SemiColon, Token::LBrace, Token::RBrace]).ignored())
+        .repeated()
+        .ignored()
+}
+
+/// Tokens between matching braces
+fn braced_parser() -> impl Parser<Token, (), Error = Simple<Token>> + Clone {
+    recursive(|braced| {
+        braced
+            .or(none_of([Token::LBrace, Token::RBrace]).ignored())
+            .repeated()
+            .delimited_by(just(Token::LBrace), just(Token::RBrace))
+            .ignored()
+    })
+}
+
+pub(super) fn type_parser() -> impl Parser<Token, Ty, Error = Simple<Token>> + Clone {
     ident_parser().map::<Ty, _>(From::from)
 }
 
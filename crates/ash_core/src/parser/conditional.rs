@@ -50,7 +50,7 @@ pub(super) fn stmt_if_parser<'a>(
 
 pub(super) fn expr_if_parser<'a>(
     stmt: StmtRecursive<'a>,
-) -> impl Parser<Token, Expr, Error = Simple<Token>> + 'a {
+) -> impl Parser<Token, Expr, Error = Simple<Token>> + Clone + 'a {
     let then = just(Token::If)
         .ignore_then(expression_parser())
         .map_with_span(|cond, span| (cond, span))
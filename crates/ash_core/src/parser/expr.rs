@@ -1,14 +1,14 @@
 use crate::{
-    core::{next_id, Id, Spanned},
+    core::{next_id, Id, Span, Spanned},
     lexer::token::Token,
-    ty::Value,
+    ty::{Ty, Value},
 };
 use chumsky::prelude::*;
 
 use super::{
-    common::ident_parser,
     function::call_parser,
-    literal::literal_parser,
+    import::path_parser,
+    literal::{interpolated_parser, literal_parser},
     operator::{operator_parser, BinaryOp, UnaryOp},
     stmt::Stmt,
     If,
@@ -16,8 +16,13 @@ use super::{
 
 #[derive(Debug, Clone)]
 pub(crate) enum Expr {
-    Variable(Id, String),
-    Literal(Value),
+    Variable(Id, Spanned<String>),
+    // Item of an imported module
+    Path(Id, Spanned<Vec<String>>),
+    // Span of the literal token, the formatter prints literals as they are written there
+    Literal(Value, Span),
+    // `"text${expr}text"`
+    Interpolated(Vec<StrPart>),
     Call {
         callee: Box<Expr>,
         args: Vec<Expr>,
@@ -29,11 +34,26 @@ pub(crate) enum Expr {
         op: UnaryOp,
         right: Box<Expr>,
     },
+    // `expr as ty`
+    Cast {
+        expr: Box<Expr>,
+        ty: Ty,
+    },
     Binary {
         left: Box<Expr>,
         op: BinaryOp,
         right: Box<Expr>,
     },
+    // Expression with a syntax error, it is reported by the parser
+    Error,
+}
+
+/// Part of an interpolated string
+#[derive(Debug, Clone)]
+pub(crate) enum StrPart {
+    // Span of the token the text is a part of
+    Text(String, Span),
+    Expr(Expr),
 }
 
 impl Expr {
@@ -45,17 +65,26 @@ impl Expr {
     }
 }
 
+/// Variable or a path to an item of an imported module
+pub(super) fn variable_parser() -> impl Parser<Token, Expr, Error = Simple<Token>> + Clone {
+    path_parser().map_with_span(|mut path, span| match path.len() {
+        1 => Expr::Variable(next_id(), (path.remove(0), span)),
+        _ => Expr::Path(next_id(), (path, span)),
+    })
+}
+
 pub(super) type ExprRecursive<'a> = Recursive<'a, Token, Expr, Simple<Token>>;
 
 pub(super) fn expression_parser() -> impl Parser<Token, Expr, Error = Simple<Token>> + Clone {
     recursive(|expr| {
-        let variable = ident_parser().map(|name| Expr::Variable(next_id(), name));
+        let variable = variable_parser();
         let group = expr
             .clone()
             .delimited_by(just(Token::LParen), just(Token::RParen))
             .map(|e| Expr::Group(Box::new(e)));
 
         let expr = literal_parser()
+            .or(interpolated_parser(expr.clone()))
             .or(call_parser(expr.clone()))
             .or(variable)
             .or(group);
@@ -5,15 +5,15 @@ use crate::ty::Ty;
 use crate::{core::next_id, lexer::token::Token};
 use chumsky::prelude::*;
 
-use super::common::{type_parser, stmt_block_parser};
-use super::expression_parser;
+use super::common::{recover, skip_expr_parser, stmt_block_parser, type_parser};
+use super::{expression_parser, expr::variable_parser};
 use super::{
     common::{ident_parser, ident_with_suffix_parser},
     expr::{Expr, ExprRecursive},
     stmt::{stmt_expression_parser, StmtRecursive},
 };
 
-pub(super) fn function_proto_parser() -> impl Parser<Token, Spanned<Stmt>, Error = Simple<Token>> {
+fn function_proto_parser() -> impl Parser<Token, Spanned<Stmt>, Error = Simple<Token>> {
     let ident = ident_parser();
 
     let name = ident.clone().labelled("function name");
@@ -49,6 +49,7 @@ pub(super) fn function_proto_parser() -> impl Parser<Token, Spanned<Stmt>, Error
                 name,
                 params,
                 ty,
+                public: false,
             };
 
             (Stmt::ProtoFunction(proto), span)
@@ -56,19 +57,27 @@ pub(super) fn function_proto_parser() -> impl Parser<Token, Spanned<Stmt>, Error
         .labelled("function")
 }
 
+/// Function without a body is a prototype of a `@[builtin]` function
 pub(super) fn function_parser<'a>(
     stmt: StmtRecursive<'a>,
 ) -> impl Parser<Token, Spanned<Stmt>, Error = Simple<Token>> + 'a {
     let body = just(Token::Arrow)
-        .ignore_then(expression_parser())
-        .then_ignore(just(Token::SemiColon))
+        .ignore_then(recover(
+            expression_parser().then_ignore(just(Token::SemiColon)),
+            skip_expr_parser().then_ignore(just(Token::SemiColon)),
+            |_| Expr::Error,
+        ))
         .map_with_span(|expr, span| (Stmt::Expression(expr), span))
         .or(stmt_block_parser(stmt))
         .map_with_span(|stmt, span| (stmt.0, span));
 
     function_proto_parser()
-        .then(body)
+        .then(body.or_not())
         .map_with_span(|(proto, body), span| {
+            let body = match body {
+                Some(body) => body,
+                None => return proto,
+            };
             let fun = Function {
                 body,
                 proto: (proto.0.proto_fun(), proto.1),
@@ -82,7 +91,7 @@ pub(super) fn function_parser<'a>(
 pub(super) fn call_parser<'a>(
     expr: ExprRecursive<'a>,
 ) -> impl Parser<Token, Expr, Error = Simple<Token>> + Clone + 'a {
-    let callee = ident_parser().map(|name| Expr::Variable(next_id(), name));
+    let callee = variable_parser();
     let args = expr
         .clone()
         .separated_by(just(Token::Comma))
@@ -0,0 +1,49 @@
+use crate::{core::Spanned, lexer::token::Token};
+use chumsky::prelude::*;
+
+use super::{common::ident_parser, Stmt};
+
+#[derive(Debug, Clone)]
+pub(crate) struct Import {
+    pub module: Vec<String>,
+    // `None` when the module itself is imported
+    pub items: Option<Vec<Spanned<String>>>,
+}
+
+impl Import {
+    pub fn module_name(&self) -> String {
+        self.module.join(".")
+    }
+
+    /// Name the module is available under
+    pub fn alias(&self) -> &str {
+        self.module.last().unwrap()
+    }
+}
+
+pub(super) fn import_parser() -> impl Parser<Token, Spanned<Stmt>, Error = Simple<Token>> {
+    let items = ident_parser()
+        .map_with_span(|name, span| (name, span))
+        .separated_by(just(Token::Comma))
+        .allow_trailing()
+        .at_least(1)
+        .delimited_by(just(Token::LBrace), just(Token::RBrace))
+        .labelled("imported items");
+
+    just(Token::Import)
+        .ignore_then(path_parser().labelled("module path"))
+        .then(just(Token::Dot).ignore_then(items).or_not())
+        .then_ignore(just(Token::SemiColon))
+        .map_with_span(|(module, items), span| (Stmt::Import(Import { module, items }), span))
+        .labelled("import")
+}
+
+/// Identifiers separated by dots
+pub(super) fn path_parser() -> impl Parser<Token, Vec<String>, Error = Simple<Token>> + Clone {
+    ident_parser()
+        .then(just(Token::Dot).ignore_then(ident_parser()).repeated())
+        .map(|(first, mut rest)| {
+            rest.insert(0, first);
+            rest
+        })
+}
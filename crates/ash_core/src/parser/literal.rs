@@ -1,15 +1,141 @@
 use chumsky::prelude::*;
 
-use crate::{lexer::token::Token, ty::Value};
+use crate::{
+    core::{error_code, Span},
+    lexer::token::Token,
+    ty::{Ty, Value},
+};
 
-use super::expr::Expr;
+use super::expr::{Expr, ExprRecursive, StrPart};
 
+#[allow(clippy::result_large_err)]
 pub(super) fn literal_parser() -> impl Parser<Token, Expr, Error = Simple<Token>> + Clone {
     select! {
-        Token::I32(value) => Expr::Literal(Value::I32(value.parse().unwrap())),
-        Token::F64(value) => Expr::Literal(Value::F64(value.parse().unwrap())),
-        Token::Str(value) => Expr::Literal(Value::String(value)),
-        Token::Bool(value) => Expr::Literal(Value::Bool(value)),
+        Token::Str(text) => Value::String(text.value),
+        Token::Bool(value) => Value::Bool(value),
     }
+    .map_with_span(Expr::Literal)
+    .or(number_parser(false))
     .labelled("literal")
 }
+
+/// Number preceded by `-`, so the smallest values of signed types fit into their range
+pub(super) fn negative_number_parser() -> impl Parser<Token, Expr, Error = Simple<Token>> + Clone {
+    just(Token::Minus).ignore_then(number_parser(true))
+}
+
+// Invalid numbers are reported as `Simple<Token>` like any other syntax error
+#[allow(clippy::result_large_err)]
+fn number_parser(negative: bool) -> impl Parser<Token, Expr, Error = Simple<Token>> + Clone {
+    select! {
+        Token::Int(text) => (text, Ty::I32),
+        Token::Float(text) => (text, Ty::F64),
+    }
+    .try_map(move |(text, default_ty), span: Span| {
+        number(&text, default_ty, negative)
+            .map(|value| Expr::Literal(value, span.clone()))
+            .map_err(|msg| Simple::custom(span, msg).with_label(error_code::INVALID_LITERAL))
+    })
+}
+
+/// Converts source text of a number literal to a value of the type of its suffix
+fn number(text: &str, default_ty: Ty, negative: bool) -> Result<Value, String> {
+    if has_misplaced_separator(text) {
+        return Err(format!(
+            "Separator `_` has to be between digits in `{text}`"
+        ));
+    }
+
+    let digits = text.replace('_', "");
+    let (radix, digits) = match digits.get(..2) {
+        Some("0x") => (16, &digits[2..]),
+        Some("0o") => (8, &digits[2..]),
+        Some("0b") => (2, &digits[2..]),
+        _ => (10, digits.as_str()),
+    };
+    // Hexadecimal digits can end like a float suffix, the lexer does not allow one there
+    let suffix = Ty::NUMBERS.into_iter().find_map(|ty| {
+        let suffix = ty.to_string();
+        let digits = digits.strip_suffix(&suffix)?;
+        (radix == 10 || !ty.is_float()).then_some((digits, ty))
+    });
+    let (digits, ty) = suffix.unwrap_or((digits, default_ty));
+    let sign = if negative { "-" } else { "" };
+    let out_of_range = || format!("Literal `{sign}{text}` does not fit into type {ty}");
+
+    if ty.is_float() {
+        let value = format!("{sign}{digits}")
+            .parse::<f64>()
+            .map_err(|_| out_of_range())?;
+        return match ty {
+            Ty::F32 if (value as f32).is_finite() => Ok(Value::F32(value as f32)),
+            Ty::F64 if value.is_finite() => Ok(Value::F64(value)),
+            _ => Err(out_of_range()),
+        };
+    }
+
+    // Digits are checked by the lexer, so only too long literals fail to parse
+    let value = i128::from_str_radix(digits, radix).map_err(|_| out_of_range())?;
+    let value = if negative { -value } else { value };
+    let value = match ty {
+        Ty::I8 => i8::try_from(value).map(Value::I8).ok(),
+        Ty::I16 => i16::try_from(value).map(Value::I16).ok(),
+        Ty::I32 => i32::try_from(value).map(Value::I32).ok(),
+        Ty::I64 => i64::try_from(value).map(Value::I64).ok(),
+        Ty::U8 => u8::try_from(value).map(Value::U8).ok(),
+        Ty::U16 => u16::try_from(value).map(Value::U16).ok(),
+        Ty::U32 => u32::try_from(value).map(Value::U32).ok(),
+        Ty::U64 => u64::try_from(value).map(Value::U64).ok(),
+        _ => None,
+    };
+
+    value.ok_or_else(out_of_range)
+}
+
+/// `_` can only be between two digits, so `1__0`, `1_` and `0x_1` are rejected
+fn has_misplaced_separator(text: &str) -> bool {
+    let radix = match text.get(..2) {
+        Some("0x") => 16,
+        Some("0o") => 8,
+        Some("0b") => 2,
+        _ => 10,
+    };
+    let chars = text.chars().collect::<Vec<_>>();
+    let is_digit = |i: Option<usize>| {
+        i.and_then(|i| chars.get(i))
+            .is_some_and(|c| c.is_digit(radix))
+    };
+
+    chars
+        .iter()
+        .enumerate()
+        .any(|(i, c)| *c == '_' && !(is_digit(i.checked_sub(1)) && is_digit(Some(i + 1))))
+}
+
+/// String with expressions between its text parts
+// `select!` fails with `Simple<Token>`, the error type of every parser
+#[allow(clippy::result_large_err)]
+pub(super) fn interpolated_parser<'a>(
+    expr: ExprRecursive<'a>,
+) -> impl Parser<Token, Expr, Error = Simple<Token>> + Clone + 'a {
+    let start = select! { Token::StrStart(text) => text.value }.map_with_span(StrPart::Text);
+    let middle = select! { Token::StrMiddle(text) => text.value }.map_with_span(StrPart::Text);
+    let end = select! { Token::StrEnd(text) => text.value }.map_with_span(StrPart::Text);
+    let expr = expr.map(StrPart::Expr);
+
+    start
+        .then(expr.clone().then(middle).repeated())
+        .then(expr)
+        .then(end)
+        .map(|(((start, middle), expr), end)| {
+            let mut parts = vec![start];
+            for (expr, text) in middle {
+                parts.push(expr);
+                parts.push(text);
+            }
+            parts.push(expr);
+            parts.push(end);
+            Expr::Interpolated(parts)
+        })
+        .labelled("str")
+}
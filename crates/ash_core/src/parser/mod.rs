@@ -1,5 +1,6 @@
 pub use conditional::*;
 pub use expr::*;
+pub(crate) use import::Import;
 pub use stmt::*;
 
 mod annotation;
@@ -7,6 +8,7 @@ pub(crate) mod common;
 pub mod conditional;
 pub(crate) mod expr;
 mod function;
+mod import;
 mod literal;
 mod loops;
 pub(crate) mod operator;
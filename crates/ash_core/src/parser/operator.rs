@@ -1,13 +1,20 @@
+use core::fmt;
+use std::iter::Peekable;
+
 use chumsky::prelude::*;
 
-use crate::lexer::token::Token;
+use crate::{
+    core::{error_code, Spanned},
+    lexer::token::Token,
+};
 
-use super::expr::Expr;
+use super::{common::type_parser, expr::Expr, literal::negative_number_parser};
 
 #[derive(Debug, Clone)]
 pub(crate) enum UnaryOp {
     Neg,
     Not,
+    BitNot,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -25,113 +32,314 @@ pub(crate) enum BinaryOp {
     Lte,
     LogicAnd,
     LogicOr,
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            Self::Neg => "-",
+            Self::Not => "!",
+            Self::BitNot => "~",
+        };
+
+        f.write_str(op)
+    }
+}
+
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            Self::Sum => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::Rem => "%",
+            Self::Equal => "==",
+            Self::NotEqual => "!=",
+            Self::Gt => ">",
+            Self::Lt => "<",
+            Self::Gte => ">=",
+            Self::Lte => "<=",
+            Self::LogicAnd => "&&",
+            Self::LogicOr => "||",
+            Self::BitAnd => "&",
+            Self::BitOr => "|",
+            Self::BitXor => "^",
+            Self::Shl => "<<",
+            Self::Shr => ">>",
+        };
+
+        f.write_str(op)
+    }
+}
+
+/// How operators of the same level group
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Assoc {
+    /// `a - b - c` is `(a - b) - c`
+    Left,
+    /// `a < b < c` is an error, parentheses are required
+    None,
 }
 
+#[derive(Debug, Clone, Copy)]
+struct Precedence {
+    level: u8,
+    assoc: Assoc,
+}
+
+const fn left(level: u8) -> Precedence {
+    Precedence {
+        level,
+        assoc: Assoc::Left,
+    }
+}
+
+const fn non_assoc(level: u8) -> Precedence {
+    Precedence {
+        level,
+        assoc: Assoc::None,
+    }
+}
+
+/// Binary operators, operators of a higher level bind tighter.
+/// New operators like range ones only need a row here
+///
+/// | Level | Operators            | Associativity |
+/// |-------|----------------------|---------------|
+/// | 1     | `\|\|`               | left          |
+/// | 2     | `&&`                 | left          |
+/// | 3     | `==` `!=`            | left          |
+/// | 4     | `<` `>` `<=` `>=`    | none          |
+/// | 5     | `\|`                 | left          |
+/// | 6     | `^`                  | left          |
+/// | 7     | `&`                  | left          |
+/// | 8     | `<<` `>>`            | left          |
+/// | 9     | `+` `-`              | left          |
+/// | 10    | `*` `/` `%`          | left          |
+///
+/// Bitwise operators bind tighter than comparisons, so `flags & MASK == 0`
+/// needs no parentheses. `as` binds tighter than any binary operator,
+/// prefix `-`, `!` and `~` bind tighter than `as`
+const BINARY_OPERATORS: [(Token, BinaryOp, Precedence); 18] = [
+    (Token::BarBar, BinaryOp::LogicOr, left(1)),
+    (Token::AndAnd, BinaryOp::LogicAnd, left(2)),
+    (Token::DoubleEqual, BinaryOp::Equal, left(3)),
+    (Token::NotEqual, BinaryOp::NotEqual, left(3)),
+    (Token::Lt, BinaryOp::Lt, non_assoc(4)),
+    (Token::Gt, BinaryOp::Gt, non_assoc(4)),
+    (Token::Lte, BinaryOp::Lte, non_assoc(4)),
+    (Token::Gte, BinaryOp::Gte, non_assoc(4)),
+    (Token::Bar, BinaryOp::BitOr, left(5)),
+    (Token::Caret, BinaryOp::BitXor, left(6)),
+    (Token::Amp, BinaryOp::BitAnd, left(7)),
+    (Token::Shl, BinaryOp::Shl, left(8)),
+    (Token::Shr, BinaryOp::Shr, left(8)),
+    (Token::Plus, BinaryOp::Sum, left(9)),
+    (Token::Minus, BinaryOp::Sub, left(9)),
+    (Token::Asterisk, BinaryOp::Mul, left(10)),
+    (Token::Slash, BinaryOp::Div, left(10)),
+    (Token::Percent, BinaryOp::Rem, left(10)),
+];
+
 pub(super) fn operator_parser<'a, P>(
     expr: P,
 ) -> impl Parser<Token, Expr, Error = Simple<Token>> + 'a
 where
     P: Parser<Token, Expr, Error = Simple<Token>> + Clone + 'a,
 {
-    binary_parser(unary_parser(expr))
+    binary_parser(cast_parser(unary_parser(expr)))
 }
 
+/// Prefix operators can be mixed, like `-!x`
 fn unary_parser<'a, P>(expr: P) -> impl Parser<Token, Expr, Error = Simple<Token>> + Clone + 'a
 where
     P: Parser<Token, Expr, Error = Simple<Token>> + Clone + 'a,
 {
-    let minus = just(Token::Minus)
-        .repeated()
-        .then(expr.clone())
-        .foldr(|_, rhs| Expr::Unary {
-            op: UnaryOp::Neg,
-            right: Box::new(rhs),
-        });
-    let not = just(Token::Bang)
-        .repeated()
-        .then(expr)
-        .foldr(|_, rhs| Expr::Unary {
-            op: UnaryOp::Not,
-            right: Box::new(rhs),
-        });
-
-    minus.or(not)
+    recursive(|unary| {
+        let op = just(Token::Minus)
+            .to(UnaryOp::Neg)
+            .or(just(Token::Bang).to(UnaryOp::Not))
+            .or(just(Token::Tilde).to(UnaryOp::BitNot));
+
+        negative_number_parser()
+            .or(op.then(unary).map(|(op, right)| Expr::Unary {
+                op,
+                right: Box::new(right),
+            }))
+            .or(expr)
+    })
 }
 
+/// `as` binds tighter than binary operators, but looser than unary ones
+fn cast_parser<'a, P>(expr: P) -> impl Parser<Token, Expr, Error = Simple<Token>> + Clone + 'a
+where
+    P: Parser<Token, Expr, Error = Simple<Token>> + Clone + 'a,
+{
+    expr.then(just(Token::As).ignore_then(type_parser()).repeated())
+        .foldl(|expr, ty| Expr::Cast {
+            expr: Box::new(expr),
+            ty,
+        })
+}
+
+/// Operands separated by operators, grouped by `climb`
+// The closure passes on the error of `climb`
+#[allow(clippy::result_large_err)]
 fn binary_parser<'a, P>(expr: P) -> impl Parser<Token, Expr, Error = Simple<Token>> + 'a
 where
     P: Parser<Token, Expr, Error = Simple<Token>> + Clone + 'a,
 {
-    let op = just(Token::Asterisk)
-        .to(BinaryOp::Mul)
-        .or(just(Token::Slash).to(BinaryOp::Div));
-    let product = expr
-        .clone()
+    let tokens = BINARY_OPERATORS
+        .into_iter()
+        .map(|(token, _, _)| token)
+        .collect::<Vec<_>>();
+    let op = one_of(tokens).map_with_span(|token, span| {
+        let (_, op, precedence) = BINARY_OPERATORS
+            .into_iter()
+            .find(|(op_token, _, _)| *op_token == token)
+            .expect("Operator token is in the table");
+        ((op, precedence), span)
+    });
+
+    expr.clone()
         .then(op.then(expr).repeated())
-        .foldl(|a, (op, b)| Expr::Binary {
-            left: Box::new(a),
-            op,
-            right: Box::new(b),
-        });
-
-    let op = just(Token::Plus)
-        .to(BinaryOp::Sum)
-        .or(just(Token::Minus).to(BinaryOp::Sub));
-    let sum = product
-        .clone()
-        .then(op.then(product).repeated())
-        .foldl(|a, (op, b)| Expr::Binary {
-            left: Box::new(a),
-            op,
-            right: Box::new(b),
-        });
-
-    let op = just(Token::DoubleEqual)
-        .to(BinaryOp::Equal)
-        .or(just(Token::NotEqual).to(BinaryOp::NotEqual));
-    let equality = sum
-        .clone()
-        .then(op.then(sum).repeated())
-        .foldl(|a, (op, b)| Expr::Binary {
-            left: Box::new(a),
-            op,
-            right: Box::new(b),
-        });
-   
-    let op = just(Token::AndAnd)
-        .to(BinaryOp::LogicAnd);
-    let logic_and = equality
-        .clone()
-        .then(op.then(equality).repeated())
-        .foldl(|a, (op, b)| Expr::Binary {
-            left: Box::new(a),
-            op,
-            right: Box::new(b),
-        });
-
-    let op = just(Token::BarBar).to(BinaryOp::LogicOr);
-    let logic_or = logic_and
-        .clone()
-        .then(op.then(logic_and).repeated())
-        .foldl(|a, (op, b)| Expr::Binary {
-            left: Box::new(a),
-            op,
-            right: Box::new(b),
-        });
-
-    let op = just(Token::Gt)
-        .to(BinaryOp::Gt)
-        .or(just(Token::Lt).to(BinaryOp::Gt))
-        .or(just(Token::Gte).to(BinaryOp::Gte))
-        .or(just(Token::Lte).to(BinaryOp::Lte));
-    let ord = logic_or
-        .clone()
-        .then(op.then(logic_or).repeated())
-        .foldl(|a, (op, b)| Expr::Binary {
-            left: Box::new(a),
+        .try_map(|(first, rest), _| climb(first, &mut rest.into_iter().peekable(), 0))
+}
+
+type Operator = Spanned<(BinaryOp, Precedence)>;
+
+/// Groups operands by precedence climbing. Consumes operators of at least
+/// `min_level`, an operator binding tighter than the previous one takes its
+/// right operand first
+// Error type of the parser is `Simple<Token>`, chumsky can not take a boxed one
+#[allow(clippy::result_large_err)]
+fn climb<I>(mut left: Expr, rest: &mut Peekable<I>, min_level: u8) -> Result<Expr, Simple<Token>>
+where
+    I: Iterator<Item = (Operator, Expr)>,
+{
+    while let Some((((_, precedence), _), _)) = rest.peek() {
+        if precedence.level < min_level {
+            break;
+        }
+        let (((op, precedence), _), mut right) = rest.next().unwrap();
+
+        if let Some((((_, next), _), _)) = rest.peek() {
+            if next.level > precedence.level {
+                right = climb(right, rest, precedence.level + 1)?;
+            }
+        }
+        if let Some((((next_op, next), span), _)) = rest.peek() {
+            if precedence.assoc == Assoc::None && next.level == precedence.level {
+                let msg = format!("`{op}` and `{next_op}` can not be chained, add parentheses");
+                return Err(
+                    Simple::custom(span.clone(), msg).with_label(error_code::UNEXPECTED_TOKEN)
+                );
+            }
+        }
+
+        left = Expr::Binary {
+            left: Box::new(left),
             op,
-            right: Box::new(b),
-        });
+            right: Box::new(right),
+        };
+    }
+
+    Ok(left)
+}
+
+#[cfg(test)]
+mod tests {
+    use chumsky::error::SimpleReason;
+
+    use super::*;
+    use crate::{ashery::parse, parser::Stmt};
+
+    /// Parses `code` as the value of a variable, every operation is wrapped in parentheses
+    fn grouped(code: &str) -> Result<String, Vec<String>> {
+        let ast = parse(&format!("val x = {code};"), 0).map_err(|errors| {
+            errors
+                .iter()
+                .map(|error| match error.reason() {
+                    SimpleReason::Custom(msg) => msg.clone(),
+                    _ => error.to_string(),
+                })
+                .collect::<Vec<_>>()
+        })?;
+        match &ast[0].0 {
+            Stmt::VariableDecl { value, .. } => Ok(group(value)),
+            stmt => panic!("Expected variable, got {stmt:?}"),
+        }
+    }
+
+    fn group(expr: &Expr) -> String {
+        match expr {
+            Expr::Binary { left, op, right } => format!("({} {op} {})", group(left), group(right)),
+            Expr::Unary { op, right } => format!("{op}{}", group(right)),
+            Expr::Cast { expr, ty } => format!("({} as {ty})", group(expr)),
+            Expr::Group(expr) => group(expr),
+            Expr::Variable(_, (name, _)) => name.clone(),
+            Expr::Literal(value, _) => value.to_string(),
+            expr => panic!("Unexpected expression {expr:?}"),
+        }
+    }
+
+    #[test]
+    fn levels() {
+        let cases = [
+            (
+                "1 + 2 * 3 - 4 / 2 % 3 << 1 & 7 | 8 ^ 2",
+                "(((((1 + (2 * 3)) - ((4 / 2) % 3)) << 1) & 7) | (8 ^ 2))",
+            ),
+            ("a | b ^ c & d", "(a | (b ^ (c & d)))"),
+            ("a || b && c == d", "(a || (b && (c == d)))"),
+            ("a == b < c | d", "(a == (b < (c | d)))"),
+            ("a << b + c", "(a << (b + c))"),
+            ("-a as i64 * b", "((-a as i64) * b)"),
+            ("(a + b) * c", "((a + b) * c)"),
+        ];
+        for (code, expected) in cases {
+            assert_eq!(grouped(code), Ok(expected.to_owned()), "{code}");
+        }
+    }
+
+    #[test]
+    fn left_associativity() {
+        let cases = [
+            ("a - b - c", "((a - b) - c)"),
+            ("a / b * c % d", "(((a / b) * c) % d)"),
+            ("a << b >> c", "((a << b) >> c)"),
+            ("a == b != c", "((a == b) != c)"),
+            ("a && b && c", "((a && b) && c)"),
+        ];
+        for (code, expected) in cases {
+            assert_eq!(grouped(code), Ok(expected.to_owned()), "{code}");
+        }
+    }
+
+    #[test]
+    fn comparisons_are_not_associative() {
+        let cases = [
+            ("a < b < c", "`<` and `<` can not be chained, add parentheses"),
+            ("a >= b <= c", "`>=` and `<=` can not be chained, add parentheses"),
+            ("x && a < b > c", "`<` and `>` can not be chained, add parentheses"),
+        ];
+        for (code, msg) in cases {
+            assert_eq!(grouped(code), Err(vec![msg.to_owned()]), "{code}");
+        }
+
+        assert_eq!(grouped("(a < b) < c"), Ok("((a < b) < c)".to_owned()));
+        assert_eq!(grouped("a < b == c < d"), Ok("((a < b) == (c < d))".to_owned()));
+    }
 
-    ord
+    #[test]
+    fn bitwise_operators_bind_tighter_than_comparisons() {
+        assert_eq!(grouped("flags & MASK == 0"), Ok("((flags & MASK) == 0)".to_owned()));
+        assert_eq!(grouped("a | b != c ^ d"), Ok("((a | b) != (c ^ d))".to_owned()));
+    }
 }
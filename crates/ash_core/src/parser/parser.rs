@@ -1,20 +1,38 @@
 use crate::{core::Spanned, lexer::token::Token, prelude::AshResult};
 use chumsky::{prelude::*, Parser as ChumskyParser, Stream};
 
-use super::stmt::{statement_parser, Stmt};
+use super::{
+    common::{recover, skip_stmt_parser},
+    stmt::{statement_parser, Stmt},
+};
 
 pub(crate) struct Parser<'a>(BoxedParser<'a, Token, Vec<Spanned<Stmt>>, Simple<Token>>);
 
 impl<'a> Parser<'a> {
     pub fn new() -> Self {
-        let parser = statement_parser().repeated();
+        // Unlike in blocks, `}` can be skipped as a part of a statement in the root scope
+        let skip = skip_stmt_parser().or(just(Token::RBrace).ignored());
+        let parser = recover(statement_parser(), skip, |span| (Stmt::Error, span)).repeated();
         Self(parser.then_ignore(end()).boxed())
     }
 
     // TODO: Return spanned Stmt
     pub fn parse(&self, tokens: Vec<Spanned<Token>>) -> AshResult<Vec<Spanned<Stmt>>, Token> {
-        let len = tokens.len();
-        let tokens = Stream::from_iter(len..len + 1, tokens.into_iter());
+        // End of input is right after the last token
+        let end = tokens.last().map(|(_, span)| span.end).unwrap_or_default();
+        let tokens = Stream::from_iter(end..end + 1, tokens.into_iter());
         self.0.parse(tokens)
     }
+
+    /// Parses every statement it can, statements with syntax errors are `Stmt::Error`.
+    /// The tree is empty when the parser can not recover, like at an unmatched `}`
+    pub fn parse_recovery(
+        &self,
+        tokens: Vec<Spanned<Token>>,
+    ) -> (Vec<Spanned<Stmt>>, Vec<Simple<Token>>) {
+        let end = tokens.last().map(|(_, span)| span.end).unwrap_or_default();
+        let tokens = Stream::from_iter(end..end + 1, tokens.into_iter());
+        let (ast, errors) = self.0.parse_recovery(tokens);
+        (ast.unwrap_or_default(), errors)
+    }
 }
@@ -12,9 +12,10 @@ use super::{
     annotation::annotation_parser,
     common::{break_parser, stmt_block_parser, expr_block_parser},
     expr::{expression_parser, Expr},
-    function::{function_parser, function_proto_parser, return_parser},
+    function::{function_parser, return_parser},
+    import::import_parser,
     loops::while_parser,
-    variable::{variable_assign_parse, variable_decl_parse}, If, stmt_if_parser, expr_if_parser,
+    variable::{variable_assign_parse, variable_decl_parse}, If, Import, stmt_if_parser, expr_if_parser,
 };
 
 #[derive(Debug, Clone)]
@@ -30,6 +31,7 @@ pub(crate) enum Stmt {
         ty: Option<Ty>,
         value: Expr,
         mutable: bool,
+        public: bool,
     },
     VariableAssign {
         id: Id,
@@ -39,7 +41,10 @@ pub(crate) enum Stmt {
     Block(Vec<Spanned<Stmt>>),
     Break(Option<Expr>),
     Return(Option<Expr>),
+    Import(Import),
     Expression(Expr),
+    /// Statement with a syntax error, it is reported by the parser
+    Error,
 }
 
 impl Stmt {
@@ -56,19 +61,63 @@ impl Stmt {
             _ => unreachable!("Not block statement"),
         }
     }
+
+    /// Id and name of the declared variable or function
+    pub fn declaration(&self) -> Option<(Id, &str)> {
+        match self {
+            Self::Annotation(_, stmt) => stmt.0.declaration(),
+            Self::ProtoFunction(proto) => Some((proto.id, &proto.name)),
+            Self::Function(fun) => Some((fun.proto.0.id, &fun.proto.0.name)),
+            Self::VariableDecl { id, name, .. } => Some((*id, name)),
+            _ => None,
+        }
+    }
+
+    pub fn is_public(&self) -> bool {
+        match self {
+            Self::Annotation(_, stmt) => stmt.0.is_public(),
+            Self::ProtoFunction(proto) => proto.public,
+            Self::Function(fun) => fun.proto.0.public,
+            Self::VariableDecl { public, .. } => *public,
+            _ => false,
+        }
+    }
+
+    /// Marks declaration as visible to modules importing it
+    pub fn set_public(&mut self) {
+        match self {
+            Self::Annotation(_, stmt) => stmt.0.set_public(),
+            Self::ProtoFunction(proto) => proto.public = true,
+            Self::Function(fun) => fun.proto.0.public = true,
+            Self::VariableDecl { public, .. } => *public = true,
+            _ => {}
+        }
+    }
 }
 
 pub(super) type StmtRecursive<'a> = Recursive<'a, Token, Spanned<Stmt>, Simple<Token>>;
 
-pub(super) fn statement_parser() -> impl Parser<Token, Spanned<Stmt>, Error = Simple<Token>> {
+pub(super) fn statement_parser() -> impl Parser<Token, Spanned<Stmt>, Error = Simple<Token>> + Clone {
     recursive(|stmt| {
         let expr = stmt_expression_parser(stmt.clone())
             .then_ignore(just(Token::SemiColon))
             .map_with_span(|expr, span| (Stmt::Expression(expr), span));
 
-        annotation_parser(stmt.clone())
+        let public = just(Token::Pub)
+            .ignore_then(
+                annotation_parser(stmt.clone())
+                    .or(function_parser(stmt.clone()))
+                    .or(variable_decl_parse(stmt.clone())),
+            )
+            .map_with_span(|(mut decl, _), span| {
+                decl.set_public();
+                (decl, span)
+            });
+
+        public
+            .or(import_parser())
+            .or(annotation_parser(stmt.clone()))
             .or(function_parser(stmt.clone()))
-            .or(function_proto_parser())
             .or(while_parser(stmt.clone()))
             .or(variable_decl_parse(stmt.clone()))
             .or(variable_assign_parse(stmt.clone()))
@@ -82,7 +131,7 @@ pub(super) fn statement_parser() -> impl Parser<Token, Spanned<Stmt>, Error = Si
 
 pub(super) fn stmt_expression_parser<'a>(
     stmt: StmtRecursive<'a>,
-) -> impl Parser<Token, Expr, Error = Simple<Token>> + 'a {
+) -> impl Parser<Token, Expr, Error = Simple<Token>> + Clone + 'a {
     expr_if_parser(stmt.clone())
         .or(expr_block_parser(stmt))
         .or(expression_parser())
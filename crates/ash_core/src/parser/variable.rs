@@ -6,7 +6,8 @@ use crate::{
 };
 
 use super::{
-    common::{ident_parser, type_parser},
+    common::{ident_parser, recover, skip_expr_parser, type_parser},
+    expr::Expr,
     stmt::{stmt_expression_parser, Stmt, StmtRecursive},
 };
 
@@ -18,7 +19,12 @@ pub(super) fn variable_decl_parse<'a>(
         .then(ident_parser())
         .then(just(Token::Colon).ignore_then(type_parser()).or_not())
         .then_ignore(just(Token::Equal))
-        .then(stmt_expression_parser(stmt).then_ignore(just(Token::SemiColon)))
+        // Variable with a syntax error in its value is still declared, its uses are not reported
+        .then(recover(
+            stmt_expression_parser(stmt).then_ignore(just(Token::SemiColon)),
+            skip_expr_parser().then_ignore(just(Token::SemiColon)),
+            |_| Expr::Error,
+        ))
         .map_with_span(|(((tok, name), ty), value), span| {
             (
                 Stmt::VariableDecl {
@@ -27,6 +33,7 @@ pub(super) fn variable_decl_parse<'a>(
                     ty,
                     value,
                     mutable: tok == Token::Var,
+                    public: false,
                 },
                 span,
             )
@@ -1,7 +1,12 @@
 pub use chumsky::error::SimpleReason;
 pub use chumsky::prelude::Simple;
 
-pub use crate::ashery::build;
-pub use crate::core::source::Source;
-pub use crate::core::{AshResult, Span};
+pub use crate::analysis::{Analysis, Symbol};
+pub use crate::ashery::{
+    build, build_library, build_project, check, check_project, write_chunk, write_out, Session,
+};
+pub use crate::core::source::{Source, SourceMap};
+pub use crate::dump::{dump, Stage};
+pub use crate::format::format;
+pub use crate::core::{error_code, AshResult, Span};
 pub use crate::mir::mir;
\ No newline at end of file
@@ -1,3 +1,5 @@
+pub(crate) mod module;
 pub(crate) mod resolver;
 
+pub(crate) use module::{sort_modules, Module, Modules};
 pub use resolver::*;
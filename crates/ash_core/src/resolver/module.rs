@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+use chumsky::{prelude::Simple, Error as _};
+
+use crate::{
+    core::{error_code, AshResult, Spanned},
+    parser::Stmt,
+};
+
+use super::Scope;
+
+/// Root scopes of already resolved modules by module name
+pub(crate) type Modules = HashMap<String, Scope>;
+
+/// Parsed source of a project
+pub(crate) struct Module {
+    pub name: String,
+    pub ast: Vec<Spanned<Stmt>>,
+}
+
+impl Module {
+    /// Names of imported modules with spans of the imports
+    fn imports(&self) -> impl Iterator<Item = (String, &Spanned<Stmt>)> {
+        self.ast.iter().filter_map(|stmt| match &stmt.0 {
+            Stmt::Import(import) => Some((import.module_name(), stmt)),
+            _ => None,
+        })
+    }
+}
+
+/// Orders modules so every module comes after the modules it imports.
+/// Fails when an imported module does not exist or imports form a cycle
+pub(crate) fn sort_modules(modules: Vec<Module>) -> AshResult<Vec<Module>, String> {
+    let mut errors = Vec::new();
+    for module in modules.iter() {
+        for (name, (_, span)) in module.imports() {
+            if !modules.iter().any(|m| m.name == name) {
+                let err = Simple::custom(span.clone(), format!("Module `{name}` not found"));
+                errors.push(err.with_label(error_code::IMPORT));
+            }
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut order = Vec::new();
+    let mut path = Vec::new();
+    for i in 0..modules.len() {
+        visit(&modules, i, &mut path, &mut order, &mut errors);
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut modules = modules.into_iter().map(Some).collect::<Vec<_>>();
+    Ok(order.into_iter().filter_map(|i| modules[i].take()).collect())
+}
+
+fn visit(
+    modules: &[Module],
+    i: usize,
+    path: &mut Vec<usize>,
+    order: &mut Vec<usize>,
+    errors: &mut Vec<Simple<String>>,
+) {
+    if order.contains(&i) || path.contains(&i) {
+        return;
+    }
+
+    path.push(i);
+    for (name, (_, span)) in modules[i].imports() {
+        let dep = modules.iter().position(|m| m.name == name).unwrap();
+        if let Some(start) = path.iter().position(|m| *m == dep) {
+            let cycle = path[start..]
+                .iter()
+                .chain([&dep])
+                .map(|m| modules[*m].name.as_str())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            let err = Simple::custom(span.clone(), format!("Found import cycle: {cycle}"));
+            errors.push(err.with_label(error_code::IMPORT));
+            continue;
+        }
+        visit(modules, dep, path, order, errors);
+    }
+    path.pop();
+    order.push(i);
+}
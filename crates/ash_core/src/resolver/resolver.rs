@@ -1,25 +1,28 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use chumsky::prelude::Simple;
+use ash_bytecode::prelude::NativeSignature;
+use chumsky::{prelude::Simple, Error as _};
 
+use super::Modules;
 use crate::{
-    core::{Context, Id, Spanned},
-    parser::{expr::Expr, stmt::Stmt, If},
+    core::{error_code, Context, Id, Spanned},
+    parser::{expr::{Expr, StrPart}, stmt::Stmt, If, Import},
     prelude::{AshResult, Span},
     ty::{function::{MAX_FUNCTION_PARAMS, ProtoFunction}, FunctionType, Ty},
 };
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub(crate) struct Scope {
     vars: HashMap<String, VarData>,
     early_exit: bool
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct VarData {
     id: Id,
     is_defined: bool,
     is_mutable: bool,
+    is_public: bool,
     ty: Option<Ty>,
 }
 
@@ -27,31 +30,71 @@ pub(crate) struct Resolver<'a> {
     context: &'a mut Context,
     scopes: Vec<Scope>,
     current_function: Option<FunctionType>,
+    // First scope of the function being resolved and its id,
+    // locals declared before it are not captured by the function
+    function_scope: Option<(usize, Id)>,
     is_expr_block: bool,
+    is_loop: bool,
     errors: Vec<Simple<String>>,
     deps: Option<(Id, String, Vec<Id>)>,
+    natives: &'a [NativeSignature],
+    // Interactive input can use any statement in the root scope
+    is_repl: bool,
+    modules: Option<&'a Modules>,
+    // Imported modules by the name they are available under
+    imports: HashMap<String, String>,
+    // Names of items imported into the root scope
+    imported: HashSet<String>,
 }
 
 impl<'a> Resolver<'a> {
-    pub fn new(context: &'a mut Context) -> Self {
+    pub fn new(context: &'a mut Context, natives: &'a [NativeSignature]) -> Self {
         Self {
             context,
             scopes: vec![Scope::default()],
             current_function: None,
+            function_scope: None,
             is_expr_block: false,
+            is_loop: false,
             errors: Vec::new(),
             deps: None,
+            natives,
+            is_repl: false,
+            modules: None,
+            imports: HashMap::new(),
+            imported: HashSet::new(),
         }
     }
 
-    pub fn run(mut self, statements: &'a [Spanned<Stmt>]) -> AshResult<(), String> {
+    /// Modules that can be imported, they have to be resolved already
+    pub fn with_modules(mut self, modules: &'a Modules) -> Self {
+        self.modules = Some(modules);
+        self
+    }
+
+    /// Returns the root scope, public declarations are visible to modules importing it
+    pub fn run(mut self, statements: &'a [Spanned<Stmt>]) -> AshResult<Scope, String> {
         self.resolve_root(statements);
         self.resolve_statements(statements);
         if !self.errors.is_empty() {
             return Err(self.errors);
         }
 
-        Ok(())
+        Ok(self.scopes.pop().unwrap())
+    }
+
+    /// Resolves single input of an interactive session on top of globals declared by earlier inputs.
+    /// Returns globals including the ones declared by `statements`
+    pub fn run_repl(mut self, statements: &'a [Spanned<Stmt>], globals: Scope) -> AshResult<Scope, String> {
+        self.scopes = vec![globals];
+        self.is_repl = true;
+        self.resolve_root(statements);
+        self.resolve_statements(statements);
+        if !self.errors.is_empty() {
+            return Err(self.errors);
+        }
+
+        Ok(self.scopes.pop().unwrap())
     }
 
     fn enter_scope(&mut self) {
@@ -66,32 +109,51 @@ impl<'a> Resolver<'a> {
         for stmt in statements {
             self.resolve_root_stmt(stmt)
         }
+        // Imports come last, so they clash with declarations regardless of order
+        for (stmt, span) in statements {
+            if let Stmt::Import(import) = stmt {
+                self.import(import, span.clone());
+            }
+        }
     }
 
     fn resolve_root_stmt(&mut self, (stmt, span): &'a Spanned<Stmt>) {
         match stmt {
-            Stmt::Annotation(_, stmt) => {
-                self.resolve_root_stmt(stmt);
-            }
-            Stmt::ProtoFunction(proto) => {
-                self.declare(proto.name.clone(), proto.id, false, Some(proto.ty.clone()));
-                self.define(proto.name.clone());
+            Stmt::Annotation((a, _), stmt) => {
+                match &stmt.0 {
+                    Stmt::ProtoFunction(proto) if a.is_builtin() => {
+                        self.resolve_builtin(proto, &stmt.1);
+                        self.declare(proto.name.clone(), proto.id, false, Some(proto.ty.clone()));
+                        self.define(proto.name.clone());
+                        self.publish(&proto.name, proto.public);
+                    }
+                    _ => self.resolve_root_stmt(stmt),
+                }
             }
+            Stmt::ProtoFunction(_) => self.new_error(
+                "Function without a body has to be marked with @[builtin]",
+                span.clone(),
+            ),
             Stmt::Function(fun) => {
                 let (proto, _) = &fun.proto;
                 self.declare(proto.name.clone(), proto.id, false, Some(proto.ty.clone()));
                 self.define(proto.name.clone());
+                self.publish(&proto.name, proto.public);
             }
             Stmt::VariableDecl {
                 id,
                 name,
                 ty,
-                value: _,
                 mutable,
+                public,
+                ..
             } => {
                 self.declare(name.clone(), *id, *mutable, ty.clone());
                 self.define(name.clone());
+                self.publish(name, *public);
             }
+            Stmt::Import(_) | Stmt::Error => {}
+            _ if self.is_repl => {}
             _ => self.new_error(
                 "This statement can not be used in the root scope",
                 span.clone(),
@@ -120,17 +182,23 @@ impl<'a> Resolver<'a> {
                 value,
                 ty,
                 mutable,
+                public,
             } => {
+                if *public && self.scopes.len() > 1 {
+                    self.new_error("Only declarations in the root scope can be public", span.clone());
+                }
                 let prev_deps = self.deps.clone();
                 self.deps = Some((*id, name.clone(), Vec::new()));
 
                 self.declare(name.clone(), *id, *mutable, ty.clone());
                 self.resolve_expr(value, span);
                 self.define(name.clone());
+                self.publish(name, *public);
 
                 let (_, _, deps) = self.deps.clone().unwrap();
                 self.context
-                    .resolve_new_var(*id, name.clone(), value.clone(), deps);
+                    .resolve_new_var(*id, name.clone(), value.clone(), deps, *mutable);
+                self.context.add_site(*id, span.clone());
                 self.deps = prev_deps;
             }
             Stmt::VariableAssign { id, name, value } => {
@@ -147,17 +215,25 @@ impl<'a> Resolver<'a> {
                 }
                 self.declare(proto.name.clone(), proto.id, false, Some(proto.ty.clone()));
                 self.define(proto.name.clone());
+                self.publish(&proto.name, proto.public);
 
-                self.context.new_var(proto.id, proto.name.clone(), None);
+                self.context.new_var(proto.id, proto.name.clone(), None, false);
+                self.context.add_site(proto.id, span.clone());
+                self.context.mark_function(proto.id);
             }
             Stmt::Function(fun) => {
                 let (proto, _) = &fun.proto;
+                if proto.public && self.scopes.len() > 1 {
+                    self.new_error("Only declarations in the root scope can be public", span.clone());
+                }
                 self.declare(proto.name.clone(), proto.id, false, Some(proto.ty.clone()));
                 self.define(proto.name.clone());
-                let prev = self.current_function;
+                self.publish(&proto.name, proto.public);
+                let prev = (self.current_function, self.function_scope);
                 self.current_function = Some(FunctionType::Function);
                 {
                     self.enter_scope();
+                    self.function_scope = Some((self.scopes.len() - 1, proto.id));
 
                     if proto.params.len() > MAX_FUNCTION_PARAMS {
                         self.new_error(
@@ -168,18 +244,26 @@ impl<'a> Resolver<'a> {
                     for (id, param, ty) in proto.params.iter() {
                         self.declare(param.clone(), *id, false, Some(ty.clone()));
                         self.define(param.clone());
-                        self.context.new_var(*id, param.clone(), Some(ty.clone()));
+                        self.context.new_var(*id, param.clone(), Some(ty.clone()), false);
+                        self.context.add_site(*id, fun.proto.1.clone());
                     }
                     self.resolve_stmt(&fun.body);
 
                     self.leave_scope();
                 }
-                self.context.new_var(proto.id, proto.name.clone(), None);
-                self.current_function = prev;
+                self.context.new_var(proto.id, proto.name.clone(), None, false);
+                self.context.add_site(proto.id, fun.proto.1.clone());
+                self.context.mark_function(proto.id);
+                (self.current_function, self.function_scope) = prev;
             }
             Stmt::While((cond, span), body) => {
                 self.resolve_expr(cond, span);
-                self.resolve_statements(body);
+
+                let prev = (self.is_expr_block, self.is_loop);
+                self.is_expr_block = false;
+                self.is_loop = true;
+                self.block(body, false);
+                (self.is_expr_block, self.is_loop) = prev;
             }
             Stmt::Return(expr) => {
                 if self.current_function.is_none() {
@@ -210,31 +294,46 @@ impl<'a> Resolver<'a> {
                 self.block(&otherwise, false);
             }
             Stmt::Break(expr) => {
-                if !self.is_expr_block {
+                if !self.is_expr_block && !self.is_loop {
                     self.new_error("break can not be used outside of expression block or loop", span.clone())
                 }
             
                 self.mark_scope_exhaustive();
 
                 match expr {
-                    Some(expr) => self.resolve_expr(expr, span),
+                    Some(expr) => {
+                        if !self.is_expr_block && self.is_loop {
+                            self.new_error("break inside a loop can not pass a value", span.clone())
+                        }
+                        self.resolve_expr(expr, span)
+                    }
                     None if self.is_expr_block => self.new_error("break inside a block expression needs to pass a value", span.clone()),
                     None => {}
                 }
             }
+            Stmt::Import(_) => {
+                if self.scopes.len() > 1 {
+                    self.import_error("Modules can only be imported in the root scope", span.clone())
+                }
+            }
+            // It could be a `break` or `return`, so the block is not reported as not exhaustive
+            Stmt::Error => self.mark_scope_exhaustive(),
         }
     }
 
     fn resolve_expr(&mut self, expr: &'a Expr, span: &'a Span) {
         match expr {
-            Expr::Variable(id, name) => {
+            Expr::Variable(id, (name, span)) => {
                 self.resolve_local(*id, name, span.clone())
             }
+            Expr::Path(id, (path, span)) => self.resolve_path(*id, path, span.clone()),
             Expr::Binary { left, right, .. } => {
                 self.resolve_expr(left, span);
                 self.resolve_expr(right, span);
             }
-            Expr::Unary { right, .. } => self.resolve_expr(right, span),
+            Expr::Unary { right, .. } | Expr::Cast { expr: right, .. } => {
+                self.resolve_expr(right, span)
+            }
             Expr::Call { callee, args } => {
                 self.resolve_expr(callee, span);
                 for arg in args {
@@ -243,6 +342,13 @@ impl<'a> Resolver<'a> {
                 }
             }
             Expr::Group(expr) => self.resolve_expr(expr, span),
+            Expr::Interpolated(parts) => {
+                for part in parts {
+                    if let StrPart::Expr(expr) = part {
+                        self.resolve_expr(expr, span);
+                    }
+                }
+            }
             Expr::Block(stmts) => {
                 let exhaustive = self.block(stmts, true);
                 if exhaustive {
@@ -281,26 +387,120 @@ impl<'a> Resolver<'a> {
     }
 
     fn resolve_local(&mut self, id: Id, name: &'a str, span: Span) {
-        for Scope { vars, .. } in self.scopes.iter_mut().rev() {
-            if let Some(data) = vars.get(name) {
-                let points_to = data.id;
-                if !data.is_defined {
-                    continue;
+        let found = self
+            .scopes
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(depth, Scope { vars, .. })| {
+                vars.get(name)
+                    .filter(|data| data.is_defined)
+                    .map(|data| (depth, data.clone()))
+            });
+        let Some((depth, data)) = found else {
+            self.new_error("Variable does not exist", span);
+            return;
+        };
+
+        // Globals are in the root scope, the only outer local a function can use is itself
+        if let Some((function_depth, function_id)) = self.function_scope {
+            if depth > 0 && depth < function_depth && data.id != function_id {
+                self.new_error(
+                    format!(
+                        "Function can not use `{name}`, it is a local variable of an enclosing scope"
+                    ),
+                    span,
+                );
+                return;
+            }
+        }
+
+        self.context.resolve(id, data.is_mutable, data.ty.clone(), data.id);
+        self.context.add_site(id, span.clone());
+        self.detect_deps(data.id, span);
+    }
+
+    fn import(&mut self, import: &Import, span: Span) {
+        let module = import.module_name();
+        let scope = match self.modules.and_then(|modules| modules.get(&module)) {
+            Some(scope) => scope,
+            None => return self.import_error(format!("Module `{module}` not found"), span),
+        };
+
+        let items = match &import.items {
+            Some(items) => items,
+            None => {
+                let alias = import.alias();
+                if self.is_unbound(alias, span) {
+                    self.imports.insert(alias.to_owned(), module);
                 }
-                self.context.resolve(id, data.is_mutable, data.ty.clone(), points_to);
-                self.detect_deps(points_to, span.clone());
                 return;
             }
+        };
+        for (name, span) in items {
+            match Self::export(scope, &module, name) {
+                Ok(data) if self.is_unbound(name, span.clone()) => {
+                    let data = VarData { is_public: false, ..data.clone() };
+                    self.scopes.last_mut().unwrap().vars.insert(name.clone(), data);
+                    self.imported.insert(name.clone());
+                }
+                Ok(_) => {}
+                Err(err) => self.import_error(err, span.clone()),
+            }
         }
+    }
 
-        self.new_error("Variable does not exist", span);
+    /// Reports name which an import would bind when the root scope already has it
+    fn is_unbound(&mut self, name: &str, span: Span) -> bool {
+        let err = if self.imports.contains_key(name) || self.imported.contains(name) {
+            format!("`{name}` is already imported")
+        } else if self.scopes.last().unwrap().vars.contains_key(name) {
+            format!("`{name}` is already declared in this scope")
+        } else {
+            return true;
+        };
+        self.import_error(err, span);
+        false
     }
 
+    /// Resolves `module.name`, module is either the full module name or the name it is imported under
+    fn resolve_path(&mut self, id: Id, path: &[String], span: Span) {
+        let (name, module) = path.split_last().unwrap();
+        let module = module.join(".");
+        let module = match self.imports.get(&module) {
+            Some(module) => module.clone(),
+            None if self.imports.values().any(|m| *m == module) => module,
+            None => {
+                self.import_error(format!("Module `{module}` is not imported"), span);
+                return;
+            }
+        };
+
+        let scope = &self.modules.unwrap()[&module];
+        match Self::export(scope, &module, name) {
+            Ok(data) => {
+                let points_to = data.id;
+                self.context.resolve(id, data.is_mutable, data.ty.clone(), points_to);
+                self.context.add_site(id, span.clone());
+                self.detect_deps(points_to, span);
+            }
+            Err(err) => self.import_error(err, span),
+        }
+    }
+
+    fn export<'s>(scope: &'s Scope, module: &str, name: &str) -> Result<&'s VarData, String> {
+        match scope.vars.get(name) {
+            Some(data) if data.is_public => Ok(data),
+            Some(_) => Err(format!("`{name}` is private to module `{module}`")),
+            None => Err(format!("`{name}` is not defined in module `{module}`")),
+        }
+    }
 
     fn block(&mut self, statements: &'a [Spanned<Stmt>], is_expr: bool) -> bool {
-        let prev = self.is_expr_block;
+        let prev = (self.is_expr_block, self.is_loop);
         if is_expr {
             self.is_expr_block = true;
+            self.is_loop = false;
         }
         
         self.enter_scope();
@@ -309,12 +509,42 @@ impl<'a> Resolver<'a> {
         self.leave_scope();
         
         if is_expr {
-            self.is_expr_block = prev;
+            (self.is_expr_block, self.is_loop) = prev;
         }
 
         exhaustive
     }
 
+    fn resolve_builtin(&mut self, proto: &ProtoFunction, span: &Span) {
+        let native = match self.natives.iter().find(|native| native.name == proto.name) {
+            Some(native) => native,
+            None => {
+                self.new_error(
+                    format!("Builtin function `{}` is not defined", proto.name),
+                    span.clone(),
+                );
+                return;
+            }
+        };
+
+        let params_match = native.params.len() == proto.params.len()
+            && proto
+                .params
+                .iter()
+                .zip(native.params.iter())
+                .all(|((_, _, ty), native_ty)| ty.native_ty() == Some(*native_ty));
+        let ret_matches = proto.ty.fun_return_ty().native_ty() == Some(native.ret);
+        if !params_match || !ret_matches {
+            self.new_error(
+                format!(
+                    "Builtin function `{}` does not match its native signature `{}`",
+                    proto.name, native
+                ),
+                span.clone(),
+            );
+        }
+    }
+
     fn declare(&mut self, name: String, id: Id, is_mutable: bool, ty: Option<Ty>) {
         if let Some(scope) = self.scopes.last_mut() {
             scope.vars.insert(
@@ -324,6 +554,7 @@ impl<'a> Resolver<'a> {
                     ty,
                     is_mutable,
                     is_defined: false,
+                    is_public: false,
                 },
             );
         }
@@ -335,6 +566,12 @@ impl<'a> Resolver<'a> {
         }
     }
 
+    fn publish(&mut self, name: &str, is_public: bool) {
+        if let Some(Scope { vars, .. }) = self.scopes.last_mut() {
+            vars.get_mut(name).unwrap().is_public = is_public;
+        }
+    }
+
     fn detect_deps(&mut self, checked_id: Id, span: Span) {
         if let Some(deps) = &mut self.deps {
             let path = self.context.check_circular_dep(deps.0, checked_id);
@@ -364,6 +601,107 @@ impl<'a> Resolver<'a> {
     }
 
     fn new_error<S: ToString>(&mut self, err_msg: S, span: Span) {
-        self.errors.push(Simple::custom(span, err_msg));
+        self.errors.push(Simple::custom(span, err_msg).with_label(error_code::RESOLVE));
+    }
+
+    fn import_error<S: ToString>(&mut self, err_msg: S, span: Span) {
+        self.errors.push(Simple::custom(span, err_msg).with_label(error_code::IMPORT));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chumsky::error::SimpleReason;
+
+    use crate::{
+        ashery,
+        core::{Source, SourceMap},
+    };
+
+    const MATH: &str = "pub fun square(x: i32) > i32 => x * x;\nfun secret() > i32 => 1;\n";
+
+    /// Messages of the errors reported for `main` compiled with the other modules
+    fn check(main: &str, modules: &[(&str, &str)]) -> Vec<String> {
+        let mut sources = SourceMap::new();
+        sources.add("main", Source::from_string(main));
+        for (name, code) in modules {
+            sources.add(*name, Source::from_string(*code));
+        }
+        match ashery::check_project(&sources, &[]) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors
+                .iter()
+                .map(|error| match error.reason() {
+                    SimpleReason::Custom(msg) => msg.clone(),
+                    _ => error.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn imports() {
+        let main = "import util.math;\nimport util.math.{square};\n\nval a = square(math.square(2));\n";
+        assert_eq!(check(main, &[("util.math", MATH)]), Vec::<String>::new());
+    }
+
+    #[test]
+    fn duplicate_imports() {
+        let modules = [("util.math", MATH), ("other", MATH)];
+        let cases = [
+            ("import util.math.{square};\nimport other.{square};\n", "`square` is already imported"),
+            ("import util.math.{square, square};\n", "`square` is already imported"),
+            ("import util.math;\nimport util.math;\n", "`math` is already imported"),
+        ];
+        for (main, msg) in cases {
+            assert_eq!(check(main, &modules), [msg], "{main}");
+        }
+    }
+
+    #[test]
+    fn imports_clash_with_declarations() {
+        let cases = [
+            ("import util.math.{square};\nfun square() {}\n", "`square` is already declared in this scope"),
+            ("fun square() {}\nimport util.math.{square};\n", "`square` is already declared in this scope"),
+            ("val math = 3;\nimport util.math;\n", "`math` is already declared in this scope"),
+            ("import util.math;\nval math = 3;\n", "`math` is already declared in this scope"),
+        ];
+        for (main, msg) in cases {
+            assert_eq!(check(main, &[("util.math", MATH)]), [msg], "{main}");
+        }
+
+        // Locals of functions shadow imports
+        let main = "import util.math.{square};\n\nfun f() > i32 {\n    val square = 2;\n    return square;\n}\n";
+        assert_eq!(check(main, &[("util.math", MATH)]), Vec::<String>::new());
+    }
+
+    #[test]
+    fn circular_imports() {
+        let modules = [("a", "import b;\n"), ("b", "import main;\n")];
+        assert_eq!(
+            check("import a;\n", &modules),
+            ["Found import cycle: main -> a -> b -> main"]
+        );
+    }
+
+    #[test]
+    fn missing_modules() {
+        assert_eq!(check("import util.math;\n", &[]), ["Module `util.math` not found"]);
+        assert_eq!(
+            check("val a = math.square(2);\n", &[("util.math", MATH)]),
+            ["Module `math` is not imported"]
+        );
+    }
+
+    #[test]
+    fn private_items() {
+        let cases = [
+            ("import util.math.{secret};\n", "`secret` is private to module `util.math`"),
+            ("import util.math;\nval a = math.secret();\n", "`secret` is private to module `util.math`"),
+            ("import util.math.{cube};\n", "`cube` is not defined in module `util.math`"),
+        ];
+        for (main, msg) in cases {
+            assert_eq!(check(main, &[("util.math", MATH)]), [msg], "{main}");
+        }
     }
 }
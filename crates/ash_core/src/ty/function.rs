@@ -34,4 +34,6 @@ pub(crate) struct ProtoFunction {
     pub name: String,
     pub params: Vec<FunArg>,
     pub ty: Ty,
+    // Visible to modules importing it
+    pub public: bool,
 }
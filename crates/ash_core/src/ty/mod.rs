@@ -11,3 +11,4 @@ pub mod ty;
 // pub mod type_system;
 pub mod value;
 pub mod typing2;
+mod rules;
@@ -0,0 +1,186 @@
+//! Typing rules of operators. Each rule returns type of the result
+//! or message of the error to report, values are never converted implicitly
+
+use crate::parser::operator::{BinaryOp, UnaryOp};
+
+use super::Ty;
+
+pub(crate) fn unary(op: &UnaryOp, ty: Ty) -> Result<Ty, String> {
+    let expected_types = match op {
+        UnaryOp::Neg => Ty::numbers(Ty::is_signed),
+        UnaryOp::Not => vec![Ty::Bool],
+        UnaryOp::BitNot => Ty::numbers(Ty::is_integer),
+    };
+
+    expect_one_of(&expected_types, &ty)?;
+    Ok(ty)
+}
+
+/// Any number can be cast to any number type
+pub(crate) fn cast(from: &Ty, to: &Ty) -> Result<Ty, String> {
+    if !from.is_number() || !to.is_number() {
+        return Err(format!("Type {from} can not be cast to {to}"));
+    }
+
+    Ok(to.clone())
+}
+
+pub(crate) fn binary(op: &BinaryOp, left: Ty, right: Ty) -> Result<Ty, String> {
+    let expected_types = match op {
+        BinaryOp::Sum => [Ty::NUMBERS.to_vec(), vec![Ty::String]].concat(),
+        BinaryOp::Sub
+        | BinaryOp::Mul
+        | BinaryOp::Div
+        | BinaryOp::Rem
+        | BinaryOp::Gt
+        | BinaryOp::Lt
+        | BinaryOp::Gte
+        | BinaryOp::Lte => Ty::NUMBERS.to_vec(),
+        BinaryOp::Equal | BinaryOp::NotEqual => {
+            [Ty::NUMBERS.to_vec(), vec![Ty::String, Ty::Bool]].concat()
+        }
+        BinaryOp::LogicAnd | BinaryOp::LogicOr => vec![Ty::Bool],
+        BinaryOp::BitAnd | BinaryOp::BitOr | BinaryOp::BitXor => Ty::numbers(Ty::is_integer),
+        // Shift amount can be of any integer type, the result has the type of the shifted value
+        BinaryOp::Shl | BinaryOp::Shr => {
+            let integers = Ty::numbers(Ty::is_integer);
+            expect_one_of(&integers, &left)?;
+            expect_one_of(&integers, &right)?;
+            return Ok(left);
+        }
+    };
+
+    if left != right && left.is_number() && right.is_number() {
+        return Err(format!(
+            "Mismatched types {left} and {right}, convert one of them with `as`"
+        ));
+    }
+
+    expect_one_of(&expected_types, &left)?;
+    expect_ty(&left, &right)?;
+
+    let ty = match op {
+        BinaryOp::Sum
+        | BinaryOp::Sub
+        | BinaryOp::Mul
+        | BinaryOp::Div
+        | BinaryOp::Rem
+        | BinaryOp::BitAnd
+        | BinaryOp::BitOr
+        | BinaryOp::BitXor => left,
+        _ => Ty::Bool,
+    };
+
+    Ok(ty)
+}
+
+pub(crate) fn expect_ty(expected_ty: &Ty, received_ty: &Ty) -> Result<(), String> {
+    if expected_ty != received_ty {
+        return Err(format!("Expected type {expected_ty}, got {received_ty}"));
+    }
+
+    Ok(())
+}
+
+pub(crate) fn expect_one_of(expected_types: &[Ty], received_ty: &Ty) -> Result<(), String> {
+    if !expected_types.contains(received_ty) {
+        let expected = expected_types
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Err(format!("Expected one of types {expected}, got {received_ty}"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unary_operators() {
+        assert_eq!(unary(&UnaryOp::Neg, Ty::F32), Ok(Ty::F32));
+        assert_eq!(unary(&UnaryOp::Not, Ty::Bool), Ok(Ty::Bool));
+        assert_eq!(
+            unary(&UnaryOp::Neg, Ty::U32),
+            Err("Expected one of types i8, i16, i32, i64, f32, f64, got u32".to_owned())
+        );
+    }
+
+    #[test]
+    fn casts_between_numbers() {
+        assert_eq!(cast(&Ty::I32, &Ty::F64), Ok(Ty::F64));
+        assert_eq!(cast(&Ty::F32, &Ty::U8), Ok(Ty::U8));
+        assert_eq!(
+            cast(&Ty::Bool, &Ty::I32),
+            Err("Type bool can not be cast to i32".to_owned())
+        );
+        assert_eq!(
+            cast(&Ty::I32, &Ty::String),
+            Err("Type i32 can not be cast to str".to_owned())
+        );
+    }
+
+    #[test]
+    fn binary_operators() {
+        assert_eq!(binary(&BinaryOp::Sum, Ty::String, Ty::String), Ok(Ty::String));
+        assert_eq!(binary(&BinaryOp::Div, Ty::F32, Ty::F32), Ok(Ty::F32));
+        assert_eq!(binary(&BinaryOp::Lt, Ty::U16, Ty::U16), Ok(Ty::Bool));
+        assert_eq!(binary(&BinaryOp::Equal, Ty::Bool, Ty::Bool), Ok(Ty::Bool));
+        assert_eq!(
+            binary(&BinaryOp::Sum, Ty::I32, Ty::I64),
+            Err("Mismatched types i32 and i64, convert one of them with `as`".to_owned())
+        );
+        assert_eq!(
+            binary(&BinaryOp::Sub, Ty::String, Ty::String),
+            Err(format!(
+                "Expected one of types {}, got str",
+                "i8, i16, i32, i64, u8, u16, u32, u64, f32, f64"
+            ))
+        );
+        assert_eq!(
+            binary(&BinaryOp::Sum, Ty::String, Ty::I32),
+            Err("Expected type str, got i32".to_owned())
+        );
+        assert_eq!(
+            binary(&BinaryOp::LogicAnd, Ty::Bool, Ty::I32),
+            Err("Expected type bool, got i32".to_owned())
+        );
+    }
+
+    #[test]
+    fn bitwise_operators() {
+        assert_eq!(unary(&UnaryOp::BitNot, Ty::U8), Ok(Ty::U8));
+        assert_eq!(binary(&BinaryOp::BitAnd, Ty::U32, Ty::U32), Ok(Ty::U32));
+        assert_eq!(binary(&BinaryOp::BitOr, Ty::I8, Ty::I8), Ok(Ty::I8));
+        assert_eq!(binary(&BinaryOp::BitXor, Ty::I64, Ty::I64), Ok(Ty::I64));
+        assert_eq!(
+            unary(&UnaryOp::BitNot, Ty::F64),
+            Err("Expected one of types i8, i16, i32, i64, u8, u16, u32, u64, got f64".to_owned())
+        );
+        assert_eq!(
+            binary(&BinaryOp::BitAnd, Ty::Bool, Ty::Bool),
+            Err("Expected one of types i8, i16, i32, i64, u8, u16, u32, u64, got bool".to_owned())
+        );
+        assert_eq!(
+            binary(&BinaryOp::BitOr, Ty::U8, Ty::I32),
+            Err("Mismatched types u8 and i32, convert one of them with `as`".to_owned())
+        );
+    }
+
+    #[test]
+    fn shifts_keep_type_of_shifted_value() {
+        assert_eq!(binary(&BinaryOp::Shl, Ty::U8, Ty::I32), Ok(Ty::U8));
+        assert_eq!(binary(&BinaryOp::Shr, Ty::I64, Ty::U8), Ok(Ty::I64));
+        assert_eq!(
+            binary(&BinaryOp::Shl, Ty::I32, Ty::F32),
+            Err("Expected one of types i8, i16, i32, i64, u8, u16, u32, u64, got f32".to_owned())
+        );
+        assert_eq!(
+            binary(&BinaryOp::Shr, Ty::F64, Ty::I32),
+            Err("Expected one of types i8, i16, i32, i64, u8, u16, u32, u64, got f64".to_owned())
+        );
+    }
+}
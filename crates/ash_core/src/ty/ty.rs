@@ -1,5 +1,7 @@
 use core::fmt;
 
+use ash_bytecode::prelude::{NativeTy, NumTy};
+
 use crate::prelude::Span;
 
 // TODO: Define some of these types as a part of std lib
@@ -7,7 +9,15 @@ use crate::prelude::Span;
 pub(crate) enum Ty {
     String,
     Bool,
+    I8,
+    I16,
     I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
     F64,
     Void,
     Fun(Vec<Ty>, Box<Ty>),
@@ -25,9 +35,17 @@ impl From<String> for Ty {
         match s.as_str() {
             "str" => Self::String,
             "bool" => Self::Bool,
+            "i8" => Self::I8,
+            "i16" => Self::I16,
             "i32" => Self::I32,
+            "i64" => Self::I64,
+            "u8" => Self::U8,
+            "u16" => Self::U16,
+            "u32" => Self::U32,
+            "u64" => Self::U64,
+            "f32" => Self::F32,
             "f64" => Self::F64,
-            "void" => Self::F64,
+            "void" => Self::Void,
             _ => todo!("Implement custom types"),
         }
     }
@@ -37,12 +55,25 @@ impl fmt::Display for Ty {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let ty = match self {
             Self::Bool => "bool".to_owned(),
-            Self::F64 => "f64".to_owned(),
+            Self::I8 => "i8".to_owned(),
+            Self::I16 => "i16".to_owned(),
             Self::I32 => "i32".to_owned(),
+            Self::I64 => "i64".to_owned(),
+            Self::U8 => "u8".to_owned(),
+            Self::U16 => "u16".to_owned(),
+            Self::U32 => "u32".to_owned(),
+            Self::U64 => "u64".to_owned(),
+            Self::F32 => "f32".to_owned(),
+            Self::F64 => "f64".to_owned(),
             Self::String => "str".to_owned(),
             Self::Void => "void".to_string(),
             Self::Fun(params, ty) => {
-                format!("fun({:?}): {}", params, ty)
+                let params = params
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("fun({params}) > {ty}")
             }
             Self::DeferTyCheck(_, _) => "Deferred Type Check".to_owned(),
         };
@@ -52,10 +83,86 @@ impl fmt::Display for Ty {
 }
 
 impl Ty {
+    /// Types of number values, integers first
+    pub const NUMBERS: [Ty; 10] = [
+        Self::I8,
+        Self::I16,
+        Self::I32,
+        Self::I64,
+        Self::U8,
+        Self::U16,
+        Self::U32,
+        Self::U64,
+        Self::F32,
+        Self::F64,
+    ];
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Self::F32 | Self::F64)
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.is_number() && !self.is_float()
+    }
+
+    pub fn is_signed(&self) -> bool {
+        matches!(self, Self::I8 | Self::I16 | Self::I32 | Self::I64) || self.is_float()
+    }
+
+    pub fn is_number(&self) -> bool {
+        Self::NUMBERS.contains(self)
+    }
+
+    /// Number types matching `f`
+    pub fn numbers<F: Fn(&Ty) -> bool>(f: F) -> Vec<Ty> {
+        Self::NUMBERS.into_iter().filter(f).collect()
+    }
+
     pub fn fun_return_ty(&self) -> Self {
         match self {
             Self::Fun(_, ty) => *ty.clone(),
             _ => panic!("Used fun_return_ty() not on a function"),
         }
     }
+
+    /// Type of `OpCode::Cast` converting a number to this type
+    pub fn num_ty(&self) -> Option<NumTy> {
+        let ty = match self {
+            Self::I8 => NumTy::I8,
+            Self::I16 => NumTy::I16,
+            Self::I32 => NumTy::I32,
+            Self::I64 => NumTy::I64,
+            Self::U8 => NumTy::U8,
+            Self::U16 => NumTy::U16,
+            Self::U32 => NumTy::U32,
+            Self::U64 => NumTy::U64,
+            Self::F32 => NumTy::F32,
+            Self::F64 => NumTy::F64,
+            _ => return None,
+        };
+
+        Some(ty)
+    }
+
+    /// Type used to pass the value to native functions
+    pub fn native_ty(&self) -> Option<NativeTy> {
+        let ty = match self {
+            Self::String => NativeTy::Str,
+            Self::Bool => NativeTy::Bool,
+            Self::I8 => NativeTy::I8,
+            Self::I16 => NativeTy::I16,
+            Self::I32 => NativeTy::I32,
+            Self::I64 => NativeTy::I64,
+            Self::U8 => NativeTy::U8,
+            Self::U16 => NativeTy::U16,
+            Self::U32 => NativeTy::U32,
+            Self::U64 => NativeTy::U64,
+            Self::F32 => NativeTy::F32,
+            Self::F64 => NativeTy::F64,
+            Self::Void => NativeTy::Void,
+            Self::Fun(_, _) | Self::DeferTyCheck(_, _) => return None,
+        };
+
+        Some(ty)
+    }
 }
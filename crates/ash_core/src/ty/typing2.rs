@@ -1,191 +1,420 @@
-use chumsky::prelude::Simple;
+use chumsky::{prelude::Simple, Error as _};
 
-use crate::{core::{Context, Spanned, Id}, hir::{Stmt, Expr}, prelude::{AshResult, Span}, parser::operator::{UnaryOp, BinaryOp}};
+use crate::{
+    core::{error_code, Context, Id, Spanned},
+    hir::{Body, Expr, Stmt},
+    parser::{
+        operator::{BinaryOp, UnaryOp},
+        If,
+    },
+    prelude::{AshResult, Span},
+    ty::function::{Function, ProtoFunction},
+};
 
-use super::{Value, Ty};
+use super::{rules, Ty};
 
+/// Checks types of desugared code. Types of declarations are stored in the context,
+/// so code compiled later against the same context can use them.
+/// Expressions with an error have no type, so an error is reported only once
 pub(crate) struct Typing<'a> {
     ctx: &'a mut Context,
+    // Return types of the functions being checked
+    ret_types: Vec<Ty>,
     errors: Vec<Simple<String>>,
 }
 
 impl<'a> Typing<'a> {
-    pub fn run(ctx: &'a mut Context, ast: Vec<Spanned<Stmt>>) -> AshResult<Vec<Spanned<super::Stmt>>, String> {
+    /// Returns type of the trailing expression in the root scope
+    pub fn run(ctx: &'a mut Context, hir: &[Spanned<Stmt>]) -> AshResult<Option<Ty>, String> {
         let mut typing = Self {
             ctx,
-            errors: Vec::new()
+            ret_types: Vec::new(),
+            errors: Vec::new(),
         };
 
+        // Functions can be called before they are declared
+        for (stmt, _) in hir {
+            match stmt {
+                Stmt::Fun(fun) => typing.declare_proto(&fun.proto.0),
+                Stmt::Proto(proto) => typing.declare_proto(proto),
+                _ => {}
+            }
+        }
+
+        let mut last_ty = None;
+        for stmt in hir.iter().filter(|(stmt, _)| !matches!(stmt, Stmt::Fun(_))) {
+            last_ty = typing.stmt(stmt);
+        }
+
+        for (stmt, _) in hir {
+            if let Stmt::Fun(fun) = stmt {
+                typing.fun(fun);
+            }
+        }
+
         if !typing.errors.is_empty() {
             return Err(typing.errors);
         }
 
-        Ok(typing.multiple_stmt(ast))
+        Ok(last_ty)
     }
 
-    fn multiple_stmt(&mut self, stmts: Vec<Spanned<Stmt>>) -> Vec<Spanned<super::Stmt>> {
-        stmts
-            .into_iter()
-            .map(|stmt| self.stmt(stmt))
-            .collect()
+    fn declare_proto(&mut self, proto: &ProtoFunction) {
+        self.ctx.set_ty(proto.id, proto.ty.clone());
+        for (id, _, ty) in proto.params.iter() {
+            self.ctx.set_ty(*id, ty.clone());
+        }
     }
 
-    fn stmt(&mut self, (stmt, span): Spanned<Stmt>) -> Spanned<super::Stmt> {
-        match stmt {
-            Stmt::Fun(_) => todo!(),
-            Stmt::Proto(_) => todo!(),
-            Stmt::DeclVar { id, name, ty, value } => todo!(),
-            Stmt::StoreVar { id, name, value } => todo!(),
-            Stmt::While(_, _) => todo!(),
-            Stmt::If(_) => todo!(),
-            Stmt::Block(_) => todo!(),
-            Stmt::Break => todo!(),
-            Stmt::Ret(_) => todo!(),
-            Stmt::Expr(expr) => self.stmt_expr(expr, span),
+    fn fun(&mut self, fun: &Function<Body>) {
+        self.ret_types.push(fun.ret_ty());
+        self.body(&fun.body.0);
+        self.ret_types.pop();
+
+        // Void functions get an implicit return when desugared
+        if fun.ret_ty() != Ty::Void && !Self::returns(&fun.body.0) {
+            let (proto, span) = &fun.proto;
+            let name = self.ctx.name_of(proto.id).unwrap_or("function").to_owned();
+            self.new_error(
+                format!("Function `{name}` does not return a value on every path"),
+                span,
+            );
         }
     }
 
-    fn expr(&mut self, expr: Expr, span: Span) -> super::Expr {
-        match expr {
-            Expr::LoadVar(id, name) => self.load_var(id, name),
-            Expr::Literal(value) => self.literal(value),
-            Expr::Call { callee, args } => todo!(),
-            Expr::Unary { op, right } => self.unary(op, *right, span),
-            Expr::Binary { left, op, right } => self.binary(*left, op, *right, span),
+    /// Whether every path through `body` ends with a return
+    fn returns(body: &[Spanned<Stmt>]) -> bool {
+        for (stmt, _) in body {
+            let returns = match stmt {
+                Stmt::Ret(_) => true,
+                // Code after it is not reached from here
+                Stmt::Break => return false,
+                Stmt::If(data) => {
+                    std::iter::once(data.then.as_ref())
+                        .chain(data.else_ifs.iter())
+                        .all(|branch| Self::returns(&branch.body))
+                        && Self::returns(&data.otherwise)
+                }
+                Stmt::Block(body) => Self::returns(body),
+                // A `break` leaves the block before it returns
+                Stmt::ExprBlock(body) => Self::returns(body) && !Self::breaks(body),
+                // Loop body may not run at all
+                _ => false,
+            };
+            if returns {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Whether `body` has a `break` jumping out of the enclosing block
+    fn breaks(body: &[Spanned<Stmt>]) -> bool {
+        body.iter().any(|(stmt, _)| match stmt {
+            Stmt::Break => true,
+            Stmt::If(data) => {
+                std::iter::once(data.then.as_ref())
+                    .chain(data.else_ifs.iter())
+                    .any(|branch| Self::breaks(&branch.body))
+                    || Self::breaks(&data.otherwise)
+            }
+            Stmt::Block(body) => Self::breaks(body),
+            _ => false,
+        })
+    }
+
+    fn body(&mut self, body: &[Spanned<Stmt>]) {
+        for stmt in body {
+            self.stmt(stmt);
+        }
+    }
+
+    /// Returns type of the expression statement
+    fn stmt(&mut self, (stmt, span): &Spanned<Stmt>) -> Option<Ty> {
+        match stmt {
+            Stmt::Fun(fun) => self.fun(fun),
+            Stmt::Proto(proto) => self.declare_proto(proto),
+            Stmt::DeclVar { id, ty, value, .. } => self.decl_var(*id, ty, value, span),
+            Stmt::StoreVar { id, value, .. } => self.store_var(*id, value, span),
+            Stmt::While((cond, cond_span), body) => {
+                self.condition(cond, cond_span);
+                self.body(body);
+            }
+            Stmt::If(data) => self.stmt_if(data),
+            Stmt::Block(body) | Stmt::ExprBlock(body) => self.body(body),
+            Stmt::Break => {}
+            Stmt::Ret(expr) => self.ret(expr, span),
+            Stmt::Expr(expr) => return self.expr(expr, span),
         }
+
+        None
     }
 
-    fn stmt_expr(&mut self, expr: Expr, span: Span) -> Spanned<super::Stmt> {
-        let expr = self.expr(expr, span.clone());
-        let ty = expr.ty();
-        (super::Stmt::Expr(expr, ty), span)
+    fn decl_var(&mut self, id: Id, ty: &Option<Ty>, value: &Option<Expr>, span: &Span) {
+        let value_ty = value.as_ref().and_then(|value| self.expr(value, span));
+        match (ty, value_ty) {
+            (Some(ty), value_ty) => {
+                if let Some(value_ty) = value_ty {
+                    self.expect_ty(ty, &value_ty, span);
+                }
+                self.ctx.set_ty(id, ty.clone());
+            }
+            (None, Some(value_ty)) => self.ctx.set_ty(id, value_ty),
+            // Temporary variables get their type from the first store
+            (None, None) => {}
+        }
     }
 
-    // TODO: check if calling function before they were defined works
-    fn load_var(&mut self, id: Id, name: String) -> super::Expr {
-        let ty = self.ctx.var_data(id).and_then(|v| v.ty);
-        let ty = match ty {
+    fn store_var(&mut self, id: Id, value: &Expr, span: &Span) {
+        let value_ty = match self.expr(value, span) {
             Some(ty) => ty,
-            None => self.var_pointing_ty(id)
+            None => return,
         };
 
-        super::Expr::LoadVar(id, name, ty)
+        match self.ctx.ty_of(id) {
+            Some(ty) => {
+                self.expect_ty(&ty, &value_ty, span);
+            }
+            None => self.ctx.set_ty(id, value_ty),
+        }
     }
 
-    fn literal(&mut self, value: Value) -> super::Expr {
-        super::Expr::Literal(value)
+    fn stmt_if(&mut self, data: &If<Expr, Stmt>) {
+        let branches = std::iter::once(data.then.as_ref()).chain(data.else_ifs.iter());
+        for branch in branches {
+            let (cond, cond_span) = &branch.condition;
+            self.condition(cond, cond_span);
+            self.body(&branch.body);
+        }
+        self.body(&data.otherwise);
     }
 
-    fn unary(&mut self, op: UnaryOp, right: Expr, span: Span) -> super::Expr {
-        let right = Box::new(self.expr(right, span.clone()));
-        let ty = right.ty();
+    fn condition(&mut self, cond: &Expr, span: &Span) {
+        if let Some(ty) = self.expr(cond, span) {
+            self.expect_ty(&Ty::Bool, &ty, span);
+        }
+    }
 
-        let expected_types = match op {
-            UnaryOp::Neg => vec![Ty::F64, Ty::I32],
-            UnaryOp::Not => vec![Ty::Bool]
+    fn ret(&mut self, expr: &Option<Expr>, span: &Span) {
+        let ty = match expr {
+            Some(expr) => match self.expr(expr, span) {
+                Some(ty) => ty,
+                None => return,
+            },
+            None => Ty::Void,
         };
 
-        self.expect_one_of(&expected_types, &ty, span);
-
-        super::Expr::Unary {
-            op,
-            right,
-            ty
-        }
-    }
-
-    fn binary(&mut self, left: Expr, op: BinaryOp, right: Expr, span: Span)  -> super::Expr {
-        let left = Box::new(self.expr(left, span.clone()));
-        let right = Box::new(self.expr(right, span.clone()));
-        let left_ty = left.ty();
-        let right_ty = right.ty();
-
-        self.expect_ty(&left_ty, &right_ty, span.clone());
-
-        let expected_types = match op {
-            BinaryOp::Sum => vec![
-                Ty::String,
-                Ty::I32,
-                Ty::F64
-            ],
-            BinaryOp::Sub |
-            BinaryOp::Mul |
-            BinaryOp::Div |
-            BinaryOp::Rem |
-            BinaryOp::Gte |
-            BinaryOp::Lte |
-            BinaryOp::Gt |
-            BinaryOp::Lt => vec![
-                Ty::I32,
-                Ty::F64
-            ],
-            BinaryOp::Equal | BinaryOp::NotEqual => vec![
-                Ty::String,
-                Ty::I32,
-                Ty::F64,
-                Ty::Bool
-            ],
-            BinaryOp::LogicAnd => todo!(),
-            BinaryOp::LogicOr => todo!(),
+        // Return outside of function is reported by the resolver
+        if let Some(ret_ty) = self.ret_types.last().cloned() {
+            self.expect_ty(&ret_ty, &ty, span);
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr, span: &Span) -> Option<Ty> {
+        match expr {
+            Expr::LoadVar(id, _) => self.ctx.ty_of(*id),
+            Expr::Literal(value) => Some(value.ty()),
+            Expr::ToStr(expr) => self.check_to_str(expr, span),
+            Expr::Call { callee, args } => self.call(callee, args, span),
+            Expr::Unary { op, right } => self.unary(op, right, span),
+            Expr::Cast { expr, ty } => self.cast(expr, ty, span),
+            Expr::Binary { left, op, right } => self.binary(left, op, right, span),
+        }
+    }
+
+    fn check_to_str(&mut self, expr: &Expr, span: &Span) -> Option<Ty> {
+        let ty = self.expr(expr, span)?;
+        let expected_types = [Ty::NUMBERS.to_vec(), vec![Ty::Bool, Ty::String]].concat();
+
+        self.expect_one_of(&expected_types, &ty, span)
+            .then_some(Ty::String)
+    }
+
+    fn call(&mut self, callee: &Expr, args: &[Expr], span: &Span) -> Option<Ty> {
+        let callee_ty = self.expr(callee, span);
+        let args = args
+            .iter()
+            .map(|arg| self.expr(arg, span))
+            .collect::<Vec<_>>();
+
+        let (params, ret) = match callee_ty? {
+            Ty::Fun(params, ret) => (params, ret),
+            ty => {
+                self.new_error(format!("Type {ty} is not callable"), span);
+                return None;
+            }
         };
 
-        self.expect_one_of(&expected_types, &left_ty, span);
-        let ty = self.get_binary_ty(&op, left_ty);
+        if params.len() != args.len() {
+            let name = match callee {
+                Expr::LoadVar(id, _) => self.ctx.name_of(*id).unwrap_or("function").to_owned(),
+                _ => "function".to_owned(),
+            };
+            self.new_error(
+                format!(
+                    "`{name}` takes {} arguments, but {} were given",
+                    params.len(),
+                    args.len()
+                ),
+                span,
+            );
+            return Some(*ret);
+        }
+
+        for (param, arg) in params.iter().zip(args) {
+            if let Some(arg) = arg {
+                self.expect_ty(param, &arg, span);
+            }
+        }
+
+        Some(*ret)
+    }
+
+    fn unary(&mut self, op: &UnaryOp, right: &Expr, span: &Span) -> Option<Ty> {
+        let ty = self.expr(right, span)?;
+        self.rule(rules::unary(op, ty), span)
+    }
+
+    fn cast(&mut self, expr: &Expr, ty: &Ty, span: &Span) -> Option<Ty> {
+        let expr_ty = self.expr(expr, span)?;
+        self.rule(rules::cast(&expr_ty, ty), span)
+    }
+
+    fn binary(&mut self, left: &Expr, op: &BinaryOp, right: &Expr, span: &Span) -> Option<Ty> {
+        let left_ty = self.expr(left, span);
+        let right_ty = self.expr(right, span);
+        let (left_ty, right_ty) = (left_ty?, right_ty?);
+
+        self.rule(rules::binary(op, left_ty, right_ty), span)
+    }
+
+    fn expect_ty(&mut self, expected_ty: &Ty, received_ty: &Ty, span: &Span) -> bool {
+        self.rule(rules::expect_ty(expected_ty, received_ty), span)
+            .is_some()
+    }
+
+    fn expect_one_of(&mut self, expected_types: &[Ty], received_ty: &Ty, span: &Span) -> bool {
+        self.rule(rules::expect_one_of(expected_types, received_ty), span)
+            .is_some()
+    }
+
+    /// Reports error of the typing rule
+    fn rule<T>(&mut self, result: Result<T, String>, span: &Span) -> Option<T> {
+        result.map_err(|msg| self.new_error(msg, span)).ok()
+    }
+
+    fn new_error<S: ToString>(&mut self, err_msg: S, span: &Span) {
+        self.errors.push(Simple::custom(span.clone(), err_msg).with_label(error_code::TYPE))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chumsky::error::SimpleReason;
 
-        super::Expr::Binary { 
-            left,
-            op,
-            right,
-            ty
+    use crate::{ashery, core::Source};
+
+    /// Messages of the errors reported for `code`
+    fn check(code: &str) -> Vec<String> {
+        match ashery::check(&Source::from_string(code), &[]) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors
+                .iter()
+                .map(|error| match error.reason() {
+                    SimpleReason::Custom(msg) => msg.clone(),
+                    _ => error.to_string(),
+                })
+                .collect(),
         }
     }
 
-    fn get_binary_ty(&self, op: &BinaryOp, received_ty: Ty) -> Ty {
-        let bool_ops = [
-            BinaryOp::Equal,
-            BinaryOp::NotEqual,
-            BinaryOp::Gt,
-            BinaryOp::Lt,
-            BinaryOp::Gte,
-            BinaryOp::Lte,
-            BinaryOp::LogicAnd,
-            BinaryOp::LogicOr,   
+    #[test]
+    fn missing_return() {
+        let cases = [
+            "fun f() > i32 {}",
+            "fun f(a: bool) > i32 { if a { return 1; } }",
+            "fun f(a: bool) > i32 { if a { return 1; } else if !a { return 2; } }",
+            "fun f(a: bool) > i32 { while a { return 1; } }",
         ];
+        for code in cases {
+            let msg = "Function `f` does not return a value on every path";
+            assert_eq!(check(code), [msg], "{code}");
+        }
 
-        if bool_ops.contains(op) {
-            Ty::Bool
-        } else {
-            received_ty
+        let cases = [
+            "fun f() > i32 => 1;",
+            "fun f() {}",
+            "fun f(a: bool) > i32 { if a { return 1; } else { return 2; } }",
+            "fun f(a: bool) > i32 { { return 1; } }",
+            "fun f(a: bool) > i32 { if a { return 1; } return 2; }",
+        ];
+        for code in cases {
+            assert_eq!(check(code), Vec::<String>::new(), "{code}");
         }
     }
 
-    fn var_pointing_ty(&self, id: Id) -> Ty {
-        self.ctx.get_pointed_local(id).ty.clone().unwrap()
+    #[test]
+    fn number_literals_have_type_of_suffix() {
+        let cases = [
+            "val a: i32 = 0b1010_0101;",
+            "val a: i8 = -128i8;",
+            "val a: u8 = 255u8;",
+            "val a: u16 = 0o177_777u16;",
+            "val a: i64 = 0x7fff_ffff_ffff_ffffi64;",
+            "val a: u64 = 0xffff_ffff_ffff_ffffu64;",
+            "val a: f64 = 1e3;",
+            "val a: f64 = 2.5E+10f64;",
+            "val a: f32 = 1.5e-3f32;",
+            "val a: f32 = 1f32;",
+        ];
+        for code in cases {
+            assert_eq!(check(code), Vec::<String>::new(), "{code}");
+        }
     }
 
-    fn expect_ty(&mut self, expected_ty: &Ty, received_ty: &Ty, span: Span) {
-        if expected_ty != received_ty {
-            self.new_error(
-                format!("Expected type {expected_ty}, got {received_ty}"),
-                span
-            );
+    #[test]
+    fn number_literals_are_not_converted() {
+        let cases = [
+            ("val a: f32 = 1.5;", "Expected type f32, got f64"),
+            ("val a: u8 = 1;", "Expected type u8, got i32"),
+            ("val a: f64 = 1;", "Expected type f64, got i32"),
+            ("val a: i32 = 1e3;", "Expected type i32, got f64"),
+        ];
+        for (code, msg) in cases {
+            assert_eq!(check(code), [msg], "{code}");
         }
     }
 
-    fn expect_one_of(&mut self, expected_types: &[Ty], received_ty: &Ty, span: Span) {
-        if !expected_types.contains(received_ty) {
-            self.new_error(
-                format!("Expected types {:?}, got: {}", expected_types, received_ty),
-                span,
-            );
+    #[test]
+    fn out_of_range_literals() {
+        let cases = [
+            ("256u8", "u8"),
+            ("-129i8", "i8"),
+            ("2147483648", "i32"),
+            ("0x1_0000u16", "u16"),
+            ("1e39f32", "f32"),
+            ("1e309", "f64"),
+        ];
+        for (literal, ty) in cases {
+            let code = format!("val a = {literal};");
+            let msg = format!("Literal `{literal}` does not fit into type {ty}");
+            assert_eq!(check(&code), [msg], "{code}");
         }
+
+        // Unsigned literal is negated like any other unsigned value
+        assert_eq!(
+            check("val a = -1u8;"),
+            ["Expected one of types i8, i16, i32, i64, f32, f64, got u8"]
+        );
     }
 
-    fn new_error<S, P>(&mut self, err_msg: S, span: P)
-    where
-        S: ToString,
-        P: ToOwned<Owned = Span>,
-    {
-        self.errors.push(Simple::custom(span.to_owned(), err_msg))
+    #[test]
+    fn misplaced_separators() {
+        for literal in ["1__0", "1_", "1_u8", "0x_", "0x_1", "1_e3", "1.5_f32"] {
+            let code = format!("val a = {literal};");
+            let msg = format!("Separator `_` has to be between digits in `{literal}`");
+            assert_eq!(check(&code), [msg], "{code}");
+        }
     }
-}
\ No newline at end of file
+}
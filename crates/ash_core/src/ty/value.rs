@@ -1,10 +1,22 @@
+use core::fmt;
+
+use crate::lexer::string::escape;
+
 use super::ty::Ty;
 
 // TODO: Remove it in favor of ash_bytecode::value
 #[derive(Clone, Debug)]
 pub enum Value {
     String(String),
+    I8(i8),
+    I16(i16),
     I32(i32),
+    I64(i64),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    F32(f32),
     F64(f64),
     Bool(bool),
 }
@@ -13,17 +25,46 @@ impl Value {
     pub(crate) fn ty(&self) -> Ty {
         match self {
             Self::String(_) => Ty::String,
+            Self::I8(_) => Ty::I8,
+            Self::I16(_) => Ty::I16,
             Self::I32(_) => Ty::I32,
+            Self::I64(_) => Ty::I64,
+            Self::U8(_) => Ty::U8,
+            Self::U16(_) => Ty::U16,
+            Self::U32(_) => Ty::U32,
+            Self::U64(_) => Ty::U64,
+            Self::F32(_) => Ty::F32,
             Self::F64(_) => Ty::F64,
             Self::Bool(_) => Ty::Bool,
         }
     }
 
+    /// Number is below zero or is a negative zero
+    pub(crate) fn is_negative(&self) -> bool {
+        match self {
+            Self::I8(v) => *v < 0,
+            Self::I16(v) => *v < 0,
+            Self::I32(v) => *v < 0,
+            Self::I64(v) => *v < 0,
+            Self::F32(v) => v.is_sign_negative(),
+            Self::F64(v) => v.is_sign_negative(),
+            _ => false,
+        }
+    }
+
     pub(crate) fn default_for_ty(ty: Ty) -> Self {
         match ty {
             Ty::String => Self::String(String::new()),
             Ty::Bool => Self::Bool(false),
+            Ty::I8 => Self::I8(0),
+            Ty::I16 => Self::I16(0),
             Ty::I32 => Self::I32(0),
+            Ty::I64 => Self::I64(0),
+            Ty::U8 => Self::U8(0),
+            Ty::U16 => Self::U16(0),
+            Ty::U32 => Self::U32(0),
+            Ty::U64 => Self::U64(0),
+            Ty::F32 => Self::F32(0.0),
             Ty::F64 => Self::F64(0.0),
             Ty::Void => unreachable!(),
             Ty::Fun(_, _) => todo!(),
@@ -31,3 +72,23 @@ impl Value {
         }
     }
 }
+
+/// Values are written as literals, numbers of other than default types have a suffix
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::String(v) => write!(f, "\"{}\"", escape(v)),
+            Self::I8(v) => write!(f, "{v}i8"),
+            Self::I16(v) => write!(f, "{v}i16"),
+            Self::I32(v) => write!(f, "{v}"),
+            Self::I64(v) => write!(f, "{v}i64"),
+            Self::U8(v) => write!(f, "{v}u8"),
+            Self::U16(v) => write!(f, "{v}u16"),
+            Self::U32(v) => write!(f, "{v}u32"),
+            Self::U64(v) => write!(f, "{v}u64"),
+            Self::F32(v) => write!(f, "{v:?}f32"),
+            Self::F64(v) => write!(f, "{v:?}"),
+            Self::Bool(v) => write!(f, "{v}"),
+        }
+    }
+}
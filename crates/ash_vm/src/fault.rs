@@ -1,9 +1,118 @@
+use std::fmt;
+
+use ash_bytecode::prelude::{NativeTy, OpCode, Span, ValueError};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum VMError {
-    #[error("Runtime error: {0}")]
-    RuntimeError(String),
+    #[error("Division by zero")]
+    DivisionByZero,
+    #[error("Integer overflow")]
+    IntegerOverflow,
+    #[error("Type mismatch: {op} can not be used with {operands}")]
+    TypeMismatch { op: OpCode, operands: String },
+    #[error("Stack underflow")]
+    StackUnderflow,
+    #[error("Stack overflow while calling `{0}`")]
+    StackOverflow(String),
+    #[error("Undefined global `{0}`")]
+    UndefinedGlobal(String),
+    #[error("Global `{0}` is not mutable")]
+    ImmutableGlobal(String),
+    #[error("Global `{name}` has type {expected}, got {got}")]
+    GlobalType {
+        name: String,
+        expected: String,
+        got: &'static str,
+    },
+    #[error("Top level code has no function to load")]
+    NoCallee,
+    #[error("Undefined local at slot {0}")]
+    UndefinedLocal(usize),
+    #[error("Undefined constant at index {0}")]
+    UndefinedConstant(usize),
+    #[error("Bad opcode: {0}")]
+    BadOpCode(u8),
+    #[error("Bad cast type: {0}")]
+    BadCastType(u8),
+    #[error("Unexpected end of code")]
+    UnexpectedEnd,
+    #[error("Can not call `{0}`, only functions are callable")]
+    NotCallable(String),
+    #[error("Function `{name}` expects {expected} arguments, got {got}")]
+    ArityMismatch {
+        name: String,
+        expected: u8,
+        got: usize,
+    },
+    #[error("Native function `{0}` is not registered")]
+    UndefinedNative(String),
+    #[error("Native function `{name}` expected {expected}, got {got}")]
+    NativeType {
+        name: String,
+        expected: NativeTy,
+        got: &'static str,
+    },
+    #[error("{0}")]
+    Native(String),
+    #[error("Native function `{0}` can not be passed to the host")]
+    NativeToHost(String),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl VMError {
+    pub(crate) fn from_value_error(err: ValueError, op: OpCode, operands: &[&str]) -> Self {
+        match err {
+            ValueError::DivisionByZero => Self::DivisionByZero,
+            ValueError::Overflow => Self::IntegerOverflow,
+            ValueError::TypeMismatch => Self::TypeMismatch {
+                op,
+                operands: operands.join(" and "),
+            },
+        }
+    }
 }
 
 pub type VMResult<T = ()> = Result<T, VMError>;
+
+#[derive(Debug, Clone)]
+pub struct TraceFrame {
+    pub function: String,
+    pub offset: usize,
+    pub span: Option<Span>,
+}
+
+/// Error returned by the VM together with the place it occurred in
+#[derive(Error, Debug)]
+pub struct Fault {
+    #[source]
+    pub error: VMError,
+    // Offset of the failed instruction
+    pub offset: usize,
+    // Source code the failed instruction was generated from
+    pub span: Option<Span>,
+    // Innermost call first
+    pub trace: Vec<TraceFrame>,
+}
+
+impl Fault {
+    // Frames shown when displaying the fault
+    const TRACE_DISPLAY_LIMIT: usize = 16;
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Runtime error: {}", self.error)?;
+        for frame in self.trace.iter().take(Self::TRACE_DISPLAY_LIMIT) {
+            write!(f, "\n    at {} ({:0>5})", frame.function, frame.offset)?;
+        }
+
+        if self.trace.len() > Self::TRACE_DISPLAY_LIMIT {
+            let hidden = self.trace.len() - Self::TRACE_DISPLAY_LIMIT;
+            write!(f, "\n    ... {hidden} more")?;
+        }
+
+        Ok(())
+    }
+}
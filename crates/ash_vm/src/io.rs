@@ -0,0 +1,72 @@
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    rc::Rc,
+};
+
+/// Streams of printing builtins, `eprint` builtins and debug traces write to stderr
+pub struct Io {
+    stdout: Box<dyn Write>,
+    stderr: Box<dyn Write>,
+}
+
+impl Io {
+    pub fn new<O, E>(stdout: O, stderr: E) -> Self
+    where
+        O: Write + 'static,
+        E: Write + 'static,
+    {
+        Self {
+            stdout: Box::new(stdout),
+            stderr: Box::new(stderr),
+        }
+    }
+
+    pub fn stdout(&mut self) -> &mut dyn Write {
+        self.stdout.as_mut()
+    }
+
+    pub fn stderr(&mut self) -> &mut dyn Write {
+        self.stderr.as_mut()
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.stdout.flush()?;
+        self.stderr.flush()
+    }
+}
+
+impl Default for Io {
+    fn default() -> Self {
+        Self::new(io::stdout(), io::stderr())
+    }
+}
+
+/// In-memory stream which stays readable after being passed to the VM
+#[derive(Debug, Default, Clone)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+
+    /// Returns written bytes and clears the buffer
+    pub fn take(&self) -> Vec<u8> {
+        self.0.take()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
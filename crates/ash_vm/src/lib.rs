@@ -1,4 +1,6 @@
 pub mod fault;
+mod io;
 mod memory;
+mod native;
 pub mod prelude;
 pub mod vm;
@@ -1,8 +1,262 @@
-use std::cell::Cell;
+use std::{cell::Cell, collections::HashMap, mem, rc::Rc};
 
-pub trait Collectable {}
+use ash_bytecode::prelude::{ObjRef, Value};
+
+use crate::native::NativeFunction;
+
+pub trait Collectable {
+    /// Adds objects referenced by self to `gray`
+    fn trace(&self, gray: &mut Vec<ObjRef>);
+
+    /// Bytes owned by the object
+    fn size(&self) -> usize;
+}
+
+#[derive(Debug)]
+pub enum Object {
+    String(Rc<str>),
+    Native(Rc<NativeFunction>),
+}
+
+impl Object {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::String(_) => "str",
+            Self::Native(_) => "fun",
+        }
+    }
+}
+
+impl Collectable for Object {
+    fn trace(&self, _gray: &mut Vec<ObjRef>) {
+        match self {
+            Self::String(_) | Self::Native(_) => {}
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Self::String(v) => v.len(),
+            // Owned by the registry
+            Self::Native(_) => 0,
+        }
+    }
+}
 
 pub struct GCObject<T> {
     inner: T,
     marked: Cell<bool>,
 }
+
+impl<T: Collectable> GCObject<T> {
+    fn new(inner: T) -> Self {
+        Self {
+            inner,
+            marked: Cell::new(false),
+        }
+    }
+
+    fn size(&self) -> usize {
+        mem::size_of::<Self>() + self.inner.size()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct GCConfig {
+    // Bytes allocated before the first collection
+    pub threshold: usize,
+    // Threshold multiplier applied to bytes that survived a collection
+    pub grow_factor: usize,
+}
+
+impl Default for GCConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 1024 * 1024,
+            grow_factor: 2,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GCStats {
+    pub collections: usize,
+    pub bytes_allocated: usize,
+    pub objects: usize,
+    pub objects_freed: usize,
+    pub next_gc: usize,
+}
+
+pub struct Heap {
+    objects: Vec<Option<GCObject<Object>>>,
+    free_slots: Vec<usize>,
+    // Every string is interned so equal strings share the same object
+    strings: HashMap<Rc<str>, ObjRef>,
+    config: GCConfig,
+    stats: GCStats,
+}
+
+impl Heap {
+    pub fn new(config: GCConfig) -> Self {
+        Self {
+            objects: Vec::new(),
+            free_slots: Vec::new(),
+            strings: HashMap::new(),
+            config,
+            stats: GCStats {
+                next_gc: config.threshold,
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn stats(&self) -> GCStats {
+        self.stats
+    }
+
+    pub fn get(&self, obj: ObjRef) -> Option<&Object> {
+        self.objects.get(obj.index())?.as_ref().map(|o| &o.inner)
+    }
+
+    pub fn get_str(&self, obj: ObjRef) -> Option<&str> {
+        match self.get(obj)? {
+            Object::String(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    pub fn interned(&self, s: &str) -> Option<ObjRef> {
+        self.strings.get(s).copied()
+    }
+
+    pub(crate) fn should_collect(&self) -> bool {
+        self.stats.bytes_allocated > self.stats.next_gc
+    }
+
+    /// Allocates string without collecting garbage.
+    /// Caller makes sure collection runs when needed
+    pub(crate) fn alloc_string(&mut self, s: &str) -> ObjRef {
+        if let Some(obj) = self.interned(s) {
+            return obj;
+        }
+
+        let s: Rc<str> = Rc::from(s);
+        let obj = self.alloc(Object::String(s.clone()));
+        self.strings.insert(s, obj);
+        obj
+    }
+
+    /// Allocates object without collecting garbage
+    pub(crate) fn alloc(&mut self, object: Object) -> ObjRef {
+        let object = GCObject::new(object);
+        self.stats.bytes_allocated += object.size();
+        self.stats.objects += 1;
+
+        match self.free_slots.pop() {
+            Some(index) => {
+                self.objects[index] = Some(object);
+                ObjRef::new(index)
+            }
+            None => {
+                self.objects.push(Some(object));
+                ObjRef::new(self.objects.len() - 1)
+            }
+        }
+    }
+
+    pub(crate) fn collect<'a, I>(&mut self, roots: I)
+    where
+        I: IntoIterator<Item = &'a Value>,
+    {
+        self.mark(roots);
+        self.sweep();
+
+        self.stats.collections += 1;
+        self.stats.next_gc = (self.stats.bytes_allocated * self.config.grow_factor)
+            .max(self.config.threshold);
+    }
+
+    fn mark<'a, I>(&mut self, roots: I)
+    where
+        I: IntoIterator<Item = &'a Value>,
+    {
+        let mut gray = roots
+            .into_iter()
+            .filter_map(|v| match v {
+                Value::Object(obj) => Some(*obj),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        while let Some(obj) = gray.pop() {
+            if let Some(Some(object)) = self.objects.get(obj.index()) {
+                if !object.marked.replace(true) {
+                    object.inner.trace(&mut gray);
+                }
+            }
+        }
+    }
+
+    fn sweep(&mut self) {
+        for (index, slot) in self.objects.iter_mut().enumerate() {
+            let object = match slot {
+                Some(object) => object,
+                None => continue,
+            };
+
+            if object.marked.replace(false) {
+                continue;
+            }
+
+            self.stats.bytes_allocated -= object.size();
+            self.stats.objects -= 1;
+            self.stats.objects_freed += 1;
+            if let Object::String(s) = &object.inner {
+                self.strings.remove(s);
+            }
+            *slot = None;
+            self.free_slots.push(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heap() -> Heap {
+        Heap::new(GCConfig {
+            threshold: 1,
+            grow_factor: 2,
+        })
+    }
+
+    #[test]
+    fn collect_frees_unreachable_strings() {
+        let mut heap = heap();
+        let kept = heap.alloc_string("kept");
+        let freed = heap.alloc_string("freed");
+        assert!(heap.should_collect());
+
+        heap.collect([&Value::Object(kept)]);
+
+        assert_eq!(heap.get_str(kept), Some("kept"));
+        assert!(heap.get(freed).is_none());
+        assert_eq!(heap.interned("freed"), None);
+        let stats = heap.stats();
+        assert_eq!(stats.collections, 1);
+        assert_eq!(stats.objects, 1);
+        assert_eq!(stats.objects_freed, 1);
+    }
+
+    #[test]
+    fn collect_reuses_freed_slots() {
+        let mut heap = heap();
+        let freed = heap.alloc_string("freed");
+        heap.collect(std::iter::empty());
+
+        let obj = heap.alloc_string("new");
+        assert_eq!(obj.index(), freed.index());
+        assert_eq!(heap.get_str(obj), Some("new"));
+    }
+}
@@ -0,0 +1,144 @@
+use std::{collections::HashMap, fmt, rc::Rc};
+
+use ash_bytecode::prelude::{NativeSignature, NativeTy, Value};
+
+use crate::{
+    fault::{VMError, VMResult},
+    io::Io,
+};
+
+/// Host function callable from Ash.
+/// Receives marshaled arguments, strings are passed as `Value::String`
+pub type NativeFn = dyn Fn(&mut Io, &[Value]) -> VMResult<Value>;
+
+pub struct NativeFunction {
+    pub signature: NativeSignature,
+    fun: Box<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn name(&self) -> &str {
+        &self.signature.name
+    }
+
+    pub(crate) fn call(&self, io: &mut Io, args: &[Value]) -> VMResult<Value> {
+        (self.fun)(io, args)
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<native {}>", self.signature)
+    }
+}
+
+/// Registry of native functions linked to `@[builtin]` prototypes by name
+#[derive(Default, Clone)]
+pub struct Natives {
+    functions: HashMap<String, Rc<NativeFunction>>,
+}
+
+impl Natives {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry with the standard builtins
+    pub fn std() -> Self {
+        let mut natives = Self::new();
+        natives.register("print", &[NativeTy::Str], NativeTy::Void, |io, args| {
+            write!(io.stdout(), "{}", args[0])?;
+            Ok(Value::Void)
+        });
+        natives.register("println", &[NativeTy::Str], NativeTy::Void, |io, args| {
+            writeln!(io.stdout(), "{}", args[0])?;
+            Ok(Value::Void)
+        });
+        natives.register("eprint", &[NativeTy::Str], NativeTy::Void, |io, args| {
+            write!(io.stderr(), "{}", args[0])?;
+            Ok(Value::Void)
+        });
+        natives.register("eprintln", &[NativeTy::Str], NativeTy::Void, |io, args| {
+            writeln!(io.stderr(), "{}", args[0])?;
+            Ok(Value::Void)
+        });
+        natives.register(
+            "printf",
+            &[NativeTy::Str, NativeTy::I32],
+            NativeTy::Void,
+            |io, args| {
+                write!(io.stdout(), "{}", format(&args[0].to_string(), &args[1..]))?;
+                Ok(Value::Void)
+            },
+        );
+
+        natives
+    }
+
+    /// Registers function under `name`, replacing previous one with the same name
+    pub fn register<F>(&mut self, name: &str, params: &[NativeTy], ret: NativeTy, fun: F)
+    where
+        F: Fn(&mut Io, &[Value]) -> VMResult<Value> + 'static,
+    {
+        let signature = NativeSignature {
+            name: name.to_owned(),
+            params: params.to_vec(),
+            ret,
+        };
+        let native = NativeFunction {
+            signature,
+            fun: Box::new(fun),
+        };
+        self.functions.insert(name.to_owned(), Rc::new(native));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Rc<NativeFunction>> {
+        self.functions.get(name)
+    }
+
+    pub fn signatures(&self) -> Vec<NativeSignature> {
+        self.functions
+            .values()
+            .map(|native| native.signature.clone())
+            .collect()
+    }
+}
+
+/// Replaces `%` placeholders (`%d`, `%s`, ...) with consecutive arguments, `%%` is a literal `%`
+fn format(format: &str, args: &[Value]) -> String {
+    let mut out = String::with_capacity(format.len());
+    let mut args = args.iter();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('%', Some('%')) => {
+                chars.next();
+                out.push('%');
+            }
+            ('%', Some(&spec)) if spec.is_ascii_alphabetic() => {
+                chars.next();
+                match args.next() {
+                    Some(arg) => out.push_str(&arg.to_string()),
+                    None => {
+                        out.push('%');
+                        out.push(spec);
+                    }
+                }
+            }
+            (c, _) => out.push(c),
+        }
+    }
+
+    out
+}
+
+pub(crate) fn check_ty(native: &NativeFunction, expected: NativeTy, value: &Value) -> VMResult {
+    match NativeTy::of(value) {
+        Some(ty) if ty == expected => Ok(()),
+        ty => Err(VMError::NativeType {
+            name: native.name().to_owned(),
+            expected,
+            got: ty.map(|ty| ty.name()).unwrap_or_else(|| value.type_name()),
+        }),
+    }
+}
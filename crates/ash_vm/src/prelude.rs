@@ -1,2 +1,5 @@
 pub use crate::fault::*;
+pub use crate::io::{Io, SharedBuffer};
+pub use crate::memory::{GCConfig, GCStats, Heap, Object};
+pub use crate::native::{NativeFn, NativeFunction, Natives};
 pub use crate::vm::*;
@@ -1,215 +1,746 @@
 use std::{
     collections::HashMap,
-    ops::{Add, Div, Mul, Rem, Sub},
+    ops::{Add, BitAnd, BitOr, BitXor, Div, Mul, Neg, Not, Rem, Shl, Shr, Sub},
+    rc::Rc,
 };
 
-use crate::{memory::Collectable, prelude::*};
+use crate::{native, prelude::*};
 use ash_bytecode::prelude::*;
 
+const FRAMES_MAX: usize = 256;
+const ENTRY_FUNCTION: &str = "main";
+
+struct CallFrame {
+    // None for the top level chunk
+    function: Option<Rc<Function>>,
+    ip: usize,
+    // Stack slot of the first argument
+    base: usize,
+}
+
+impl CallFrame {
+    fn name(&self) -> &str {
+        match &self.function {
+            Some(function) => &function.name,
+            None => "script",
+        }
+    }
+
+    fn chunk<'c>(&'c self, script: &'c Chunk) -> &'c Chunk {
+        match &self.function {
+            Some(function) => &function.chunk,
+            None => script,
+        }
+    }
+}
+
 pub struct VM<'a> {
     chunk: &'a Chunk,
-    ip: usize,
-    objects: Vec<&'a dyn Collectable>,
+    frames: Vec<CallFrame>,
+    heap: Heap,
+    natives: Natives,
+    io: Io,
     stack: Vec<Value>,
     globals: HashMap<String, Value>,
 }
 
 impl<'a> VM<'a> {
     pub fn new(chunk: &'a Chunk) -> Self {
+        Self::with_config(chunk, Natives::std(), GCConfig::default())
+    }
+
+    pub fn with_config(chunk: &'a Chunk, natives: Natives, gc_config: GCConfig) -> Self {
         Self {
             chunk,
-            ip: 0,
-            objects: Vec::new(),
+            frames: Vec::new(),
+            heap: Heap::new(gc_config),
+            natives,
+            io: Io::default(),
             stack: Vec::with_capacity(256),
             globals: HashMap::new(),
         }
     }
 
-    pub fn run(&mut self) -> VMResult {
-        loop {
-            let instr: OpCode = self.read_byte().into();
-            #[cfg(feature = "debug_info")]
-            {
-                print!("Stack: ");
-                if self.stack.is_empty() {
-                    print!("| ");
-                }
-                for v in self.stack.iter() {
-                    print!("| {} ", v.to_string())
-                }
-                println!("|");
-                instr.print(self.chunk, self.ip - 1);
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+
+    /// Natives have to be registered before the chunk links them
+    pub fn natives_mut(&mut self) -> &mut Natives {
+        &mut self.natives
+    }
+
+    pub fn set_io(&mut self, io: Io) {
+        self.io = io;
+    }
+
+    pub fn io_mut(&mut self) -> &mut Io {
+        &mut self.io
+    }
+
+    pub fn gc_stats(&self) -> GCStats {
+        self.heap.stats()
+    }
+
+    pub fn collect_garbage(&mut self) {
+        #[cfg(feature = "debug_info")]
+        let before = self.heap.stats().bytes_allocated;
+
+        // Functions of call frames are kept alive by their callee slot on the stack
+        let roots = self.stack.iter().chain(self.globals.values());
+        self.heap.collect(roots);
+
+        #[cfg(feature = "debug_info")]
+        {
+            let stats = self.heap.stats();
+            // Debug traces are best effort
+            let _ = writeln!(
+                self.io.stderr(),
+                "-- gc collected {} bytes (from {} to {}) next at {}",
+                before - stats.bytes_allocated,
+                before,
+                stats.bytes_allocated,
+                stats.next_gc
+            );
+        }
+    }
+
+    pub fn value_to_string(&self, value: &Value) -> String {
+        match value {
+            Value::Object(obj) => match self.heap.get(*obj) {
+                Some(Object::String(v)) => v.to_string(),
+                Some(Object::Native(native)) => format!("<native {}>", native.name()),
+                None => value.to_string(),
+            },
+            // Unlike `Display`, which rounds them for listings, every digit is kept
+            Value::F32(v) => format!("{v:?}"),
+            Value::F64(v) => format!("{v:?}"),
+            _ => value.to_string(),
+        }
+    }
+
+    /// Defines globals and returns value returned by the entry function
+    pub fn run(&mut self) -> Result<Value, Fault> {
+        self.init()?;
+        self.call_global(ENTRY_FUNCTION, Vec::new())
+    }
+
+    /// Runs the top level chunk which defines globals
+    pub fn init(&mut self) -> Result<(), Fault> {
+        self.frames.push(CallFrame {
+            function: None,
+            ip: 0,
+            base: 0,
+        });
+        self.execute().map(|_| ())
+    }
+
+    /// Calls function stored in global `name`
+    pub fn call_global(&mut self, name: &str, args: Vec<Value>) -> Result<Value, Fault> {
+        match self.globals.get(name) {
+            Some(callee) => self.call_value(callee.clone(), args),
+            None => Err(self.fault(VMError::UndefinedGlobal(self.source_name(name)), 0)),
+        }
+    }
+
+    /// Calls `callee` with arguments passed by the host.
+    /// Strings are passed and returned as `Value::String`
+    pub fn call_value(&mut self, callee: Value, args: Vec<Value>) -> Result<Value, Fault> {
+        let arg_count = args.len();
+        self.push(callee);
+        for arg in args {
+            let arg = self.unmarshal(arg);
+            self.push(arg);
+        }
+
+        if let Err(error) = self.call(arg_count) {
+            self.stack.clear();
+            return Err(self.fault(error, 0));
+        }
+        // Native functions return right away
+        let result = if self.frames.is_empty() {
+            self.stack.pop().unwrap_or(Value::Void)
+        } else {
+            self.execute()?
+        };
+
+        self.marshal_to_host(&result)
+            .map_err(|error| self.fault(error, 0))
+    }
+
+    /// Returns value of global `name`, strings are returned as `Value::String`.
+    /// Native functions live on the heap and are not handed out to the host
+    pub fn global(&self, name: &str) -> VMResult<Value> {
+        let value = self
+            .globals
+            .get(name)
+            .ok_or_else(|| VMError::UndefinedGlobal(self.source_name(name)))?;
+        self.marshal_to_host(value)
+    }
+
+    /// Replaces value of already defined global `name`,
+    /// globals of the program have to be mutable and keep their declared type
+    pub fn set_global(&mut self, name: &str, value: Value) -> VMResult {
+        if !self.globals.contains_key(name) {
+            return Err(VMError::UndefinedGlobal(self.source_name(name)));
+        }
+        if let Some((source_name, symbol)) = self.chunk.global_symbol(name) {
+            if !symbol.mutable {
+                return Err(VMError::ImmutableGlobal(source_name.to_owned()));
+            }
+            if value.type_name() != symbol.ty {
+                return Err(VMError::GlobalType {
+                    name: source_name.to_owned(),
+                    expected: symbol.ty.clone(),
+                    got: value.type_name(),
+                });
             }
+        }
 
-            match instr {
-                OpCode::Ret => {
-                    println!("{}", self.pop().to_string());
-                    return Ok(());
-                }
-                OpCode::Const => {
-                    let constant = self.read_const();
-                    self.push(constant);
-                }
-                OpCode::ConstLong => {
-                    let constant = self.read_const_long();
-                    self.push(constant);
-                }
-                OpCode::Neg => {
-                    let v = self.pop();
-                    self.push(-v);
-                }
-                OpCode::Sum => self.bin_op(Add::add),
-                OpCode::Sub => self.bin_op(Sub::sub),
-                OpCode::Mul => self.bin_op(Mul::mul),
-                OpCode::Div => self.bin_op(Div::div),
-                OpCode::Rem => self.bin_op(Rem::rem),
-                OpCode::True => self.push(Value::Bool(true)),
-                OpCode::False => self.push(Value::Bool(false)),
-                OpCode::Not => {
-                    let v = self.pop();
-                    self.push(!v);
-                }
-                OpCode::Eq => self.bin_op(Value::eq),
-                OpCode::Neq => self.bin_op(Value::neq),
-                OpCode::Gt => self.bin_op(Value::gt),
-                OpCode::Lt => self.bin_op(Value::lt),
-                OpCode::Gte => self.bin_op(Value::gte),
-                OpCode::Lte => self.bin_op(Value::lte),
-                OpCode::Pop => {
-                    let _ = self.pop();
-                }
-                OpCode::DefGlobal => {
-                    let name = self.read_const();
-                    self.def_global(name.string_value());
-                }
-                OpCode::DefGlobalLong => {
-                    let name = self.read_const_long();
-                    self.def_global(name.string_value());
-                }
-                OpCode::LoadGlobal => {
-                    let name = self.read_const().string_value();
-                    self.load_global(name);
-                }
-                OpCode::LoadGlobalLong => {
-                    let name = self.read_const_long().string_value();
-                    self.load_global(name);
-                }
-                OpCode::StoreGlobal => {
-                    let name = self.read_const().string_value();
-                    self.store_global(name);
-                }
-                OpCode::StoreGlobalLong => {
-                    let name = self.read_const_long().string_value();
-                    self.load_global(name);
-                }
-                OpCode::LoadLocal => {
-                    let slot = self.read_byte() as usize;
-                    self.load_local(slot);
-                }
-                OpCode::LoadLocalLong => {
-                    let slot = self.read_long();
-                    self.load_local(slot)
-                }
-                OpCode::StoreLocal => {
-                    let slot = self.read_byte() as usize;
-                    self.store_local(slot);
+        let value = self.unmarshal(value);
+        self.globals.insert(name.to_owned(), value);
+        Ok(())
+    }
+
+    /// Executes instructions until the first frame returns.
+    /// State is reset after a fault so the VM can be used again
+    fn execute(&mut self) -> Result<Value, Fault> {
+        let result = loop {
+            let offset = self.frame().ip;
+            match self.step() {
+                Ok(Some(result)) => break Ok(result),
+                Ok(None) => {}
+                Err(error) => {
+                    let fault = self.fault(error, offset);
+                    self.frames.clear();
+                    self.stack.clear();
+                    break Err(fault);
                 }
-                OpCode::StoreLocalLong => {
-                    let slot = self.read_long();
-                    self.store_local(slot);
+            }
+        };
+
+        // Output is flushed even when faulted so it shows up before the error
+        match self.io.flush() {
+            Err(error) if result.is_ok() => Err(self.fault(error.into(), 0)),
+            _ => result,
+        }
+    }
+
+    /// Executes single instruction.
+    /// Returns value once the first frame returns
+    fn step(&mut self) -> VMResult<Option<Value>> {
+        let byte = self.read_byte()?;
+        let instr = OpCode::try_from(byte).map_err(VMError::BadOpCode)?;
+        #[cfg(feature = "debug_info")]
+        self.trace_instruction();
+
+        match instr {
+            OpCode::Ret => {
+                let result = self.pop()?;
+                let frame = self.frames.pop().unwrap();
+                // Discards arguments and the callee, top level chunk has no callee
+                self.stack.truncate(frame.base.saturating_sub(1));
+                if self.frames.is_empty() {
+                    return Ok(Some(result));
                 }
-                OpCode::JmpIfFalse => {
-                    let offset = self.read_short();
-                    let cond = self.peek();
-                    if !cond.clone().bool_value() {
-                        self.ip += offset;
-                    }
+
+                self.push(result);
+            }
+            OpCode::Const => {
+                let constant = self.read_const()?;
+                self.push_const(constant);
+            }
+            OpCode::ConstLong => {
+                let constant = self.read_const_long()?;
+                self.push_const(constant);
+            }
+            OpCode::Neg => self.unary_op(instr, Neg::neg)?,
+            OpCode::Sum => self.sum()?,
+            OpCode::Sub => self.bin_op(instr, Sub::sub)?,
+            OpCode::Mul => self.bin_op(instr, Mul::mul)?,
+            OpCode::Div => self.bin_op(instr, Div::div)?,
+            OpCode::Rem => self.bin_op(instr, Rem::rem)?,
+            OpCode::True => self.push(Value::Bool(true)),
+            OpCode::False => self.push(Value::Bool(false)),
+            OpCode::Not => self.unary_op(instr, Not::not)?,
+            OpCode::Eq => self.bin_op(instr, Value::eq)?,
+            OpCode::Neq => self.bin_op(instr, Value::neq)?,
+            OpCode::Gt => self.bin_op(instr, Value::gt)?,
+            OpCode::Lt => self.bin_op(instr, Value::lt)?,
+            OpCode::Gte => self.bin_op(instr, Value::gte)?,
+            OpCode::Lte => self.bin_op(instr, Value::lte)?,
+            OpCode::BitAnd => self.bin_op(instr, BitAnd::bitand)?,
+            OpCode::BitOr => self.bin_op(instr, BitOr::bitor)?,
+            OpCode::BitXor => self.bin_op(instr, BitXor::bitxor)?,
+            OpCode::BitNot => self.unary_op(instr, Value::bit_not)?,
+            OpCode::Shl => self.bin_op(instr, Shl::shl)?,
+            OpCode::Shr => self.bin_op(instr, Shr::shr)?,
+            OpCode::LoadCallee => {
+                let function = self.frame().function.clone().ok_or(VMError::NoCallee)?;
+                self.push(Value::Function(function));
+            }
+            OpCode::ToStr => self.stringify()?,
+            OpCode::Cast => {
+                let ty = NumTy::try_from(self.read_byte()?).map_err(VMError::BadCastType)?;
+                self.unary_op(instr, |v| v.cast(ty))?
+            }
+            OpCode::Pop => {
+                let _ = self.pop()?;
+            }
+            OpCode::DefGlobal => {
+                let name = self.read_const()?;
+                self.def_global(instr, name)?;
+            }
+            OpCode::DefGlobalLong => {
+                let name = self.read_const_long()?;
+                self.def_global(instr, name)?;
+            }
+            OpCode::LoadGlobal => {
+                let name = self.read_const()?;
+                self.load_global(instr, name)?;
+            }
+            OpCode::LoadGlobalLong => {
+                let name = self.read_const_long()?;
+                self.load_global(instr, name)?;
+            }
+            OpCode::StoreGlobal => {
+                let name = self.read_const()?;
+                self.store_global(instr, name)?;
+            }
+            OpCode::StoreGlobalLong => {
+                let name = self.read_const_long()?;
+                self.store_global(instr, name)?;
+            }
+            OpCode::LoadLocal => {
+                let slot = self.read_byte()? as usize;
+                self.load_local(slot)?;
+            }
+            OpCode::LoadLocalLong => {
+                let slot = self.read_long()?;
+                self.load_local(slot)?;
+            }
+            OpCode::StoreLocal => {
+                let slot = self.read_byte()? as usize;
+                self.store_local(slot)?;
+            }
+            OpCode::StoreLocalLong => {
+                let slot = self.read_long()?;
+                self.store_local(slot)?;
+            }
+            OpCode::JmpIfFalse => {
+                let offset = self.read_short()?;
+                let cond = self.peek()?;
+                let ty = self.type_name(cond);
+                let cond = cond
+                    .clone()
+                    .bool_value()
+                    .map_err(|err| VMError::from_value_error(err, instr, &[ty]))?;
+                if !cond {
+                    self.frame_mut().ip += offset;
                 }
-                OpCode::Jmp => {
-                    let offset = self.read_short();
-                    self.ip += offset;
+            }
+            OpCode::Jmp => {
+                let offset = self.read_short()?;
+                self.frame_mut().ip += offset;
+            }
+            OpCode::Loop => {
+                let offset = self.read_short()?;
+                let frame = self.frame_mut();
+                frame.ip = frame.ip.checked_sub(offset).ok_or(VMError::UnexpectedEnd)?;
+            }
+            OpCode::Void => self.push(Value::Void),
+            OpCode::Call => {
+                let arg_count = self.read_byte()? as usize;
+                self.call(arg_count)?;
+            }
+            OpCode::LoadNative => {
+                let name = self.read_const()?;
+                self.load_native(instr, name)?;
+            }
+            OpCode::LoadNativeLong => {
+                let name = self.read_const_long()?;
+                self.load_native(instr, name)?;
+            }
+        }
+
+        Ok(None)
+    }
+
+    #[cfg(feature = "debug_info")]
+    fn trace_instruction(&mut self) {
+        let mut stack = String::new();
+        if self.stack.is_empty() {
+            stack.push_str("| ");
+        }
+        for v in self.stack.iter() {
+            stack.push_str(&format!("| {} ", self.value_to_string(v)));
+        }
+
+        let frame = self.frames.last().unwrap();
+        let name = self.source_name(frame.name());
+        let out = self.io.stderr();
+        // Debug traces are best effort
+        let _ = writeln!(out, "[{name}] Stack: {stack}|");
+        let _ = frame
+            .chunk(self.chunk)
+            .disassemble_instruction(frame.ip - 1, out);
+    }
+
+    fn fault(&self, error: VMError, offset: usize) -> Fault {
+        let trace = self
+            .frames
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(i, frame)| {
+                let offset = if i == 0 {
+                    offset
+                } else {
+                    // Callers are stopped right after the 2 byte call instruction
+                    frame.ip.saturating_sub(2)
+                };
+                let span = frame.chunk(self.chunk).span_at(offset).cloned();
+
+                TraceFrame {
+                    function: self.source_name(frame.name()),
+                    offset,
+                    span,
                 }
-                OpCode::Loop => {
-                    let offset = self.read_short();
-                    self.ip -= offset;
+            })
+            .collect::<Vec<_>>();
+        let span = trace.first().and_then(|frame| frame.span.clone());
+
+        Fault {
+            error,
+            offset,
+            span,
+            trace,
+        }
+    }
+
+    fn call(&mut self, arg_count: usize) -> VMResult {
+        let base = self
+            .stack
+            .len()
+            .checked_sub(arg_count)
+            .filter(|base| *base > 0)
+            .ok_or(VMError::StackUnderflow)?;
+        let function = match &self.stack[base - 1] {
+            Value::Function(function) => function.clone(),
+            Value::Object(obj) => match self.heap.get(*obj) {
+                Some(Object::Native(native)) => return self.call_native(native.clone(), base),
+                _ => return Err(VMError::NotCallable(self.value_to_string(&self.stack[base - 1]))),
+            },
+            callee => return Err(VMError::NotCallable(callee.to_string())),
+        };
+
+        if function.arity as usize != arg_count {
+            return Err(VMError::ArityMismatch {
+                name: self.source_name(&function.name),
+                expected: function.arity,
+                got: arg_count,
+            });
+        }
+
+        if self.frames.len() == FRAMES_MAX {
+            return Err(VMError::StackOverflow(self.source_name(&function.name)));
+        }
+
+        self.frames.push(CallFrame {
+            function: Some(function),
+            ip: 0,
+            base,
+        });
+
+        Ok(())
+    }
+
+    fn call_native(&mut self, native: Rc<NativeFunction>, base: usize) -> VMResult {
+        let params = &native.signature.params;
+        let arg_count = self.stack.len() - base;
+        if params.len() != arg_count {
+            return Err(VMError::ArityMismatch {
+                name: self.source_name(native.name()),
+                expected: params.len() as u8,
+                got: arg_count,
+            });
+        }
+
+        let args = self.stack[base..]
+            .iter()
+            .zip(params)
+            .map(|(arg, ty)| {
+                let arg = self.marshal(arg);
+                native::check_ty(&native, *ty, &arg)?;
+                Ok(arg)
+            })
+            .collect::<VMResult<Vec<_>>>()?;
+        let result = native.call(&mut self.io, &args)?;
+        native::check_ty(&native, native.signature.ret, &result)?;
+
+        // Discards arguments and the callee
+        self.stack.truncate(base - 1);
+        let result = self.unmarshal(result);
+        self.push(result);
+        Ok(())
+    }
+
+    /// Converts heap values into values understood by the host
+    fn marshal(&self, value: &Value) -> Value {
+        match value {
+            Value::Object(obj) => match self.heap.get(*obj) {
+                Some(Object::String(v)) => Value::String(v.to_string()),
+                _ => value.clone(),
+            },
+            _ => value.clone(),
+        }
+    }
+
+    /// Converts values leaving the VM, heap handles would not be rooted once returned
+    fn marshal_to_host(&self, value: &Value) -> VMResult<Value> {
+        match value {
+            Value::Object(obj) => match self.heap.get(*obj) {
+                Some(Object::String(v)) => Ok(Value::String(v.to_string())),
+                Some(Object::Native(native)) => {
+                    Err(VMError::NativeToHost(self.source_name(native.name())))
                 }
-            }
+                None => Err(VMError::NativeToHost(format!("{obj:?}"))),
+            },
+            _ => Ok(value.clone()),
         }
     }
 
-    fn def_global(&mut self, name: String) {
-        self.globals.insert(name, self.peek().clone());
-        let _ = self.pop();
+    /// Moves values passed by the host onto the heap
+    fn unmarshal(&mut self, value: Value) -> Value {
+        match value {
+            Value::String(v) => self.alloc_string(&v),
+            value => value,
+        }
     }
 
-    fn load_global(&mut self, name: String) {
-        let value = self.globals.get(&name).unwrap();
+    fn load_native(&mut self, instr: OpCode, name: Value) -> VMResult {
+        let name = self.global_name(instr, name)?;
+        let native = self
+            .natives
+            .get(&name)
+            .cloned()
+            .ok_or(VMError::UndefinedNative(name))?;
+        if self.heap.should_collect() {
+            self.collect_garbage();
+        }
+
+        let obj = self.heap.alloc(Object::Native(native));
+        self.push(Value::Object(obj));
+        Ok(())
+    }
+
+    fn def_global(&mut self, instr: OpCode, name: Value) -> VMResult {
+        let name = self.global_name(instr, name)?;
+        let value = self.pop()?;
+        self.globals.insert(name, value);
+        Ok(())
+    }
+
+    fn load_global(&mut self, instr: OpCode, name: Value) -> VMResult {
+        let name = self.global_name(instr, name)?;
+        let value = self
+            .globals
+            .get(&name)
+            .ok_or_else(|| VMError::UndefinedGlobal(self.source_name(&name)))?;
         self.push(value.clone());
+        Ok(())
+    }
+
+    fn store_global(&mut self, instr: OpCode, name: Value) -> VMResult {
+        let name = self.global_name(instr, name)?;
+        let v = self.pop()?;
+        match self.globals.get_mut(&name) {
+            Some(global) => *global = v,
+            None => return Err(VMError::UndefinedGlobal(self.source_name(&name))),
+        }
+        Ok(())
+    }
+
+    /// Name the global is declared with in source, globals without a symbol keep their name
+    fn source_name(&self, global: &str) -> String {
+        self.chunk.source_name(global).unwrap_or(global).to_owned()
+    }
+
+    fn global_name(&self, instr: OpCode, name: Value) -> VMResult<String> {
+        let ty = name.type_name();
+        name.string_value()
+            .map_err(|err| VMError::from_value_error(err, instr, &[ty]))
+    }
+
+    fn load_local(&mut self, slot: usize) -> VMResult {
+        let v = self
+            .stack
+            .get(self.frame().base + slot)
+            .ok_or(VMError::UndefinedLocal(slot))?
+            .clone();
+        self.push(v);
+        Ok(())
+    }
+
+    fn store_local(&mut self, slot: usize) -> VMResult {
+        let v = self.pop()?;
+        let base = self.frame().base;
+        let local = self
+            .stack
+            .get_mut(base + slot)
+            .ok_or(VMError::UndefinedLocal(slot))?;
+        *local = v;
+        Ok(())
+    }
+
+    fn push_const(&mut self, constant: Value) {
+        match constant {
+            Value::String(v) => {
+                let v = self.alloc_string(&v);
+                self.push(v);
+            }
+            constant => self.push(constant),
+        }
+    }
+
+    fn alloc_string(&mut self, s: &str) -> Value {
+        if self.heap.interned(s).is_none() && self.heap.should_collect() {
+            self.collect_garbage();
+        }
+
+        Value::Object(self.heap.alloc_string(s))
     }
 
-    fn store_global(&mut self, name: String) {
-        let v = self.pop();
-        self.globals.insert(name, v);
+    fn sum(&mut self) -> VMResult {
+        let len = self.stack.len();
+        let concatenated = match self.stack.get(len.saturating_sub(2)..) {
+            Some([Value::Object(a), Value::Object(b)]) => {
+                match (self.heap.get_str(*a), self.heap.get_str(*b)) {
+                    (Some(a), Some(b)) => Some([a, b].concat()),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+
+        match concatenated {
+            Some(s) => {
+                // Operands stay on the stack during allocation so they survive collection
+                let v = self.alloc_string(&s);
+                self.stack.truncate(len - 2);
+                self.push(v);
+                Ok(())
+            }
+            None => self.bin_op(OpCode::Sum, Add::add),
+        }
     }
 
-    fn load_local(&mut self, slot: usize) {
-        let v = self.stack[slot].clone();
+    fn stringify(&mut self) -> VMResult {
+        let s = self.value_to_string(self.peek()?);
+        // Converted value stays on the stack during allocation so it survives collection
+        let v = self.alloc_string(&s);
+        self.pop()?;
         self.push(v);
+        Ok(())
     }
 
-    fn store_local(&mut self, slot: usize) {
-        let v = self.pop();
-        self.stack[slot] = v;
+    fn type_name(&self, value: &Value) -> &'static str {
+        match value {
+            Value::Object(obj) => self
+                .heap
+                .get(*obj)
+                .map(Object::type_name)
+                .unwrap_or("object"),
+            _ => value.type_name(),
+        }
+    }
+
+    fn unary_op<F>(&mut self, instr: OpCode, op_f: F) -> VMResult
+    where
+        F: FnOnce(Value) -> ValueResult,
+    {
+        let v = self.pop()?;
+        let ty = self.type_name(&v);
+        let v = op_f(v).map_err(|err| VMError::from_value_error(err, instr, &[ty]))?;
+        self.push(v);
+        Ok(())
     }
 
-    fn bin_op<F>(&mut self, op_f: F)
+    fn bin_op<F>(&mut self, instr: OpCode, op_f: F) -> VMResult
     where
-        F: FnOnce(Value, Value) -> Value,
+        F: FnOnce(Value, Value) -> ValueResult,
     {
-        let b = self.pop();
-        let a = self.pop();
-        self.push(op_f(a, b))
+        let b = self.pop()?;
+        let a = self.pop()?;
+        let types = [self.type_name(&a), self.type_name(&b)];
+        let v = op_f(a, b).map_err(|err| VMError::from_value_error(err, instr, &types))?;
+        self.push(v);
+        Ok(())
     }
 
-    fn read_byte(&mut self) -> u8 {
-        let b = self.chunk.get_byte(self.ip);
-        self.ip += 1;
-        b
+    fn frame(&self) -> &CallFrame {
+        self.frames.last().unwrap()
     }
 
-    fn read_short(&mut self) -> usize {
-        let c1 = self.read_byte() as usize;
-        let c2 = self.read_byte() as usize;
+    fn frame_mut(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().unwrap()
+    }
+
+    fn chunk(&self) -> &Chunk {
+        self.frame().chunk(self.chunk)
+    }
 
-        c1 | (c2 << 8) 
+    fn read_byte(&mut self) -> VMResult<u8> {
+        let frame = self.frames.last_mut().unwrap();
+        let b = frame
+            .chunk(self.chunk)
+            .get_byte(frame.ip)
+            .ok_or(VMError::UnexpectedEnd)?;
+        frame.ip += 1;
+        Ok(b)
     }
 
-    fn read_long(&mut self) -> usize {
-        let c1 = self.read_byte() as usize;
-        let c2 = self.read_byte() as usize;
-        let c3 = self.read_byte() as usize;
+    fn read_short(&mut self) -> VMResult<usize> {
+        let c1 = self.read_byte()? as usize;
+        let c2 = self.read_byte()? as usize;
+
+        Ok(c1 | (c2 << 8))
+    }
+
+    fn read_long(&mut self) -> VMResult<usize> {
+        let c1 = self.read_byte()? as usize;
+        let c2 = self.read_byte()? as usize;
+        let c3 = self.read_byte()? as usize;
+
+        Ok(c1 | (c2 << 8) | (c3 << 16))
+    }
 
-        c1 | (c2 << 8) | (c3 << 16)
+    fn read_const(&mut self) -> VMResult<Value> {
+        let index = self.read_byte()? as usize;
+        self.get_const(index)
     }
 
-    fn read_const(&mut self) -> Value {
-        self.chunk.get_const(self.read_byte() as usize).clone()
+    fn read_const_long(&mut self) -> VMResult<Value> {
+        let index = self.read_long()?;
+        self.get_const(index)
     }
 
-    fn read_const_long(&mut self) -> Value {
-        let index = self.read_long();
-        self.chunk.get_const(index).clone()
+    fn get_const(&self, index: usize) -> VMResult<Value> {
+        self.chunk()
+            .get_const(index)
+            .cloned()
+            .ok_or(VMError::UndefinedConstant(index))
     }
 
-    fn peek(&self) -> &Value {
-        self.stack.last().unwrap()
+    fn peek(&self) -> VMResult<&Value> {
+        self.stack.last().ok_or(VMError::StackUnderflow)
     }
 
     fn push(&mut self, value: Value) {
         self.stack.push(value);
     }
 
-    fn pop(&mut self) -> Value {
-        self.stack.pop().unwrap()
+    fn pop(&mut self) -> VMResult<Value> {
+        self.stack.pop().ok_or(VMError::StackUnderflow)
     }
 }
@@ -1,7 +1,16 @@
-use crate::code;
-use anyhow::Result;
+use crate::failure::{
+    report::{self, MessageFormat},
+    Reported,
+};
+use crate::{code, lsp, repl};
+use anyhow::{bail, Result};
 use argh::FromArgs;
-use std::path::PathBuf;
+use ash_core::prelude::Stage;
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process,
+};
 
 /// All commands
 #[derive(FromArgs, Debug)]
@@ -14,6 +23,12 @@ struct TopLevel {
 #[argh(subcommand)]
 enum CliOptions {
     Run(RunOptions),
+    Build(BuildOptions),
+    Check(CheckOptions),
+    Dump(DumpOptions),
+    Fmt(FmtOptions),
+    Repl(ReplOptions),
+    Lsp(LspOptions),
 }
 
 /// Runs provided file or project
@@ -21,15 +36,158 @@ enum CliOptions {
 #[argh(subcommand, name = "run")]
 pub struct RunOptions {
     /// path to file or project
-    #[argh(option, default = "std::env::current_dir().unwrap()")]
+    #[argh(positional)]
+    pub path: Option<PathBuf>,
+    /// same as the positional path
+    #[argh(option, long = "path")]
+    pub path_option: Option<PathBuf>,
+    /// format of diagnostics, human or json
+    #[argh(option, default = "MessageFormat::Human")]
+    pub message_format: MessageFormat,
+}
+
+/// Compiles provided file or project to bytecode without running it
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand, name = "build")]
+pub struct BuildOptions {
+    /// path to file or project
+    #[argh(positional, default = "std::env::current_dir().unwrap()")]
+    pub path: PathBuf,
+    /// directory for compiled files, defaults to `out` next to the source or in the project root
+    #[argh(option)]
+    pub out_dir: Option<PathBuf>,
+    /// format of diagnostics, human or json
+    #[argh(option, default = "MessageFormat::Human")]
+    pub message_format: MessageFormat,
+}
+
+/// Reports errors in provided file or project without generating code
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand, name = "check")]
+pub struct CheckOptions {
+    /// path to file or project
+    #[argh(positional, default = "std::env::current_dir().unwrap()")]
+    pub path: PathBuf,
+    /// format of diagnostics, human or json
+    #[argh(option, default = "MessageFormat::Human")]
+    pub message_format: MessageFormat,
+}
+
+/// Prints output of a compiler stage for provided file
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand, name = "dump")]
+pub struct DumpOptions {
+    /// path to file
+    #[argh(positional)]
+    pub path: PathBuf,
+    /// one of tokens, ast, resolved, hir, typed, bytecode
+    #[argh(option)]
+    pub stage: Stage,
+    /// format of diagnostics, human or json
+    #[argh(option, default = "MessageFormat::Human")]
+    pub message_format: MessageFormat,
+}
+
+/// Formats provided file or every file of a project
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand, name = "fmt")]
+pub struct FmtOptions {
+    /// path to file or project
+    #[argh(positional, default = "std::env::current_dir().unwrap()")]
     pub path: PathBuf,
+    /// report files which are not formatted instead of writing them
+    #[argh(switch)]
+    pub check: bool,
+    /// format of diagnostics, human or json
+    #[argh(option, default = "MessageFormat::Human")]
+    pub message_format: MessageFormat,
+}
+
+/// Starts interactive session, globals are kept between inputs
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand, name = "repl")]
+pub struct ReplOptions {
+    /// format of diagnostics, human or json
+    #[argh(option, default = "MessageFormat::Human")]
+    pub message_format: MessageFormat,
+}
+
+/// Starts language server on stdio
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand, name = "lsp")]
+pub struct LspOptions {}
+
+impl RunOptions {
+    /// Path given either way, defaults to the current directory
+    pub fn path(&self) -> Result<PathBuf> {
+        match (&self.path, &self.path_option) {
+            (Some(_), Some(_)) => bail!("Path is given both as an argument and with --path"),
+            (Some(path), None) | (None, Some(path)) => Ok(path.clone()),
+            (None, None) => Ok(env::current_dir()?),
+        }
+    }
+}
+
+impl CliOptions {
+    fn message_format(&self) -> MessageFormat {
+        match self {
+            Self::Run(options) => options.message_format,
+            Self::Build(options) => options.message_format,
+            Self::Check(options) => options.message_format,
+            Self::Dump(options) => options.message_format,
+            Self::Fmt(options) => options.message_format,
+            Self::Repl(options) => options.message_format,
+            Self::Lsp(_) => MessageFormat::Human,
+        }
+    }
 }
 
 pub fn init() -> Result<()> {
-    let top_level: TopLevel = argh::from_env();
-    match top_level.nested {
-        CliOptions::Run(options) => code::run(options)?,
+    let top_level = parse_args();
+    let format = top_level.nested.message_format();
+    let result = match top_level.nested {
+        CliOptions::Run(options) => code::run(options),
+        CliOptions::Build(options) => code::build(options),
+        CliOptions::Check(options) => code::check(options),
+        CliOptions::Dump(options) => code::dump(options),
+        CliOptions::Fmt(options) => code::fmt(options),
+        CliOptions::Repl(options) => repl::run(options),
+        CliOptions::Lsp(options) => lsp::run(options),
+    };
+
+    match result {
+        // Unreported errors are left to `main` in human format
+        Err(why) if format == MessageFormat::Json && !why.is::<Reported>() => {
+            report::failure(&why, format);
+            Err(Reported.into())
+        }
+        result => result,
     }
+}
+
+/// Same as `argh::from_env`, but options can also be passed as `--option=value`
+fn parse_args() -> TopLevel {
+    let args = env::args().collect::<Vec<_>>();
+    let cmd = Path::new(&args[0])
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(&args[0]);
+    let args = args[1..]
+        .iter()
+        .flat_map(|arg| match arg.split_once('=') {
+            Some((option, value)) if option.starts_with("--") => vec![option, value],
+            _ => vec![arg.as_str()],
+        })
+        .collect::<Vec<_>>();
 
-    Ok(())
+    TopLevel::from_args(&[cmd], &args).unwrap_or_else(|early_exit| {
+        match early_exit.status {
+            Ok(()) => println!("{}", early_exit.output),
+            Err(()) => eprintln!(
+                "{}\nRun {cmd} --help for more information.",
+                early_exit.output
+            ),
+        }
+        process::exit(early_exit.status.map_or(1, |_| 0))
+    })
 }
@@ -1,20 +1,196 @@
-use crate::cli::RunOptions;
-use crate::failure::report;
+use crate::cli::{BuildOptions, CheckOptions, DumpOptions, FmtOptions, RunOptions};
+use crate::failure::{
+    report::{self, MessageFormat},
+    Reported,
+};
+use crate::project::Project;
 use anyhow::{bail, Result};
+use ash_bytecode::prelude::{Chunk, EXTENSION};
 use ash_core::prelude as ash;
 use ash_vm::prelude::*;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
 pub fn run(options: RunOptions) -> Result<()> {
-    if !options.path.exists() {
+    let path = options.path()?;
+    if !path.exists() {
         bail!("Path does not exist");
     }
-    if options.path.is_file() {
-        let src = ash::Source::from_file(options.path)?;
-        if let Err(errs) = ash::build(&src) {
-            errs.into_iter().for_each(|err| report::error(&src, err));
+    if is_bytecode(&path) {
+        return run_bytecode(&path, options.message_format);
+    }
+
+    // Files of a project are run as the whole project, so their imports resolve
+    let project = match path.is_file() {
+        true => Project::containing(&path)?,
+        false => Some(Project::load(&path)?),
+    };
+    if let Some(project) = project {
+        let (sources, chunk) = compile_project(&project, options.message_format)?;
+        if let Err(fault) = VM::new(&chunk).run() {
+            report::fault_in(&sources, &fault, options.message_format);
+            return Err(Reported.into());
         }
     } else {
-        unimplemented!();
+        let (src, chunk) = compile(path, options.message_format)?;
+        if let Err(fault) = VM::new(&chunk).run() {
+            report::fault(&src, &fault, options.message_format);
+            return Err(Reported.into());
+        }
+    }
+
+    Ok(())
+}
+
+pub fn build(options: BuildOptions) -> Result<()> {
+    if !options.path.exists() {
+        bail!("Path does not exist");
+    }
+    if options.path.is_dir() {
+        let project = Project::load(&options.path)?;
+        let (_, chunk) = compile_project(&project, options.message_format)?;
+        let out_dir = options.out_dir.unwrap_or_else(|| project.root.join("out"));
+        let out = out_dir.join(project.name()).with_extension(EXTENSION);
+        ash::write_chunk(&chunk, &out)?;
+        let size = fs::metadata(&out)?.len();
+        println!(
+            "Built {} v{} -> {} ({size} bytes)",
+            project.name(),
+            project.version(),
+            out.display()
+        );
+        return Ok(());
+    }
+
+    let (src, chunk) = compile(options.path, options.message_format)?;
+    let out = ash::write_out(&src, &chunk, options.out_dir.as_deref())?;
+    let size = fs::metadata(&out)?.len();
+    println!("Built {} -> {} ({size} bytes)", src.location(), out.display());
+
+    Ok(())
+}
+
+pub fn check(options: CheckOptions) -> Result<()> {
+    if !options.path.exists() {
+        bail!("Path does not exist");
+    }
+    let natives = Natives::std().signatures();
+    if options.path.is_dir() {
+        let project = Project::load(&options.path)?;
+        let sources = project.sources()?;
+        if let Err(errs) = ash::check_project(&sources, &natives) {
+            errs.into_iter().for_each(|err| report::error_in(&sources, err, options.message_format));
+            return Err(Reported.into());
+        }
+        println!("Checked {} v{}", project.name(), project.version());
+        return Ok(());
+    }
+
+    let src = ash::Source::from_file(options.path)?;
+    if let Err(errs) = ash::check(&src, &natives) {
+        errs.into_iter().for_each(|err| report::error(&src, err, options.message_format));
+        return Err(Reported.into());
+    }
+    println!("Checked {}", src.location());
+
+    Ok(())
+}
+
+pub fn dump(options: DumpOptions) -> Result<()> {
+    if !options.path.is_file() {
+        bail!("Path is not a file");
+    }
+    let src = ash::Source::from_file(options.path)?;
+    match ash::dump(&src, &Natives::std().signatures(), options.stage) {
+        Ok(out) => print!("{out}"),
+        Err(errs) => {
+            errs.into_iter().for_each(|err| report::error(&src, err, options.message_format));
+            return Err(Reported.into());
+        }
+    }
+
+    Ok(())
+}
+
+pub fn fmt(options: FmtOptions) -> Result<()> {
+    if !options.path.exists() {
+        bail!("Path does not exist");
+    }
+    let files = match options.path.is_dir() {
+        true => Project::load(&options.path)?.files()?,
+        false => vec![options.path],
+    };
+
+    let mut failed = false;
+    for file in files {
+        let code = fs::read_to_string(&file)?;
+        let formatted = match ash::format(&code) {
+            Ok(formatted) => formatted,
+            Err(errs) => {
+                let src = ash::Source::from_string(code).with_location(file.display().to_string());
+                errs.into_iter().for_each(|err| report::error(&src, err, options.message_format));
+                failed = true;
+                continue;
+            }
+        };
+
+        if formatted == code {
+            continue;
+        }
+        if options.check {
+            let src = ash::Source::from_string(code).with_location(file.display().to_string());
+            report::unformatted(&src, options.message_format);
+            failed = true;
+        } else {
+            fs::write(&file, formatted)?;
+            println!("Formatted {}", file.display());
+        }
+    }
+
+    match failed {
+        true => Err(Reported.into()),
+        false => Ok(()),
+    }
+}
+
+/// Compiles file to bytecode, errors are reported
+fn compile(path: PathBuf, format: MessageFormat) -> Result<(ash::Source, Chunk)> {
+    let src = ash::Source::from_file(path)?;
+    let natives = Natives::std();
+    match ash::build(&src, &natives.signatures()) {
+        Ok(chunk) => Ok((src, chunk)),
+        Err(errs) => {
+            errs.into_iter().for_each(|err| report::error(&src, err, format));
+            Err(Reported.into())
+        }
+    }
+}
+
+/// Compiles every source file of the project together, errors are reported
+fn compile_project(project: &Project, format: MessageFormat) -> Result<(ash::SourceMap, Chunk)> {
+    let sources = project.sources()?;
+    let natives = Natives::std();
+    match ash::build_project(&sources, &natives.signatures()) {
+        Ok(chunk) => Ok((sources, chunk)),
+        Err(errs) => {
+            errs.into_iter().for_each(|err| report::error_in(&sources, err, format));
+            Err(Reported.into())
+        }
+    }
+}
+
+fn is_bytecode(path: &Path) -> bool {
+    path.is_file() && path.extension().is_some_and(|ext| ext == EXTENSION)
+}
+
+/// Runs file precompiled to bytecode, faults are reported without source
+fn run_bytecode(path: &Path, format: MessageFormat) -> Result<()> {
+    let chunk = Chunk::from_bytes(&fs::read(path)?)?;
+    if let Err(fault) = VM::new(&chunk).run() {
+        report::fault_unlocated(&fault, format);
+        return Err(Reported.into());
     }
 
     Ok(())
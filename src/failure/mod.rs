@@ -1 +1,15 @@
+use std::{error::Error, fmt};
+
 pub mod report;
+
+/// Errors were already shown to the user, only the exit code is left to set
+#[derive(Debug)]
+pub struct Reported;
+
+impl fmt::Display for Reported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Aborting due to previous errors")
+    }
+}
+
+impl Error for Reported {}
@@ -1,72 +1,247 @@
-use std::hash::Hash;
+use std::{fmt, hash::Hash, str::FromStr};
 
-use ariadne::{Color, Fmt, Label, Report, ReportKind, Source, Config};
+use ariadne::{Color, Config, Fmt, Label, Report, ReportKind, Source};
+use serde::Serialize;
 
-use ash_core::prelude::{Simple, SimpleReason, Source as SvSource};
+use ash_core::prelude::{error_code, Simple, SimpleReason, Source as SvSource, SourceMap, Span};
+use ash_vm::prelude::Fault;
 
-pub fn error<T>(source: &SvSource, err: Simple<T>)
+// Faults are not produced by the compiler, so their code is defined here
+const RUNTIME_ERROR: &str = "E0900";
+// Failures of the command itself, e.g. unreadable files or invalid bytecode
+const COMMAND_ERROR: &str = "E0800";
+const UNFORMATTED: &str = "E0500";
+
+/// How diagnostics are shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MessageFormat {
+    /// Rendered reports on stderr
+    #[default]
+    Human,
+    /// One JSON object per diagnostic on stdout
+    Json,
+}
+
+impl FromStr for MessageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("Unknown message format `{s}`, expected human or json")),
+        }
+    }
+}
+
+impl fmt::Display for MessageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Human => f.write_str("human"),
+            Self::Json => f.write_str("json"),
+        }
+    }
+}
+
+/// Error or fault with spans local to its source
+struct Diagnostic {
+    code: &'static str,
+    message: String,
+    // Primary label comes first
+    labels: Vec<(Span, String, Color)>,
+}
+
+pub fn error<T>(source: &SvSource, err: Simple<T>, format: MessageFormat)
+where
+    T: ToString + Hash + Eq,
+{
+    error_at(Some((0, source)), err, format)
+}
+
+/// Reports error of sources compiled together
+pub fn error_in<T>(sources: &SourceMap, err: Simple<T>, format: MessageFormat)
 where
     T: ToString + Hash + Eq,
 {
+    error_at(sources.locate(err.span().start), err, format)
+}
+
+/// Error spans are shifted by offset of the source
+fn error_at<T>(source: Option<(usize, &SvSource)>, err: Simple<T>, format: MessageFormat)
+where
+    T: ToString + Hash + Eq,
+{
+    let code = error_code(&err);
     let err = err.map(|c| c.to_string());
-    let location = source.location();
-    let location = location.as_str();
+    let offset = source.map(|(offset, _)| offset).unwrap_or_default();
+    let local = |span: Span| span.start.saturating_sub(offset)..span.end.saturating_sub(offset);
+    let err_span = local(err.span());
+
+    let message = error_message(&err);
+    let labels = match err.reason() {
+        SimpleReason::Unexpected => {
+            let found = match err.found() {
+                Some(found) => format!("token {found}"),
+                None => "end of input".to_owned(),
+            };
+            vec![(err_span, format!("Unexpected {found}"), Color::Red)]
+        }
+        SimpleReason::Unclosed { span, .. } => {
+            let found = err
+                .found()
+                .cloned()
+                .unwrap_or_else(|| "end of input".to_owned());
+            vec![
+                (err_span, format!("Must be closed before this {found}"), Color::Red),
+                (local(span.clone()), message.clone(), Color::Cyan),
+            ]
+        }
+        SimpleReason::Custom(msg) => vec![(err_span, msg.clone(), Color::Red)],
+    };
+
+    let diagnostic = Diagnostic {
+        code,
+        message,
+        labels,
+    };
+    emit(source.map(|(_, source)| source), diagnostic, format);
+}
+
+/// Message of the error as shown in reports
+pub fn error_message(err: &Simple<String>) -> String {
+    match err.reason() {
+        SimpleReason::Unexpected => {
+            let mut message = match err.found() {
+                Some(_) => "Unexpected token found".to_owned(),
+                None => "Unexpected end of input".to_owned(),
+            };
+            if err.expected().len() != 0 {
+                let expected = err
+                    .expected()
+                    .map(|e| match e {
+                        Some(e) => e.to_owned(),
+                        None => "end of input".to_owned(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                message += &format!(". expected {expected}");
+            }
+            message
+        }
+        SimpleReason::Unclosed { delimiter, .. } => format!("Unclosed delimiter {delimiter}"),
+        SimpleReason::Custom(msg) => msg.clone(),
+    }
+}
+
+pub fn fault(source: &SvSource, fault: &Fault, format: MessageFormat) {
+    fault_at(Some((0, source)), fault, format)
+}
 
-    let report = Report::build(ReportKind::Error, location, err.span().start);
-    let report = match err.reason() {
-        SimpleReason::Unexpected => report
-            .with_message(format!(
-                "{}{}",
-                if err.found().is_some() {
-                    "Unexpected token found"
-                } else {
-                    "Unexpected end of input"
-                },
-                if err.expected().len() != 0 {
-                    let expected = err
-                        .expected()
-                        .map(|e| match e {
-                            Some(e) => e.to_owned(),
-                            None => "end of input".to_owned(),
-                        })
-                        .collect::<Vec<_>>()
-                        .join(", ");
-
-                    format!(". expected {}", expected)
-                } else {
-                    "".to_owned()
+/// Reports fault of sources compiled together
+pub fn fault_in(sources: &SourceMap, fault: &Fault, format: MessageFormat) {
+    let source = fault
+        .span
+        .as_ref()
+        .and_then(|span| sources.locate(span.start));
+    fault_at(source, fault, format)
+}
+
+/// Reports fault of code without source, e.g. precompiled bytecode
+pub fn fault_unlocated(fault: &Fault, format: MessageFormat) {
+    fault_at(None, fault, format)
+}
+
+/// Reports failure not tied to any code, human output is left to `main`
+pub fn failure(why: &anyhow::Error, format: MessageFormat) {
+    let diagnostic = Diagnostic {
+        code: COMMAND_ERROR,
+        message: format!("{why:#}"),
+        labels: Vec::new(),
+    };
+    emit(None, diagnostic, format);
+}
+
+/// Reports source which differs from its formatted version
+pub fn unformatted(source: &SvSource, format: MessageFormat) {
+    let message = format!("{} is not formatted", source.location());
+    if format == MessageFormat::Human {
+        return eprintln!("{message}");
+    }
+    let diagnostic = Diagnostic {
+        code: UNFORMATTED,
+        message,
+        labels: Vec::new(),
+    };
+    emit(Some(source), diagnostic, format);
+}
+
+/// Fault spans are shifted by offset of the source, callers from other sources are skipped
+fn fault_at(source: Option<(usize, &SvSource)>, fault: &Fault, format: MessageFormat) {
+    let message = format!("Runtime error: {}", fault.error);
+    let (source, offset, span) = match (source, &fault.span) {
+        (Some((offset, source)), Some(span)) => (source, offset, span.clone()),
+        _ if format == MessageFormat::Human => return eprintln!("{fault}"),
+        _ => {
+            let diagnostic = Diagnostic {
+                code: RUNTIME_ERROR,
+                message,
+                labels: Vec::new(),
+            };
+            return emit(None, diagnostic, format);
+        }
+    };
+    let end = offset + source.inner().chars().count();
+    let local = |span: &Span| span.start - offset..span.end - offset;
+    let span = local(&span);
+
+    let mut labels = vec![(span.clone(), message.clone(), Color::Red)];
+    for frame in fault.trace.iter().skip(1) {
+        match &frame.span {
+            Some(caller) if (offset..end).contains(&caller.start) => {
+                let caller = local(caller);
+                if caller != span && !labels.iter().any(|(span, _, _)| *span == caller) {
+                    labels.push((caller, "Called from here".to_owned(), Color::Cyan))
                 }
-            ))
-            .with_label(Label::new((location, err.span())).with_message(format!(
-                "Unexpected {}",
-                err
-                    .found()
-                    .map(|v| format!("token {}", v.fg(Color::Red)))
-                    .unwrap_or("end of input".fg(Color::Red).to_string())
-            ))),
-        SimpleReason::Unclosed { span, delimiter } => report
-            .with_message(format!("Unclosed delimiter {}", delimiter.fg(Color::Cyan)))
-            .with_label(
-                Label::new((location, span.clone()))
-                    .with_message(format!("Unclosed delimiter {}", delimiter.fg(Color::Cyan)))
-                    .with_color(Color::Cyan),
-            )
-            .with_label(
-                Label::new((location, err.span()))
-                    .with_message(format!(
-                        "Must be closed before this {}",
-                        err.found()
-                            .unwrap_or(&"end of input".to_string())
-                            .fg(Color::Red)
-                    ))
-                    .with_color(Color::Red),
-            ),
-        SimpleReason::Custom(msg) => report.with_message(msg).with_label(
-            Label::new((location, err.span()))
-                .with_message(format!("{}", msg.fg(Color::Red)))
-                .with_color(Color::Red),
-        ),
+            }
+            _ => {}
+        }
+    }
+
+    let diagnostic = Diagnostic {
+        code: RUNTIME_ERROR,
+        message,
+        labels,
     };
+    emit(Some(source), diagnostic, format);
+}
+
+fn emit(source: Option<&SvSource>, diagnostic: Diagnostic, format: MessageFormat) {
+    match (format, source) {
+        (MessageFormat::Json, _) => println!("{}", json::diagnostic(source, diagnostic)),
+        (MessageFormat::Human, Some(source)) => render(source, diagnostic),
+        (MessageFormat::Human, None) => eprintln!("Error: {}", diagnostic.message),
+    }
+}
+
+fn render(source: &SvSource, diagnostic: Diagnostic) {
+    let location = source.location();
+    let location = location.as_str();
+    let start = diagnostic
+        .labels
+        .first()
+        .map(|(span, _, _)| span.start)
+        .unwrap_or_default();
+
+    let mut report = Report::build(ReportKind::Error, location, start)
+        .with_code(diagnostic.code)
+        .with_message(diagnostic.message);
+    for (span, message, color) in diagnostic.labels {
+        report = report.with_label(
+            Label::new((location, span))
+                .with_message(message.fg(color))
+                .with_color(color),
+        );
+    }
 
     let config = Config::default()
         .with_compact(true);
@@ -77,3 +252,84 @@ where
         .eprint((location, Source::from(source.inner())))
         .unwrap();
 }
+
+mod json {
+    use super::{Diagnostic, Serialize, Span, SvSource};
+
+    #[derive(Serialize)]
+    struct JsonDiagnostic {
+        file: Option<String>,
+        severity: &'static str,
+        code: &'static str,
+        message: String,
+        span: Option<JsonSpan>,
+        labels: Vec<JsonLabel>,
+    }
+
+    #[derive(Serialize)]
+    struct JsonLabel {
+        message: String,
+        span: JsonSpan,
+    }
+
+    /// Lines and columns start at 1, columns count characters
+    #[derive(Serialize, Clone)]
+    struct JsonSpan {
+        byte_start: usize,
+        byte_end: usize,
+        line_start: usize,
+        column_start: usize,
+        line_end: usize,
+        column_end: usize,
+    }
+
+    pub(super) fn diagnostic(source: Option<&SvSource>, diagnostic: Diagnostic) -> String {
+        let text = source.map(|source| source.inner()).unwrap_or_default();
+        let labels = diagnostic
+            .labels
+            .into_iter()
+            .map(|(span, message, _)| JsonLabel {
+                message,
+                span: json_span(text, span),
+            })
+            .collect::<Vec<_>>();
+
+        let diagnostic = JsonDiagnostic {
+            file: source.map(|source| source.location()),
+            severity: "error",
+            code: diagnostic.code,
+            message: diagnostic.message,
+            span: labels.first().map(|label| label.span.clone()),
+            labels,
+        };
+        // Diagnostics contain only strings and numbers
+        serde_json::to_string(&diagnostic).unwrap()
+    }
+
+    /// Converts character span to byte offsets and positions
+    fn json_span(text: &str, span: Span) -> JsonSpan {
+        let position = |offset: usize| {
+            let before = text.chars().take(offset);
+            let (mut line, mut column, mut bytes) = (1, 1, 0);
+            for c in before {
+                bytes += c.len_utf8();
+                match c {
+                    '\n' => (line, column) = (line + 1, 1),
+                    _ => column += 1,
+                }
+            }
+            (bytes, line, column)
+        };
+        let (byte_start, line_start, column_start) = position(span.start);
+        let (byte_end, line_end, column_end) = position(span.end);
+
+        JsonSpan {
+            byte_start,
+            byte_end,
+            line_start,
+            column_start,
+            line_end,
+            column_end,
+        }
+    }
+}
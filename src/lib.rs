@@ -0,0 +1,180 @@
+//! Embedding API for running Ash from Rust applications
+//!
+//! ```
+//! # fn main() -> ash::Result<()> {
+//! let engine = ash::Engine::new();
+//! let source = ash::Source::from_string("fun sum(a: i32, b: i32) > i32 => a + b;");
+//! let program = engine.compile(&source)?;
+//! let mut instance = engine.instantiate(&program)?;
+//! let sum = instance.call("sum", vec![1.into(), 2.into()])?;
+//! assert_eq!(i32::try_from(sum), Ok(3));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+
+use ash_bytecode::prelude::Chunk;
+use ash_core::prelude as core;
+use ash_vm::prelude::*;
+use thiserror::Error;
+
+pub use ash_bytecode::prelude::{NativeTy, Value, ValueError};
+pub use ash_core::prelude::{Simple, Source};
+pub use ash_vm::prelude::{Fault, GCConfig, GCStats, Io, SharedBuffer, VMError, VMResult};
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("{}", CompileErrors(.0))]
+    Compile(Vec<Simple<String>>),
+    #[error(transparent)]
+    Runtime(#[from] Fault),
+    #[error("`{0}` is not declared in the program")]
+    UndefinedSymbol(String),
+    #[error(transparent)]
+    VM(#[from] VMError),
+}
+
+struct CompileErrors<'e>(&'e [Simple<String>]);
+
+impl fmt::Display for CompileErrors<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors = self
+            .0
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        f.write_str(&errors.join("\n"))
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Compiles programs and creates instances sharing the same natives
+pub struct Engine {
+    natives: Natives,
+    gc_config: GCConfig,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Engine {
+    /// Engine with the standard builtins
+    pub fn new() -> Self {
+        Self {
+            natives: Natives::std(),
+            gc_config: GCConfig::default(),
+        }
+    }
+
+    pub fn with_gc_config(mut self, gc_config: GCConfig) -> Self {
+        self.gc_config = gc_config;
+        self
+    }
+
+    /// Registers function which Ash can declare with `@[builtin]`
+    pub fn register_native<F>(&mut self, name: &str, params: &[NativeTy], ret: NativeTy, fun: F)
+    where
+        F: Fn(&mut Io, &[Value]) -> VMResult<Value> + 'static,
+    {
+        self.natives.register(name, params, ret, fun);
+    }
+
+    pub fn compile(&self, source: &Source) -> Result<Program> {
+        let chunk = core::build_library(source, &self.natives.signatures()).map_err(Error::Compile)?;
+        Ok(Program { chunk })
+    }
+
+    /// Creates VM for the program and defines its globals
+    pub fn instantiate<'p>(&self, program: &'p Program) -> Result<Instance<'p>> {
+        let mut vm = VM::with_config(&program.chunk, self.natives.clone(), self.gc_config);
+        vm.init()?;
+        Ok(Instance { vm, program })
+    }
+}
+
+pub struct Program {
+    chunk: Chunk,
+}
+
+impl Program {
+    pub fn chunk(&self) -> &Chunk {
+        &self.chunk
+    }
+}
+
+/// Running program, globals persist between calls
+pub struct Instance<'p> {
+    vm: VM<'p>,
+    program: &'p Program,
+}
+
+impl<'p> Instance<'p> {
+    /// Calls function `name` declared at the root of the program
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value> {
+        let global = self.global_name(name)?;
+        Ok(self.vm.call_global(global, args)?)
+    }
+
+    pub fn global(&self, name: &str) -> Result<Value> {
+        let global = self.global_name(name)?;
+        Ok(self.vm.global(global)?)
+    }
+
+    /// Replaces value of a global declared with `var`, the value has to be of its declared type
+    ///
+    /// ```
+    /// # fn main() -> ash::Result<()> {
+    /// let engine = ash::Engine::new();
+    /// let source = ash::Source::from_string("val limit = 10;\nvar count = 0;");
+    /// let program = engine.compile(&source)?;
+    /// let mut instance = engine.instantiate(&program)?;
+    /// instance.set_global("count", 5.into())?;
+    /// assert_eq!(i32::try_from(instance.global("count")?), Ok(5));
+    /// assert!(instance.set_global("count", "five".into()).is_err());
+    /// assert!(instance.set_global("limit", 5.into()).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_global(&mut self, name: &str, value: Value) -> Result<()> {
+        let global = self.global_name(name)?;
+        Ok(self.vm.set_global(global, value)?)
+    }
+
+    /// Redirects output of printing builtins
+    ///
+    /// ```
+    /// # fn main() -> ash::Result<()> {
+    /// let engine = ash::Engine::new();
+    /// let source = ash::Source::from_string(
+    ///     "@[builtin]\nfun println(msg: str)\n\nfun greet() {\n    println(\"hi\");\n}",
+    /// );
+    /// let program = engine.compile(&source)?;
+    /// let mut instance = engine.instantiate(&program)?;
+    /// let stdout = ash::SharedBuffer::new();
+    /// instance.set_io(ash::Io::new(stdout.clone(), std::io::sink()));
+    /// instance.call("greet", vec![])?;
+    /// assert_eq!(stdout.contents(), "hi\n");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_io(&mut self, io: Io) {
+        self.vm.set_io(io);
+    }
+
+    pub fn vm(&mut self) -> &mut VM<'p> {
+        &mut self.vm
+    }
+
+    fn global_name(&self, name: &str) -> Result<&'p str> {
+        self.program
+            .chunk
+            .symbol(name)
+            .map(|symbol| symbol.global.as_str())
+            .ok_or_else(|| Error::UndefinedSymbol(name.to_owned()))
+    }
+}
@@ -0,0 +1,462 @@
+use crate::cli::LspOptions;
+use crate::failure::report;
+use crate::project::{canonical, Project};
+use anyhow::{anyhow, Result};
+use ash_bytecode::prelude::NativeSignature;
+use ash_core::prelude::{self as ash, error_code, Analysis, Span};
+use ash_vm::prelude::Natives;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+        PublishDiagnostics,
+    },
+    request::{DocumentSymbolRequest, GotoDefinition, HoverRequest, References, Request as _},
+    Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse,
+    GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, Location, MarkupContent, MarkupKind, NumberOrString, OneOf, Position,
+    PublishDiagnosticsParams, Range, ReferenceParams, ServerCapabilities, SymbolKind,
+    TextDocumentPositionParams, TextDocumentSyncCapability, TextDocumentSyncKind, Uri,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Serves editors over stdio until they ask to exit
+pub fn run(_options: LspOptions) -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    connection.initialize(serde_json::to_value(capabilities())?)?;
+    Server::new(&connection).main_loop()?;
+    // Writer thread stops once the connection is gone
+    drop(connection);
+    io_threads.join()?;
+
+    Ok(())
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    }
+}
+
+struct Server<'a> {
+    connection: &'a Connection,
+    natives: Vec<NativeSignature>,
+    // Open documents by canonical path, their text is newer than the file on disk
+    documents: HashMap<PathBuf, Document>,
+    // Files which have diagnostics shown in the editor
+    published: HashSet<PathBuf>,
+}
+
+/// Document opened by the client
+struct Document {
+    // URI the client knows the document by, it can be a link to the file
+    uri: Uri,
+    text: String,
+}
+
+/// Sources analyzed together with a document, the project it belongs to or the document alone
+struct Workspace {
+    sources: ash::SourceMap,
+    // Path of each source in the order they were added
+    files: Vec<PathBuf>,
+    analysis: Analysis,
+}
+
+impl<'a> Server<'a> {
+    fn new(connection: &'a Connection) -> Self {
+        Self {
+            connection,
+            natives: Natives::std().signatures(),
+            documents: HashMap::new(),
+            published: HashSet::new(),
+        }
+    }
+
+    fn main_loop(&mut self) -> Result<()> {
+        for message in &self.connection.receiver {
+            match message {
+                Message::Request(request) => {
+                    if self.connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    self.request(request)?;
+                }
+                Message::Notification(notification) => self.notification(notification)?,
+                Message::Response(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn request(&mut self, request: Request) -> Result<()> {
+        let result = match request.method.as_str() {
+            HoverRequest::METHOD => self.respond(&request, Self::hover),
+            GotoDefinition::METHOD => self.respond(&request, Self::definition),
+            References::METHOD => self.respond(&request, Self::references),
+            DocumentSymbolRequest::METHOD => self.respond(&request, Self::document_symbols),
+            method => Err((
+                ErrorCode::MethodNotFound,
+                format!("Unsupported request {method}"),
+            )),
+        };
+
+        let response = match result {
+            Ok(value) => Response::new_ok(request.id, value),
+            Err((code, message)) => Response::new_err(request.id, code as i32, message),
+        };
+        self.connection.sender.send(response.into())?;
+
+        Ok(())
+    }
+
+    /// Decodes params of the request and encodes the result of `handler`
+    fn respond<P, R>(
+        &self,
+        request: &Request,
+        handler: fn(&Self, P) -> Result<R>,
+    ) -> Result<serde_json::Value, (ErrorCode, String)>
+    where
+        P: DeserializeOwned,
+        R: Serialize,
+    {
+        let params = serde_json::from_value(request.params.clone())
+            .map_err(|err| (ErrorCode::InvalidParams, err.to_string()))?;
+        handler(self, params)
+            .and_then(|result| Ok(serde_json::to_value(result)?))
+            .map_err(|err| (ErrorCode::InternalError, format!("{err:#}")))
+    }
+
+    fn notification(&mut self, notification: Notification) -> Result<()> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: DidOpenTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                let path = canonical(&to_path(&params.text_document.uri)?);
+                let document = Document {
+                    uri: params.text_document.uri,
+                    text: params.text_document.text,
+                };
+                self.documents.insert(path.clone(), document);
+                self.publish(&path)
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: DidChangeTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                let path = canonical(&to_path(&params.text_document.uri)?);
+                // Documents are synced in full, the last change is the whole text
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    let document = Document {
+                        uri: params.text_document.uri,
+                        text: change.text,
+                    };
+                    self.documents.insert(path.clone(), document);
+                }
+                self.publish(&path)
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: DidCloseTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                let path = canonical(&to_path(&params.text_document.uri)?);
+                self.documents.remove(&path);
+                // Errors are shown only for open files, the one on disk may be outdated
+                self.published.remove(&path);
+                self.send_diagnostics(params.text_document.uri, Vec::new())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Sends errors of every file in the workspace of the document, fixed files are cleared
+    fn publish(&mut self, path: &Path) -> Result<()> {
+        let workspace = match self.workspace(path) {
+            Ok(workspace) => workspace,
+            // Broken manifests and deleted files are not errors of the code
+            Err(_) => return Ok(()),
+        };
+
+        let mut diagnostics = workspace
+            .files
+            .iter()
+            .map(|file| (file.clone(), Vec::new()))
+            .collect::<HashMap<_, _>>();
+        for err in workspace.analysis.errors() {
+            let Some((file, range)) = workspace.locate(err.span()) else {
+                continue;
+            };
+            diagnostics.entry(file).or_default().push(Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::String(error_code(err).to_owned())),
+                source: Some("ash".to_owned()),
+                message: report::error_message(err),
+                ..Default::default()
+            });
+        }
+
+        for (file, diagnostics) in diagnostics {
+            if diagnostics.is_empty() && !self.published.remove(&file) {
+                continue;
+            }
+            if !diagnostics.is_empty() {
+                self.published.insert(file.clone());
+            }
+            let uri = match self.documents.get(&file) {
+                Some(document) => document.uri.clone(),
+                None => to_uri(&file)?,
+            };
+            self.send_diagnostics(uri, diagnostics)?;
+        }
+
+        Ok(())
+    }
+
+    /// Replaces diagnostics shown for the document, empty list clears them
+    fn send_diagnostics(&self, uri: Uri, diagnostics: Vec<Diagnostic>) -> Result<()> {
+        let params = PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version: None,
+        };
+        let notification = Notification::new(PublishDiagnostics::METHOD.to_owned(), params);
+        self.connection.sender.send(notification.into())?;
+
+        Ok(())
+    }
+
+    fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let (workspace, offset) = self.position(&params.text_document_position_params)?;
+        let Some(symbol) = workspace.analysis.symbol_at(offset) else {
+            return Ok(None);
+        };
+
+        let signature = match &symbol.ty {
+            Some(ty) => format!("{}: {ty}", symbol.name),
+            None => symbol.name.clone(),
+        };
+        let range = workspace
+            .analysis
+            .references(symbol)
+            .into_iter()
+            .find(|span| span.start <= offset && offset <= span.end)
+            .and_then(|span| workspace.locate(span))
+            .map(|(_, range)| range);
+
+        Ok(Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: match &symbol.doc {
+                    Some(doc) => format!("```ash\n{signature}\n```\n\n{doc}"),
+                    None => format!("```ash\n{signature}\n```"),
+                },
+            }),
+            range,
+        }))
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Result<Option<GotoDefinitionResponse>> {
+        let (workspace, offset) = self.position(&params.text_document_position_params)?;
+        let location = workspace
+            .analysis
+            .symbol_at(offset)
+            .map(|symbol| workspace.location(symbol.span.clone()))
+            .transpose()?
+            .flatten();
+
+        Ok(location.map(GotoDefinitionResponse::Scalar))
+    }
+
+    fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+        let (workspace, offset) = self.position(&params.text_document_position)?;
+        let Some(symbol) = workspace.analysis.symbol_at(offset) else {
+            return Ok(None);
+        };
+
+        let mut locations = Vec::new();
+        for span in workspace.analysis.references(symbol) {
+            if span == symbol.span && !params.context.include_declaration {
+                continue;
+            }
+            locations.extend(workspace.location(span)?);
+        }
+
+        Ok(Some(locations))
+    }
+
+    // Deprecated fields of the symbol still have to be set
+    #[allow(deprecated)]
+    fn document_symbols(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> Result<Option<DocumentSymbolResponse>> {
+        let path = to_path(&params.text_document.uri)?;
+        let workspace = self.workspace(&path)?;
+
+        let mut symbols = Vec::new();
+        for function in workspace.analysis.functions() {
+            let Some((file, range)) = workspace.locate(function.span.clone()) else {
+                continue;
+            };
+            if file != canonical(&path) {
+                continue;
+            }
+            symbols.push(DocumentSymbol {
+                name: function.name.clone(),
+                detail: function.ty.clone(),
+                kind: SymbolKind::FUNCTION,
+                tags: None,
+                deprecated: None,
+                range,
+                selection_range: range,
+                children: None,
+            });
+        }
+
+        Ok(Some(DocumentSymbolResponse::Nested(symbols)))
+    }
+
+    /// Workspace of the document and the offset of the position in its sources
+    fn position(&self, params: &TextDocumentPositionParams) -> Result<(Workspace, usize)> {
+        let path = canonical(&to_path(&params.text_document.uri)?);
+        let workspace = self.workspace(&path)?;
+        let offset = workspace
+            .files
+            .iter()
+            .zip(workspace.sources.iter())
+            .find(|(file, _)| **file == path)
+            .map(|(_, (offset, _, source))| offset + to_offset(source.inner(), params.position))
+            .ok_or_else(|| anyhow!("{} is not a part of the workspace", path.display()))?;
+
+        Ok((workspace, offset))
+    }
+
+    /// Analyzes the project containing the file, files which are not in a project are analyzed alone
+    fn workspace(&self, path: &Path) -> Result<Workspace> {
+        let path = canonical(path);
+        let read = |file: &Path| match self.documents.get(&canonical(file)) {
+            Some(document) => Ok(document.text.clone()),
+            None => fs::read_to_string(file),
+        };
+
+        let (sources, files) = match Project::containing(&path)? {
+            Some(project) => {
+                let files = project
+                    .files()?
+                    .iter()
+                    .map(|file| canonical(file))
+                    .collect();
+                (project.sources_with(read)?, files)
+            }
+            None => {
+                let module = path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                let source = ash::Source::from_string(read(&path)?)
+                    .with_location(path.display().to_string());
+                let mut sources = ash::SourceMap::new();
+                sources.add(module, source);
+                (sources, vec![path])
+            }
+        };
+
+        let analysis = Analysis::run(&sources, &self.natives);
+        Ok(Workspace {
+            sources,
+            files,
+            analysis,
+        })
+    }
+}
+
+impl Workspace {
+    /// File containing the span and the range of the span in it
+    fn locate(&self, span: Span) -> Option<(PathBuf, Range)> {
+        let (file, (offset, _, source)) = self
+            .files
+            .iter()
+            .zip(self.sources.iter())
+            .filter(|(_, (offset, _, _))| *offset <= span.start)
+            .last()?;
+        let text = source.inner();
+        let range = Range::new(
+            to_position(text, span.start - offset),
+            to_position(text, span.end.saturating_sub(offset)),
+        );
+
+        Some((file.clone(), range))
+    }
+
+    fn location(&self, span: Span) -> Result<Option<Location>> {
+        self.locate(span)
+            .map(|(file, range)| Ok(Location::new(to_uri(&file)?, range)))
+            .transpose()
+    }
+}
+
+fn to_path(uri: &Uri) -> Result<PathBuf> {
+    match uri.scheme() {
+        Some(scheme) if scheme.as_str() == "file" => {
+            let path = uri.path().as_estr().decode().into_string_lossy();
+            Ok(PathBuf::from(path.as_ref()))
+        }
+        _ => Err(anyhow!(
+            "Only file URIs are supported, got {}",
+            uri.as_str()
+        )),
+    }
+}
+
+fn to_uri(path: &Path) -> Result<Uri> {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'.' | b'_' | b'~' => {
+                uri.push(byte as char)
+            }
+            _ => uri += &format!("%{byte:02X}"),
+        }
+    }
+
+    Uri::from_str(&uri).map_err(|err| anyhow!("Invalid URI {uri}: {err}"))
+}
+
+/// Sources count characters, editors count lines and UTF-16 code units
+fn to_position(text: &str, offset: usize) -> Position {
+    let (mut line, mut character) = (0, 0);
+    for c in text.chars().take(offset) {
+        match c {
+            '\n' => (line, character) = (line + 1, 0),
+            _ => character += c.len_utf16() as u32,
+        }
+    }
+
+    Position::new(line, character)
+}
+
+fn to_offset(text: &str, position: Position) -> usize {
+    let (mut line, mut character) = (0, 0);
+    for (offset, c) in text.chars().enumerate() {
+        if line == position.line && (character >= position.character || c == '\n') {
+            return offset;
+        }
+        match c {
+            '\n' => (line, character) = (line + 1, 0),
+            _ => character += c.len_utf16() as u32,
+        }
+    }
+
+    text.chars().count()
+}
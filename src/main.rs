@@ -1,9 +1,22 @@
+use std::process::ExitCode;
+
+use failure::Reported;
+
 mod cli;
 mod code;
 mod failure;
+mod lsp;
+mod project;
+mod repl;
 
-fn main() {
-    if let Err(why) = cli::init() {
-        eprintln!("Error occurred: {why}")
+fn main() -> ExitCode {
+    match cli::init() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(why) => {
+            if !why.is::<Reported>() {
+                eprintln!("Error occurred: {why:#}");
+            }
+            ExitCode::FAILURE
+        }
     }
 }
@@ -0,0 +1,186 @@
+use anyhow::{bail, Context, Result};
+use ash_core::prelude as ash;
+use serde::Deserialize;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+pub const MANIFEST: &str = "ash.toml";
+
+const SOURCE_EXTENSION: &str = "ash";
+
+/// Contents of `ash.toml` at the root of a project
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub package: Package,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Package {
+    pub name: String,
+    pub version: String,
+    /// File defining `main`, relative to the project root
+    #[serde(default = "default_entry")]
+    pub entry: PathBuf,
+    /// Directories searched for source files, relative to the project root
+    #[serde(default = "default_sources")]
+    pub sources: Vec<PathBuf>,
+}
+
+fn default_entry() -> PathBuf {
+    PathBuf::from("src/main.ash")
+}
+
+fn default_sources() -> Vec<PathBuf> {
+    vec![PathBuf::from("src")]
+}
+
+pub struct Project {
+    pub root: PathBuf,
+    pub manifest: Manifest,
+}
+
+impl Project {
+    pub fn load(root: &Path) -> Result<Self> {
+        let path = root.join(MANIFEST);
+        if !path.is_file() {
+            bail!("No {MANIFEST} found in {}", root.display());
+        }
+        let manifest = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let manifest = toml::from_str(&manifest)
+            .with_context(|| format!("Invalid manifest {}", path.display()))?;
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            manifest,
+        })
+    }
+
+    /// Root of the project containing `path`, the closest directory with a manifest
+    pub fn find_root(path: &Path) -> Option<PathBuf> {
+        path.ancestors()
+            .skip(1)
+            .find(|dir| dir.join(MANIFEST).is_file())
+            .map(Path::to_path_buf)
+    }
+
+    /// Project having `file` among its sources, the one in the closest directory with a manifest
+    pub fn containing(file: &Path) -> Result<Option<Self>> {
+        let file = canonical(file);
+        let project = Self::find_root(&file)
+            .map(|root| Self::load(&root))
+            .transpose()?;
+
+        Ok(project.filter(|project| {
+            project
+                .files()
+                .is_ok_and(|files| files.iter().any(|source| canonical(source) == file))
+        }))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.manifest.package.name
+    }
+
+    pub fn version(&self) -> &str {
+        &self.manifest.package.version
+    }
+
+    /// Source files of the project, entry comes first and the rest is sorted by path
+    pub fn files(&self) -> Result<Vec<PathBuf>> {
+        let package = &self.manifest.package;
+        let entry = self.root.join(&package.entry);
+        if !entry.is_file() {
+            bail!("Entry file {} does not exist", entry.display());
+        }
+
+        let mut files = Vec::new();
+        for dir in package.sources.iter() {
+            let dir = self.root.join(dir);
+            if !dir.is_dir() {
+                bail!("Source directory {} does not exist", dir.display());
+            }
+            find_sources(&dir, &mut files)?;
+        }
+        files.sort();
+
+        // The same file can be found through different paths
+        let entry_id = fs::canonicalize(&entry)?;
+        let mut seen = vec![entry_id];
+        let mut sources = vec![entry];
+        for file in files {
+            let id = fs::canonicalize(&file)?;
+            if !seen.contains(&id) {
+                seen.push(id);
+                sources.push(file);
+            }
+        }
+
+        Ok(sources)
+    }
+
+    pub fn sources(&self) -> Result<ash::SourceMap> {
+        self.sources_with(|file| fs::read_to_string(file))
+    }
+
+    /// Same as [`Project::sources`], but contents of the files are provided by `read`
+    pub fn sources_with<F>(&self, read: F) -> Result<ash::SourceMap>
+    where
+        F: Fn(&Path) -> io::Result<String>,
+    {
+        let mut sources = ash::SourceMap::new();
+        let mut modules = Vec::new();
+        for file in self.files()? {
+            let module = self.module_name(&file);
+            if modules.contains(&module) {
+                bail!("Module `{module}` is defined more than once, found {}", file.display());
+            }
+            let code = read(&file).with_context(|| format!("Failed to read {}", file.display()))?;
+            let source = ash::Source::from_string(code).with_location(file.display().to_string());
+            sources.add(module.clone(), source);
+            modules.push(module);
+        }
+
+        Ok(sources)
+    }
+
+    /// Path of the file relative to its source directory joined by dots, e.g. `util.math`.
+    /// Files outside of source directories are named by their file name
+    pub fn module_name(&self, file: &Path) -> String {
+        let path = self
+            .manifest
+            .package
+            .sources
+            .iter()
+            .find_map(|dir| file.strip_prefix(self.root.join(dir)).ok())
+            .unwrap_or_else(|| Path::new(file.file_name().unwrap_or_default()));
+
+        path.with_extension("")
+            .iter()
+            .map(|part| part.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+}
+
+/// Files are compared by their canonical paths, files which are not saved yet keep their path
+pub fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn find_sources(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_sources(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == SOURCE_EXTENSION) {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
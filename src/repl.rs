@@ -0,0 +1,120 @@
+use crate::cli::ReplOptions;
+use crate::failure::report::{self, MessageFormat};
+use anyhow::Result;
+use ash_bytecode::prelude::{Chunk, Function, Value};
+use ash_core::prelude as ash;
+use ash_vm::prelude::*;
+use std::{
+    io::{self, BufRead, IsTerminal, Write},
+    mem,
+    rc::Rc,
+};
+
+const LOCATION: &str = "<repl>";
+
+const HELP: &str = "\
+:type <expr>  shows type of the expression without running it
+:dis <code>   shows bytecode of the code without running it
+:help         shows this message
+:quit         exits the repl";
+
+/// Reads inputs line by line, lines are joined while delimiters are unclosed
+pub fn run(options: ReplOptions) -> Result<()> {
+    let format = options.message_format;
+    let natives = Natives::std();
+    let mut session = ash::Session::new(natives.signatures());
+    // Every input runs as a separate function, the script itself is empty
+    let script = Chunk::default();
+    let mut vm = VM::with_config(&script, natives, GCConfig::default());
+
+    let stdin = io::stdin();
+    let interactive = stdin.is_terminal();
+    let mut lines = stdin.lock().lines();
+    let mut input = String::new();
+    loop {
+        if interactive {
+            print!("{}", if input.is_empty() { "> " } else { ". " });
+            io::stdout().flush()?;
+        }
+
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+        input.push_str(&line);
+        input.push('\n');
+        if session.is_incomplete(&input) {
+            continue;
+        }
+
+        let input = mem::take(&mut input);
+        let input = input.trim();
+        match input.split_once(char::is_whitespace).unwrap_or((input, "")) {
+            ("", _) => {}
+            (":quit" | ":q", _) => break,
+            (":help", _) => println!("{HELP}"),
+            (":type", code) => type_of(&mut session, code, format),
+            (":dis", code) => disassemble(&mut session, code, format)?,
+            (command, _) if command.starts_with(':') => {
+                eprintln!("Unknown command `{command}`, see :help")
+            }
+            _ => eval(&mut session, &mut vm, input, format),
+        }
+    }
+
+    Ok(())
+}
+
+fn eval(session: &mut ash::Session, vm: &mut VM, code: &str, format: MessageFormat) {
+    let src = source(code);
+    let (chunk, ty) = match session.compile(&src) {
+        Ok(compiled) => compiled,
+        Err(errs) => return errs.into_iter().for_each(|err| report::error(&src, err, format)),
+    };
+
+    let function = Function {
+        name: "repl".to_owned(),
+        arity: 0,
+        chunk,
+    };
+    let value = match vm.call_value(Value::Function(Rc::new(function)), vec![]) {
+        Ok(value) => value,
+        Err(fault) => return report::fault(&src, &fault, format),
+    };
+    session.commit();
+
+    match ty {
+        Some(ty) if ty != "void" => println!("{}: {ty}", display(vm, &value)),
+        _ => {}
+    }
+}
+
+fn type_of(session: &mut ash::Session, code: &str, format: MessageFormat) {
+    let src = source(code);
+    match session.type_of(&src) {
+        Ok(Some(ty)) => println!("{ty}"),
+        Ok(None) => eprintln!("Not an expression"),
+        Err(errs) => errs.into_iter().for_each(|err| report::error(&src, err, format)),
+    }
+}
+
+fn disassemble(session: &mut ash::Session, code: &str, format: MessageFormat) -> Result<()> {
+    let src = source(code);
+    match session.compile(&src) {
+        Ok((chunk, _)) => chunk.disassemble("repl", &mut io::stdout())?,
+        Err(errs) => errs.into_iter().for_each(|err| report::error(&src, err, format)),
+    }
+
+    Ok(())
+}
+
+fn source(code: &str) -> ash::Source {
+    ash::Source::from_string(code).with_location(LOCATION)
+}
+
+fn display(vm: &VM, value: &Value) -> String {
+    match value {
+        Value::String(v) => format!("{v:?}"),
+        _ => vm.value_to_string(value),
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    fs,
+    path::PathBuf,
+    process::{self, Command, Output},
+};
+
+/// Writes `code` to a file of its own for the test named `test`
+fn source(test: &str, code: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ash-cli-{}-{test}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("main.ash");
+    fs::write(&path, code).unwrap();
+    path
+}
+
+/// Project with an entry module importing `util.math`
+const PROJECT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/project");
+
+fn ash(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_ash"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn run_and_build_require_main() {
+    let path = source("require_main", "fun sum(a: i32, b: i32) > i32 => a + b;\n");
+    let path = path.to_str().unwrap();
+
+    for args in [vec!["run", "--path", path], vec!["build", path]] {
+        let output = ash(&[&args[..], &["--message-format", "json"]].concat());
+        assert!(!output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains(r#""code":"E0400""#), "{stdout}");
+        assert!(stdout.contains("Function `main` is not defined"), "{stdout}");
+    }
+}
+
+#[test]
+fn run_calls_main() {
+    let path = source(
+        "run_main",
+        "@[builtin]\nfun println(s: str)\n\nfun main() {\n    println(\"hi\");\n}\n",
+    );
+
+    let output = ash(&["run", "--path", path.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hi\n");
+}
+
+#[test]
+fn eprintln_writes_to_stderr() {
+    let path = source(
+        "eprintln",
+        "@[builtin]\nfun println(s: str)\n@[builtin]\nfun eprintln(s: str)\n\nfun main() {\n    println(\"out\");\n    eprintln(\"err\");\n}\n",
+    );
+
+    let output = ash(&["run", "--path", path.to_str().unwrap()]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "out\n");
+    // Traces of the `debug_info` feature are written to stderr as well
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.lines().any(|line| line == "err"), "{stderr}");
+}
+
+#[test]
+fn fmt_check_reports_unformatted_files() {
+    let path = source("fmt_check", "fun main(){\nval a=1+2;\n}\n");
+    let path = path.to_str().unwrap();
+
+    let output = ash(&["fmt", "--check", path]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(&format!("{path} is not formatted")), "{stderr}");
+    // Checking leaves the file as it is
+    assert_eq!(fs::read_to_string(path).unwrap(), "fun main(){\nval a=1+2;\n}\n");
+
+    let output = ash(&["fmt", path]);
+    assert!(output.status.success());
+    let output = ash(&["fmt", "--check", path]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(fs::read_to_string(path).unwrap(), "fun main() {\n    val a = 1 + 2;\n}\n");
+}
+
+#[test]
+fn json_format_reports_unlocated_errors() {
+    let path = source("json_unformatted", "fun main(){\n}\n");
+    let path = path.to_str().unwrap();
+    let output = ash(&["fmt", "--check", path, "--message-format", "json"]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(r#""code":"E0500""#), "{stdout}");
+    assert!(stdout.contains(&format!(r#""file":"{path}""#)), "{stdout}");
+    assert!(output.stderr.is_empty(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let bytecode = source("json_bytecode", "").with_extension("ashc");
+    fs::write(&bytecode, "not bytecode").unwrap();
+    let output = ash(&["run", "--path", bytecode.to_str().unwrap(), "--message-format", "json"]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(r#""code":"E0800""#), "{stdout}");
+    assert!(stdout.contains("Not an Ash bytecode file"), "{stdout}");
+    assert!(output.stderr.is_empty(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn project_modules_are_resolved() {
+    let output = ash(&["check", PROJECT]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Checked project v0.1.0\n");
+
+    let output = ash(&["run", "--path", PROJECT]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "49");
+}
+
+#[test]
+fn run_takes_project_or_its_file() {
+    let entry = format!("{PROJECT}/src/main.ash");
+    for args in [vec!["run", PROJECT], vec!["run", &entry], vec!["run", "--path", &entry]] {
+        let output = ash(&args);
+        assert!(output.status.success(), "{args:?}: {}", String::from_utf8_lossy(&output.stderr));
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "49", "{args:?}");
+    }
+
+    // Files outside of projects are run alone
+    let path = source(
+        "run_positional",
+        "@[builtin]\nfun print(s: str)\n\nfun main() {\n    print(\"alone\");\n}\n",
+    );
+    let output = ash(&["run", path.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "alone");
+
+    let output = ash(&["run", PROJECT, "--path", PROJECT]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn dump_prints_every_stage() {
+    let module = format!("{PROJECT}/src/util/math.ash");
+    let stages = [
+        ("tokens", "3:9     identifier square\n"),
+        ("ast", "pub fun square(x: i32) > i32 => x * x;\n"),
+        ("resolved", "pub fun square#3(x#2: i32) > i32 => x#2 * x#2;\n"),
+        ("hir", "fun __square3(__x2: i32) > i32 {\n    return __x2 * __x2;\n}\n"),
+        ("typed", "var __answer1: i32 = 7;\n"),
+        ("bytecode", "-= __square3 =-\n"),
+    ];
+    for (stage, expected) in stages {
+        let output = ash(&["dump", &module, "--stage", stage]);
+        assert!(output.status.success(), "{stage}: {}", String::from_utf8_lossy(&output.stderr));
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains(expected), "{stage}: {stdout}");
+    }
+
+    let output = ash(&["dump", &format!("{PROJECT}/src/main.ash"), "--stage", "ast"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("import util.math;\nimport util.math.{square};\n"), "{stdout}");
+
+    let output = ash(&["dump", &module, "--stage", "mir"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Unknown stage `mir`"), "{stderr}");
+}
+
+#[test]
+fn build_writes_runnable_bytecode() {
+    let out_dir = std::env::temp_dir().join(format!("ash-cli-{}-build_project", process::id()));
+    let output = ash(&["build", PROJECT, "--out-dir", out_dir.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let out = out_dir.join("project.ashc");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with(&format!("Built project v0.1.0 -> {}", out.display())), "{stdout}");
+    let output = ash(&["run", out.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "49");
+
+    // Files are built into `out` next to them
+    let path = source(
+        "build_file",
+        "@[builtin]\nfun print(s: str)\n\nfun main() {\n    print(\"built\");\n}\n",
+    );
+    let output = ash(&["build", path.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let out = path.with_file_name("out").join("main.ashc");
+    let output = ash(&["run", out.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "built");
+
+    // Nothing is written when the code has errors
+    let path = source("build_error", "fun main() {\n    val a: i32 = true;\n}\n");
+    let output = ash(&["build", path.to_str().unwrap()]);
+    assert!(!output.status.success());
+    assert!(!path.with_file_name("out").exists());
+}
+
+#[test]
+fn nested_functions_call_themselves() {
+    let path = source(
+        "nested_recursion",
+        "@[builtin]\nfun println(s: str)\n\nfun main() {\n    fun fact(n: i32) > i32 {\n        if n < 2 {\n            return 1;\n        }\n        return n * fact(n - 1);\n    }\n    println(\"${fact(5)}\");\n}\n",
+    );
+
+    let output = ash(&["run", "--path", path.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "120\n");
+}
+
+#[test]
+fn nested_functions_reject_outer_locals() {
+    let path = source(
+        "nested_capture",
+        "fun main() {\n    val a = 1;\n    fun get() > i32 => a;\n}\n",
+    );
+
+    let output = ash(&["check", path.to_str().unwrap(), "--message-format", "json"]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Function can not use `a`, it is a local variable of an enclosing scope"),
+        "{stdout}"
+    );
+}
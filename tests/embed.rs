@@ -0,0 +1,118 @@
+use ash::{Engine, Error, NativeTy, Source, VMError, Value};
+
+const PROGRAM: &str = r#"
+fun sum(a: i32, b: i32) > i32 => a + b;
+
+fun recurse(n: i32) > i32 => recurse(n + 1);
+"#;
+
+#[test]
+fn stack_overflow_names_function() {
+    let engine = Engine::new();
+    let program = engine.compile(&Source::from_string(PROGRAM)).unwrap();
+    let mut instance = engine.instantiate(&program).unwrap();
+
+    let fault = match instance.call("recurse", vec![0.into()]) {
+        Err(Error::Runtime(fault)) => fault,
+        result => panic!("Expected runtime error, got {result:?}"),
+    };
+    assert!(matches!(&fault.error, VMError::StackOverflow(name) if name == "recurse"));
+    assert_eq!(
+        fault.error.to_string(),
+        "Stack overflow while calling `recurse`"
+    );
+    assert!(fault.trace.iter().all(|frame| frame.function == "recurse"));
+}
+
+#[test]
+fn arity_mismatch_names_function() {
+    let engine = Engine::new();
+    let program = engine.compile(&Source::from_string(PROGRAM)).unwrap();
+    let mut instance = engine.instantiate(&program).unwrap();
+
+    let error = instance.call("sum", vec![1.into()]).unwrap_err();
+    assert_eq!(
+        error.to_string().lines().next(),
+        Some("Runtime error: Function `sum` expects 2 arguments, got 1")
+    );
+    // The instance can still be used after a failed call
+    let sum = instance.call("sum", vec![1.into(), 2.into()]).unwrap();
+    assert_eq!(i32::try_from(sum), Ok(3));
+}
+
+#[test]
+fn natives_take_sized_numbers() {
+    let mut engine = Engine::new();
+    engine.register_native("widen", &[NativeTy::U8, NativeTy::F32], NativeTy::I64, |_, args| {
+        match (&args[0], &args[1]) {
+            (Value::U8(int), Value::F32(float)) => Ok(Value::I64(*int as i64 * *float as i64)),
+            _ => unreachable!("Arguments are checked by the VM"),
+        }
+    });
+    let code = "@[builtin]\nfun widen(a: u8, b: f32) > i64\n\nfun run() > i64 => widen(255u8, 1000f32);";
+    let program = engine.compile(&Source::from_string(code)).unwrap();
+    let mut instance = engine.instantiate(&program).unwrap();
+
+    let result = instance.call("run", vec![]).unwrap();
+    assert_eq!(i64::try_from(result), Ok(255_000));
+
+    // Declared types have to match the native signature
+    let code = "@[builtin]\nfun widen(a: i32, b: f32) > i64\n\nfun run() > i64 => widen(1, 2f32);";
+    let Err(error) = engine.compile(&Source::from_string(code)) else {
+        panic!("Mismatched builtin compiled");
+    };
+    assert!(
+        format!("{error:?}")
+            .contains("does not match its native signature `fun widen(u8, f32) > i64`"),
+        "{error:?}"
+    );
+}
+
+#[test]
+fn interpolation_keeps_float_digits() {
+    let engine = Engine::new();
+    let code = r#"
+fun third() > str => "${1.0 / 3.0}";
+
+fun small() > str => "${0.001}";
+
+fun whole() > str => "${2.0}";
+
+fun single() > str => "${0.1f32} ${1e-7f32}";
+"#;
+    let program = engine.compile(&Source::from_string(code)).unwrap();
+    let mut instance = engine.instantiate(&program).unwrap();
+
+    let cases = [
+        ("third", "0.3333333333333333"),
+        ("small", "0.001"),
+        ("whole", "2.0"),
+        ("single", "0.1 1e-7"),
+    ];
+    for (function, expected) in cases {
+        let result = instance.call(function, vec![]).unwrap();
+        assert_eq!(String::try_from(result), Ok(expected.to_owned()), "{function}");
+    }
+}
+
+#[test]
+fn bitwise_operators() {
+    let engine = Engine::new();
+    let code = r#"
+fun mask(a: u8, b: u8) > u8 => (a & b) | (a ^ b) & ~b;
+
+fun shift(a: i32, by: u8) > i32 => a << by >> 1u8;
+"#;
+    let program = engine.compile(&Source::from_string(code)).unwrap();
+    let mut instance = engine.instantiate(&program).unwrap();
+
+    let result = instance.call("mask", vec![0b1100u8.into(), 0b1010u8.into()]).unwrap();
+    assert_eq!(u8::try_from(result), Ok(0b1100));
+    let result = instance.call("shift", vec![(-3).into(), 4u8.into()]).unwrap();
+    assert_eq!(i32::try_from(result), Ok(-24));
+
+    let Err(Error::Runtime(fault)) = instance.call("shift", vec![1.into(), 32u8.into()]) else {
+        panic!("Shifting by the bit width did not fail");
+    };
+    assert!(matches!(fault.error, VMError::IntegerOverflow), "{fault}");
+}
@@ -0,0 +1,73 @@
+use ash::{Engine, Error, GCConfig, Io, SharedBuffer, Source, VMError};
+
+const PROGRAM: &str = r#"
+var kept = "";
+
+fun garbage(n: i32) {
+    var i = 0;
+    while i < n {
+        val s = "garbage ${i}";
+        i = i + 1;
+    }
+}
+
+fun build() > str {
+    val local = "local ${1}";
+    garbage(10);
+    kept = "global ${2}";
+    garbage(10);
+    return local + " " + kept;
+}
+"#;
+
+#[test]
+fn collection_keeps_reachable_strings() {
+    let engine = Engine::new().with_gc_config(GCConfig {
+        threshold: 1,
+        grow_factor: 1,
+    });
+    let program = engine.compile(&Source::from_string(PROGRAM)).unwrap();
+    let mut instance = engine.instantiate(&program).unwrap();
+
+    // Local of `build` and the global survive collections run by `garbage`
+    let result = instance.call("build", vec![]).unwrap();
+    assert_eq!(String::try_from(result).as_deref(), Ok("local 1 global 2"));
+    assert_eq!(
+        String::try_from(instance.global("kept").unwrap()).as_deref(),
+        Ok("global 2")
+    );
+
+    let vm = instance.vm();
+    vm.collect_garbage();
+    let stats = vm.gc_stats();
+    assert!(stats.collections > 1);
+    assert!(stats.objects_freed > 0);
+    // Only the global is reachable once the call returned
+    assert_eq!(stats.objects, 1);
+    assert!(vm.heap().interned("global 2").is_some());
+    assert!(vm.heap().interned("garbage 9").is_none());
+    assert!(vm.heap().interned("local 1").is_none());
+}
+
+#[test]
+fn natives_are_not_handed_out_to_the_host() {
+    let engine = Engine::new().with_gc_config(GCConfig {
+        threshold: 1,
+        grow_factor: 1,
+    });
+    let source = "@[builtin]\nfun println(msg: str)\n\nfun greet() {\n    println(\"hi ${1}\");\n}";
+    let program = engine.compile(&Source::from_string(source)).unwrap();
+    let mut instance = engine.instantiate(&program).unwrap();
+    let stdout = SharedBuffer::new();
+    instance.set_io(Io::new(stdout.clone(), std::io::sink()));
+
+    // A handle to the native would dangle once the heap is collected
+    let global = instance.global("println");
+    instance.vm().collect_garbage();
+    instance.call("greet", vec![]).unwrap();
+    assert!(
+        matches!(global, Err(Error::VM(VMError::NativeToHost(ref name))) if name == "println"),
+        "{global:?}"
+    );
+    assert_eq!(stdout.contents(), "hi 1\n");
+}
@@ -0,0 +1,130 @@
+use std::{
+    fs,
+    io::Write,
+    process::{self, Command, Stdio},
+};
+
+use serde_json::{json, Value};
+
+/// Frames message as the language server protocol expects on stdio
+fn frame(message: Value) -> String {
+    let content = message.to_string();
+    format!("Content-Length: {}\r\n\r\n{content}", content.len())
+}
+
+/// Sends `messages` to a server and returns everything it sent back
+fn lsp(messages: Vec<Value>) -> Vec<Value> {
+    let mut server = Command::new(env!("CARGO_BIN_EXE_ash"))
+        .arg("lsp")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let input = messages.into_iter().map(frame).collect::<String>();
+    server.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    let output = server.wait_with_output().unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    stdout
+        .split("Content-Length: ")
+        .filter_map(|message| message.split_once("\r\n\r\n"))
+        .map(|(_, content)| serde_json::from_str(content).unwrap())
+        .collect()
+}
+
+/// Numbers of diagnostics in each list published for `uri`
+fn published(messages: &[Value], uri: &str) -> Vec<usize> {
+    messages
+        .iter()
+        .filter(|message| message["method"] == "textDocument/publishDiagnostics")
+        .filter(|message| message["params"]["uri"] == uri)
+        .map(|message| message["params"]["diagnostics"].as_array().unwrap().len())
+        .collect()
+}
+
+#[test]
+fn closing_document_clears_its_diagnostics() {
+    let dir = std::env::temp_dir().join(format!("ash-lsp-{}-close", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("main.ash");
+    // File on disk is broken as well, it must not be analyzed once closed
+    let code = "fun main() {\n    val a: i32 = true;\n}\n";
+    fs::write(&path, code).unwrap();
+    let uri = format!("file://{}", path.display());
+
+    let messages = lsp(vec![
+        json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"capabilities": {}}}),
+        json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}),
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {
+                "textDocument": {"uri": uri, "languageId": "ash", "version": 1, "text": code}
+            }
+        }),
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didClose",
+            "params": {"textDocument": {"uri": uri}}
+        }),
+        json!({"jsonrpc": "2.0", "id": 2, "method": "shutdown"}),
+        json!({"jsonrpc": "2.0", "method": "exit"}),
+    ]);
+
+    let published = published(&messages, &uri);
+    assert_eq!(published.len(), 2, "{messages:?}");
+    assert_ne!(published[0], 0, "{messages:?}");
+    assert_eq!(published[1], 0, "{messages:?}");
+}
+
+#[cfg(unix)]
+#[test]
+fn documents_opened_through_links() {
+    let dir = std::env::temp_dir().join(format!("ash-lsp-{}-links", process::id()));
+    let (real, link) = (dir.join("real"), dir.join("link"));
+    fs::create_dir_all(&real).unwrap();
+    if !link.exists() {
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+    }
+    // Only the text sent by the client is correct
+    fs::write(real.join("main.ash"), "fun main() {\n    val a: i32 = true;\n}\n").unwrap();
+    let uri = format!("file://{}", link.join("main.ash").display());
+    let real_uri = format!("file://{}", fs::canonicalize(&real).unwrap().join("main.ash").display());
+
+    let messages = lsp(vec![
+        json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"capabilities": {}}}),
+        json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}),
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": {
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": "ash",
+                    "version": 1,
+                    "text": "fun main() {\n    val a: i32 = 1;\n}\n"
+                }
+            }
+        }),
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didChange",
+            "params": {
+                "textDocument": {"uri": uri, "version": 2},
+                "contentChanges": [{"text": "fun main() {\n    val a: i32 = 1.5;\n}\n"}]
+            }
+        }),
+        json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didClose",
+            "params": {"textDocument": {"uri": uri}}
+        }),
+        json!({"jsonrpc": "2.0", "id": 2, "method": "shutdown"}),
+        json!({"jsonrpc": "2.0", "method": "exit"}),
+    ]);
+
+    // Correct text is not reported, the changed one is reported under the opened URI
+    assert_eq!(published(&messages, &uri), [1, 0], "{messages:?}");
+    assert_eq!(published(&messages, &real_uri), Vec::<usize>::new(), "{messages:?}");
+}